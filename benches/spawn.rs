@@ -0,0 +1,57 @@
+//! Spawn latency with and without the wake handshake, run as root with
+//! `cargo bench`.
+//!
+//! Without id maps, a v1 cgroup or anything else for the parent to do after
+//! clone, the child starts right away. The two benches only differ by an
+//! empty `after_clone` callback, which makes the child wait for the parent
+//! to wake it, so their difference is the cost of the round trip.
+
+#![feature(test)]
+
+extern crate test;
+
+// The rootfs of the crate's tests, its `crate::` paths resolve to these.
+#[allow(dead_code)]
+#[path = "../src/testing/rootfs.rs"]
+mod rootfs;
+
+use curium::{Container, MountSpec};
+use rootfs::Rootfs;
+use test::Bencher;
+
+fn is_root() -> bool {
+    let root = unsafe { libc::geteuid() } == 0;
+    if !root {
+        eprintln!("skipped, spawning without a user namespace needs root");
+    }
+    root
+}
+
+#[bench]
+fn spawn_without_handshake(b: &mut Bencher) {
+    if !is_root() {
+        return;
+    }
+    let rootfs = Rootfs::new();
+    b.iter(|| {
+        let status =
+            rootfs.container_without_ids(&["/bin/true"]).run().unwrap();
+        assert!(status.success());
+    });
+}
+
+#[bench]
+fn spawn_with_handshake(b: &mut Bencher) {
+    if !is_root() {
+        return;
+    }
+    let rootfs = Rootfs::new();
+    b.iter(|| {
+        let status = rootfs
+            .container_without_ids(&["/bin/true"])
+            .after_clone(|_| Ok(()))
+            .run()
+            .unwrap();
+        assert!(status.success());
+    });
+}
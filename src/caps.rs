@@ -0,0 +1,145 @@
+//! Linux capabilities, see capabilities(7).
+
+use std::io::Error;
+
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x20080522;
+
+/// A capability, numbered like `linux/capability.h`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    Chown = 0,
+    DacOverride = 1,
+    DacReadSearch = 2,
+    Fowner = 3,
+    Fsetid = 4,
+    Kill = 5,
+    Setgid = 6,
+    Setuid = 7,
+    Setpcap = 8,
+    LinuxImmutable = 9,
+    NetBindService = 10,
+    NetBroadcast = 11,
+    NetAdmin = 12,
+    NetRaw = 13,
+    IpcLock = 14,
+    IpcOwner = 15,
+    SysModule = 16,
+    SysRawio = 17,
+    SysChroot = 18,
+    SysPtrace = 19,
+    SysPacct = 20,
+    SysAdmin = 21,
+    SysBoot = 22,
+    SysNice = 23,
+    SysResource = 24,
+    SysTime = 25,
+    SysTtyConfig = 26,
+    Mknod = 27,
+    Lease = 28,
+    AuditWrite = 29,
+    AuditControl = 30,
+    Setfcap = 31,
+    MacOverride = 32,
+    MacAdmin = 33,
+    Syslog = 34,
+    WakeAlarm = 35,
+    BlockSuspend = 36,
+    AuditRead = 37,
+    Perfmon = 38,
+    Bpf = 39,
+    CheckpointRestore = 40,
+}
+
+#[repr(C)]
+struct CapHeader {
+    version: u32,
+    pid: i32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CapData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+/// Add `caps` to the inheritable and ambient sets of the calling thread, so
+/// they are kept in the permitted and effective sets across exec, even for a
+/// non-root user.
+///
+/// Every capability must be in the permitted set, and the ambient set is
+/// cleared again by a later uid change, so this should be called right
+/// before exec.
+///
+/// # Signal Safety
+/// This function is signal safe.
+pub fn raise_ambient(caps: &[Capability]) -> Result<(), Error> {
+    let mut header = CapHeader {
+        version: LINUX_CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    // Version 3 uses two u32 sets for the 64 capabilities.
+    let mut data = [CapData {
+        effective: 0,
+        permitted: 0,
+        inheritable: 0,
+    }; 2];
+
+    let ret = unsafe {
+        libc::syscall(libc::SYS_capget, &mut header, data.as_mut_ptr())
+    };
+    if ret != 0 {
+        return Err(Error::last_os_error());
+    }
+
+    for &cap in caps {
+        let cap = cap as usize;
+        data[cap / 32].inheritable |= 1 << (cap % 32);
+    }
+
+    let ret =
+        unsafe { libc::syscall(libc::SYS_capset, &mut header, data.as_ptr()) };
+    if ret != 0 {
+        return Err(Error::last_os_error());
+    }
+
+    for &cap in caps {
+        let ret = unsafe {
+            libc::prctl(
+                libc::PR_CAP_AMBIENT,
+                libc::PR_CAP_AMBIENT_RAISE,
+                cap as libc::c_ulong,
+                0,
+                0,
+            )
+        };
+        if ret != 0 {
+            return Err(Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+/// Drop `caps` from the bounding set of the calling thread, so neither this
+/// thread nor anything it executes (setuid binaries included) can gain them
+/// again.
+///
+/// This needs `CAP_SETPCAP`, and unlike the other sets the bounding set is
+/// only ever reduced.
+///
+/// # Signal Safety
+/// This function is signal safe.
+pub fn drop_bounding(caps: &[Capability]) -> Result<(), Error> {
+    for &cap in caps {
+        let ret = unsafe {
+            libc::prctl(libc::PR_CAPBSET_DROP, cap as libc::c_ulong, 0, 0, 0)
+        };
+        if ret != 0 {
+            return Err(Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,650 @@
+//! Control groups for containers.
+//!
+//! On a cgroup v2 host the container is cloned straight into its cgroup with
+//! `CLONE_INTO_CGROUP`. Hybrid and v1-only hosts have a hierarchy per
+//! controller under `/sys/fs/cgroup`, the container is added to the `tasks`
+//! file of each one after it's cloned (before it's woken), see cgroups(7).
+
+use std::{
+    ffi::CString,
+    fs::{self, File},
+    io::{Error, ErrorKind},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        unix::fs::FileExt,
+    },
+    path::{Path, PathBuf},
+};
+
+use crate::kernel::CGROUP2_SUPER_MAGIC;
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+const CGROUP_SUPER_MAGIC: i64 = 0x27e0eb;
+const TMPFS_MAGIC: i64 = 0x01021994;
+
+/// The cgroup layout of the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CgroupBackend {
+    /// A hierarchy per controller, mounted under a tmpfs at `/sys/fs/cgroup`.
+    /// Hybrid hosts (with a `unified` v2 hierarchy next to them) use this
+    /// backend since their controllers are bound to v1.
+    V1,
+    /// A single cgroup2 hierarchy mounted at `/sys/fs/cgroup`.
+    V2,
+}
+
+impl CgroupBackend {
+    /// Detect the backend from the filesystem mounted at `/sys/fs/cgroup`.
+    pub fn detect() -> Result<Self, Error> {
+        match fs_type(Path::new(CGROUP_ROOT))? {
+            CGROUP2_SUPER_MAGIC => Ok(Self::V2),
+            TMPFS_MAGIC => Ok(Self::V1),
+            _ => Err(Error::new(
+                ErrorKind::Unsupported,
+                "no cgroup hierarchy at /sys/fs/cgroup",
+            )),
+        }
+    }
+}
+
+/// Block I/O limits of a device, in bytes and I/O operations per second.
+/// `None` leaves the limit as it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct IoLimit {
+    pub rbps: Option<u64>,
+    pub wbps: Option<u64>,
+    pub riops: Option<u64>,
+    pub wiops: Option<u64>,
+}
+
+impl IoLimit {
+    /// The `io.max` line of the device `major:minor`, e.g. `8:0 rbps=1024`.
+    pub(crate) fn io_max_line(&self, (major, minor): (u32, u32)) -> String {
+        let mut line = format!("{major}:{minor}");
+        for (key, _, value) in self.limits() {
+            line.push_str(&format!(" {key}={value}"));
+        }
+        line
+    }
+
+    /// The set limits, with their `io.max` key and v1 file.
+    fn limits(
+        &self,
+    ) -> impl Iterator<Item = (&'static str, &'static str, u64)> {
+        [
+            ("rbps", "blkio.throttle.read_bps_device", self.rbps),
+            ("wbps", "blkio.throttle.write_bps_device", self.wbps),
+            ("riops", "blkio.throttle.read_iops_device", self.riops),
+            ("wiops", "blkio.throttle.write_iops_device", self.wiops),
+        ]
+        .into_iter()
+        .filter_map(|(key, file, value)| Some((key, file, value?)))
+    }
+}
+
+/// A cgroup created (or reused) for a container.
+pub(crate) struct Cgroup {
+    backend: CgroupBackend,
+    /// The cgroup directory in every hierarchy, with the hierarchy name
+    /// (empty for v2).
+    dirs: Vec<(String, PathBuf)>,
+}
+
+impl Cgroup {
+    /// Create the cgroup `name` (relative to the root of the hierarchies) if
+    /// it doesn't exist.
+    ///
+    /// With v1, the cgroup is created in every mounted hierarchy.
+    pub(crate) fn create(
+        backend: CgroupBackend,
+        name: &Path,
+    ) -> Result<Self, Error> {
+        let dirs = match backend {
+            CgroupBackend::V2 => vec![(String::new(), join(CGROUP_ROOT, name))],
+            CgroupBackend::V1 => v1_hierarchies()?
+                .into_iter()
+                .map(|(controller, root)| (controller, join(&root, name)))
+                .collect(),
+        };
+
+        for (controller, dir) in &dirs {
+            fs::create_dir_all(dir)?;
+            if controller == "cpuset" {
+                init_cpuset(dir)?;
+            }
+        }
+
+        Ok(Self { backend, dirs })
+    }
+
+    pub(crate) fn backend(&self) -> CgroupBackend {
+        self.backend
+    }
+
+    /// Limit the memory of the cgroup to `bytes`.
+    ///
+    /// This writes `memory.max` with v2 and `memory.limit_in_bytes` in the
+    /// memory hierarchy with v1.
+    pub(crate) fn set_memory_limit(&self, bytes: u64) -> Result<(), Error> {
+        let file = match self.backend {
+            CgroupBackend::V2 => "memory.max",
+            CgroupBackend::V1 => "memory.limit_in_bytes",
+        };
+        fs::write(self.controller_dir("memory")?.join(file), bytes.to_string())
+    }
+
+    /// Limit the number of processes (and threads) of the cgroup to `max` by
+    /// writing `pids.max`, zero removes the limit.
+    pub(crate) fn set_pids_limit(&self, max: u64) -> Result<(), Error> {
+        let value = match max {
+            0 => "max".to_owned(),
+            max => max.to_string(),
+        };
+        fs::write(self.controller_dir("pids")?.join("pids.max"), value).map_err(
+            |e| match e.kind() {
+                ErrorKind::NotFound => Error::new(
+                    ErrorKind::Unsupported,
+                    "the pids controller isn't enabled for the cgroup",
+                ),
+                _ => e,
+            },
+        )
+    }
+
+    /// Kill every process of the cgroup together when one of them is killed
+    /// by the OOM killer, by writing `memory.oom.group`.
+    ///
+    /// This needs v2 with the memory controller enabled for the cgroup, v1
+    /// has no equivalent.
+    pub(crate) fn set_oom_group(&self) -> Result<(), Error> {
+        self.write_v2_memory("memory.oom.group", "1")
+    }
+
+    /// Limit the swap used by the cgroup to `bytes`, by writing
+    /// `memory.swap.max`. Zero keeps its processes from swapping.
+    ///
+    /// This needs v2 with the memory controller enabled for the cgroup, v1
+    /// only limits the memory and swap together.
+    pub(crate) fn set_swap_limit(&self, bytes: u64) -> Result<(), Error> {
+        self.write_v2_memory("memory.swap.max", &bytes.to_string())
+    }
+
+    /// Set the swappiness (0 to 100) of the cgroup by writing
+    /// `memory.swappiness`, which only v1 has.
+    pub(crate) fn set_swappiness(&self, swappiness: u8) -> Result<(), Error> {
+        if self.backend != CgroupBackend::V1 {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "cgroup v2 has no per-cgroup swappiness",
+            ));
+        }
+        fs::write(
+            self.controller_dir("memory")?.join("memory.swappiness"),
+            swappiness.to_string(),
+        )
+    }
+
+    /// Throttle the block I/O of the cgroup on the device `major:minor`.
+    ///
+    /// This writes a line of `io.max` with v2, which needs the io controller
+    /// enabled for the cgroup, and the `blkio.throttle.*_device` files in the
+    /// blkio hierarchy with v1.
+    pub(crate) fn set_io_limit(
+        &self,
+        device: (u32, u32),
+        limit: &IoLimit,
+    ) -> Result<(), Error> {
+        if limit.limits().next().is_none() {
+            return Ok(());
+        }
+
+        match self.backend {
+            CgroupBackend::V2 => fs::write(
+                self.dirs[0].1.join("io.max"),
+                limit.io_max_line(device),
+            )
+            .map_err(|e| match e.kind() {
+                ErrorKind::NotFound => Error::new(
+                    ErrorKind::Unsupported,
+                    "the io controller isn't enabled for the cgroup",
+                ),
+                _ => e,
+            }),
+            CgroupBackend::V1 => {
+                let dir = self.controller_dir("blkio")?;
+                let (major, minor) = device;
+                for (_, file, value) in limit.limits() {
+                    fs::write(
+                        dir.join(file),
+                        format!("{major}:{minor} {value}"),
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Give the cgroup the lowest I/O weight, so its I/O waits for every
+    /// other cgroup's: `io.weight` with v2, BFQ's `blkio.bfq.weight` with v1.
+    ///
+    /// Fails with `Unsupported` when the cgroup has neither file: the io
+    /// controller isn't enabled for it, or the blkio one doesn't use BFQ.
+    pub(crate) fn set_lowest_io_weight(&self) -> Result<(), Error> {
+        let (dir, file, value) = match self.backend {
+            CgroupBackend::V2 => (self.controller_dir("io"), "io.weight", "1"),
+            CgroupBackend::V1 => {
+                (self.controller_dir("blkio"), "blkio.bfq.weight", "1")
+            }
+        };
+        let unsupported = || {
+            Error::new(
+                ErrorKind::Unsupported,
+                format!("the cgroup has no {file}"),
+            )
+        };
+        let dir = dir.map_err(|_| unsupported())?;
+        fs::write(dir.join(file), value).map_err(|e| match e.kind() {
+            ErrorKind::NotFound => unsupported(),
+            _ => e,
+        })
+    }
+
+    /// Write the memory controller `file` of a v2 cgroup.
+    fn write_v2_memory(&self, file: &str, value: &str) -> Result<(), Error> {
+        if self.backend != CgroupBackend::V2 {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                format!("{file} needs cgroup v2"),
+            ));
+        }
+        fs::write(self.dirs[0].1.join(file), value).map_err(|e| {
+            match e.kind() {
+                ErrorKind::NotFound => Error::new(
+                    ErrorKind::Unsupported,
+                    "the memory controller isn't enabled for the cgroup",
+                ),
+                _ => e,
+            }
+        })
+    }
+
+    /// Open the cgroup directory to clone a process into it with
+    /// `CLONE_INTO_CGROUP`, only possible with v2.
+    pub(crate) fn open(&self) -> Result<OwnedFd, Error> {
+        if self.backend != CgroupBackend::V2 {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "CLONE_INTO_CGROUP needs cgroup v2",
+            ));
+        }
+        let dir = CString::new(self.dirs[0].1.as_os_str().as_encoded_bytes())
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+
+        let fd = unsafe {
+            libc::open(
+                dir.as_ptr(),
+                libc::O_PATH | libc::O_DIRECTORY | libc::O_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(Error::last_os_error());
+        }
+
+        // SAFETY: open returned a new file descriptor.
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
+    /// Move the process `pid` into the cgroup of every v1 hierarchy.
+    pub(crate) fn add_task(&self, pid: i64) -> Result<(), Error> {
+        for (_, dir) in &self.dirs {
+            fs::write(dir.join("tasks"), pid.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Watch the OOM events of the cgroup, see [`OomSource`]. Fails if the
+    /// memory controller isn't enabled for it.
+    pub(crate) fn oom_source(&self) -> Result<OomSource, Error> {
+        let dir = self.controller_dir("memory")?;
+        match self.backend {
+            CgroupBackend::V2 => {
+                let events = File::open(dir.join("memory.events"))?;
+                let mut source = OomSource::V2 { events };
+                // The file is readable with POLLPRI once changed since the
+                // last read.
+                source.counts()?;
+                Ok(source)
+            }
+            CgroupBackend::V1 => {
+                let fd = unsafe {
+                    libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK)
+                };
+                if fd < 0 {
+                    return Err(Error::last_os_error());
+                }
+                // SAFETY: eventfd returned a new fd.
+                let eventfd = unsafe { OwnedFd::from_raw_fd(fd) };
+                let oom_control = File::open(dir.join("memory.oom_control"))?;
+                fs::write(
+                    dir.join("cgroup.event_control"),
+                    format!(
+                        "{} {}",
+                        eventfd.as_raw_fd(),
+                        oom_control.as_raw_fd()
+                    ),
+                )?;
+                Ok(OomSource::V1 {
+                    eventfd,
+                    oom_control,
+                    ooms: 0,
+                })
+            }
+        }
+    }
+
+    /// Return the cgroup directory of `controller`, the only directory with
+    /// v2.
+    fn controller_dir(&self, controller: &str) -> Result<&Path, Error> {
+        self.dirs
+            .iter()
+            .find(|(name, _)| {
+                self.backend == CgroupBackend::V2 || name == controller
+            })
+            .map(|(_, dir)| dir.as_path())
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::NotFound,
+                    format!("the {controller} controller is not mounted"),
+                )
+            })
+    }
+}
+
+/// How many times a cgroup ran out of memory, and how many of its processes
+/// the OOM killer killed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct OomCounts {
+    pub(crate) ooms: u64,
+    pub(crate) kills: u64,
+}
+
+/// The OOM events of a cgroup, polled by
+/// [`ContainerHandle::events`](crate::ContainerHandle::events).
+pub(crate) enum OomSource {
+    /// `memory.events`, which has both counts.
+    V2 { events: File },
+    /// An eventfd notified of every OOM of `memory.oom_control`, which has
+    /// the kill count (Linux 4.13), through `cgroup.event_control`.
+    V1 {
+        eventfd: OwnedFd,
+        oom_control: File,
+        ooms: u64,
+    },
+}
+
+impl OomSource {
+    /// The fd to poll, with its events, before reading [`OomSource::counts`].
+    pub(crate) fn pollfd(&self) -> (RawFd, i16) {
+        match self {
+            OomSource::V2 { events } => (events.as_raw_fd(), libc::POLLPRI),
+            OomSource::V1 { eventfd, .. } => {
+                (eventfd.as_raw_fd(), libc::POLLIN)
+            }
+        }
+    }
+
+    /// Read the counts since the cgroup was created, with v1 the OOMs since
+    /// the source was.
+    pub(crate) fn counts(&mut self) -> Result<OomCounts, Error> {
+        match self {
+            OomSource::V2 { events } => {
+                let contents = read_from_start(events)?;
+                Ok(OomCounts {
+                    ooms: flat_key(&contents, "oom").unwrap_or(0),
+                    kills: flat_key(&contents, "oom_kill").unwrap_or(0),
+                })
+            }
+            OomSource::V1 {
+                eventfd,
+                oom_control,
+                ooms,
+            } => {
+                let mut count = 0u64;
+                let ret = unsafe {
+                    libc::read(
+                        eventfd.as_raw_fd(),
+                        (&mut count as *mut u64).cast(),
+                        size_of::<u64>(),
+                    )
+                };
+                if ret == size_of::<u64>() as isize {
+                    *ooms += count;
+                } else if Error::last_os_error().kind() != ErrorKind::WouldBlock
+                {
+                    return Err(Error::last_os_error());
+                }
+                let contents = read_from_start(oom_control)?;
+                Ok(OomCounts {
+                    ooms: *ooms,
+                    kills: flat_key(&contents, "oom_kill").unwrap_or(0),
+                })
+            }
+        }
+    }
+}
+
+/// Read the whole of a cgroup file opened earlier.
+fn read_from_start(file: &File) -> Result<String, Error> {
+    let mut buf = vec![0u8; 4096];
+    let len = file.read_at(&mut buf, 0)?;
+    buf.truncate(len);
+    String::from_utf8(buf).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+/// The value of `key` in a flat keyed file, `KEY VALUE` lines like
+/// `memory.events`.
+fn flat_key(contents: &str, key: &str) -> Option<u64> {
+    contents.lines().find_map(|line| {
+        let (name, value) = line.split_once(' ')?;
+        (name == key).then(|| value.trim().parse().ok())?
+    })
+}
+
+/// Return the name and mount point of every cgroup v1 hierarchy.
+///
+/// Hierarchies mounted with comounted controllers (e.g. `cpu,cpuacct`) often
+/// have symlinks for each controller, those are skipped.
+fn v1_hierarchies() -> Result<Vec<(String, PathBuf)>, Error> {
+    let mut hierarchies = vec![];
+    for entry in fs::read_dir(CGROUP_ROOT)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir()
+            || fs_type(&entry.path())? != CGROUP_SUPER_MAGIC
+        {
+            continue;
+        }
+
+        hierarchies.push((
+            entry.file_name().to_string_lossy().into_owned(),
+            entry.path(),
+        ));
+    }
+    Ok(hierarchies)
+}
+
+/// Copy the cpus and memory nodes of the parent to `dir`, and to its
+/// ancestors that don't have them, or tasks can't be added to it (`ENOSPC`).
+fn init_cpuset(dir: &Path) -> Result<(), Error> {
+    let parent = dir.parent().expect("cpuset cgroups have a parent");
+    for file in ["cpuset.cpus", "cpuset.mems"] {
+        if !fs::read_to_string(dir.join(file))?.trim().is_empty() {
+            continue;
+        }
+        if fs::read_to_string(parent.join(file))?.trim().is_empty() {
+            init_cpuset(parent)?;
+        }
+        fs::write(dir.join(file), fs::read(parent.join(file))?)?;
+    }
+    Ok(())
+}
+
+/// Join `name` under `root`, even when `name` is absolute.
+fn join<P: AsRef<Path>>(root: P, name: &Path) -> PathBuf {
+    root.as_ref().join(name.strip_prefix("/").unwrap_or(name))
+}
+
+fn fs_type(path: &Path) -> Result<i64, Error> {
+    let path = CString::new(path.as_os_str().as_encoded_bytes())
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(stat.f_type)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A cgroup with a temporary directory standing in for its directory in
+    /// each hierarchy, named after the controllers (`""` for v2).
+    fn fake_cgroup(
+        name: &str,
+        backend: CgroupBackend,
+        controllers: &[&str],
+    ) -> (PathBuf, Cgroup) {
+        let root = std::env::temp_dir()
+            .join(format!("curium-cgroup-{name}-{}", std::process::id()));
+        let dirs = controllers
+            .iter()
+            .map(|controller| {
+                let dir = root.join(controller);
+                fs::create_dir_all(&dir).unwrap();
+                (controller.to_string(), dir)
+            })
+            .collect();
+        (root, Cgroup { backend, dirs })
+    }
+
+    fn read(dir: &Path, file: &str) -> String {
+        fs::read_to_string(dir.join(file)).unwrap()
+    }
+
+    #[test]
+    fn oom_group() {
+        let (root, cgroup) = fake_cgroup("oom", CgroupBackend::V2, &[""]);
+        cgroup.set_oom_group().unwrap();
+        assert_eq!(read(&root, "memory.oom.group"), "1");
+        // The cgroupfs has no memory files unless the controller is enabled,
+        // which the missing directory stands in for.
+        fs::remove_dir_all(&root).unwrap();
+        let err = cgroup.set_oom_group().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+
+        let (v1_root, v1) =
+            fake_cgroup("oom-v1", CgroupBackend::V1, &["memory"]);
+        let err = v1.set_oom_group().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+        assert!(!v1_root.join("memory/memory.oom.group").exists());
+        fs::remove_dir_all(v1_root).unwrap();
+    }
+
+    #[test]
+    fn swap_limit_and_swappiness() {
+        let (root, cgroup) = fake_cgroup("swap", CgroupBackend::V2, &[""]);
+        cgroup.set_swap_limit(0).unwrap();
+        assert_eq!(read(&root, "memory.swap.max"), "0");
+        cgroup.set_swap_limit(1 << 30).unwrap();
+        assert_eq!(read(&root, "memory.swap.max"), "1073741824");
+        let err = cgroup.set_swappiness(10).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+        assert!(!root.join("memory.swappiness").exists());
+
+        let (v1_root, v1) =
+            fake_cgroup("swap-v1", CgroupBackend::V1, &["memory"]);
+        let dir = v1_root.join("memory");
+        v1.set_swappiness(10).unwrap();
+        assert_eq!(read(&dir, "memory.swappiness"), "10");
+        let err = v1.set_swap_limit(0).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+        assert!(!dir.join("memory.swap.max").exists());
+
+        for root in [root, v1_root] {
+            fs::remove_dir_all(root).unwrap();
+        }
+    }
+
+    #[test]
+    fn io_limit() {
+        let limit = IoLimit {
+            rbps: Some(1 << 20),
+            wiops: Some(120),
+            ..Default::default()
+        };
+
+        let (root, cgroup) = fake_cgroup("io", CgroupBackend::V2, &[""]);
+        cgroup.set_io_limit((8, 0), &IoLimit::default()).unwrap();
+        assert!(!root.join("io.max").exists());
+        cgroup.set_io_limit((8, 0), &limit).unwrap();
+        assert_eq!(read(&root, "io.max"), "8:0 rbps=1048576 wiops=120");
+
+        let (v1_root, v1) = fake_cgroup("io-v1", CgroupBackend::V1, &["blkio"]);
+        let dir = v1_root.join("blkio");
+        v1.set_io_limit((8, 16), &limit).unwrap();
+        assert_eq!(
+            read(&dir, "blkio.throttle.read_bps_device"),
+            "8:16 1048576"
+        );
+        assert_eq!(read(&dir, "blkio.throttle.write_iops_device"), "8:16 120");
+        assert!(!dir.join("blkio.throttle.write_bps_device").exists());
+        assert!(!dir.join("blkio.throttle.read_iops_device").exists());
+
+        for root in [root, v1_root] {
+            fs::remove_dir_all(root).unwrap();
+        }
+    }
+
+    #[test]
+    fn pids_limit() {
+        let (root, cgroup) = fake_cgroup("pids", CgroupBackend::V2, &[""]);
+        cgroup.set_pids_limit(64).unwrap();
+        assert_eq!(read(&root, "pids.max"), "64");
+        cgroup.set_pids_limit(0).unwrap();
+        assert_eq!(read(&root, "pids.max"), "max");
+
+        let (v1_root, v1) =
+            fake_cgroup("pids-v1", CgroupBackend::V1, &["pids"]);
+        v1.set_pids_limit(8).unwrap();
+        assert_eq!(read(&v1_root.join("pids"), "pids.max"), "8");
+        let (no_pids_root, no_pids) =
+            fake_cgroup("no-pids-v1", CgroupBackend::V1, &["memory"]);
+        let err = no_pids.set_pids_limit(8).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+
+        for root in [root, v1_root, no_pids_root] {
+            fs::remove_dir_all(root).unwrap();
+        }
+    }
+
+    #[test]
+    fn io_max_line_skips_unset_limits() {
+        let limit = IoLimit {
+            rbps: Some(1 << 20),
+            wiops: Some(120),
+            ..Default::default()
+        };
+        assert_eq!(limit.io_max_line((8, 0)), "8:0 rbps=1048576 wiops=120");
+    }
+
+    #[test]
+    fn flat_keys() {
+        let events = "low 0\nhigh 0\nmax 3\noom 2\noom_kill 1\n";
+        assert_eq!(flat_key(events, "oom"), Some(2));
+        assert_eq!(flat_key(events, "oom_kill"), Some(1));
+        assert_eq!(flat_key(events, "oom_group_kill"), None);
+        let oom_control = "oom_kill_disable 0\nunder_oom 0\noom_kill 4\n";
+        assert_eq!(flat_key(oom_control, "oom_kill"), Some(4));
+    }
+}
@@ -1,11 +1,43 @@
-use std::{io::Error, mem};
+use std::{fmt, io::Error, mem, os::fd::RawFd};
 
+/// `CLONE_INTO_CGROUP` of clone(2), which libc declares as a `c_int`, where
+/// `0x200000000` is truncated to 0.
+pub const CLONE_INTO_CGROUP: u64 = 0x2_0000_0000;
+
+/// A process created by clone3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Child {
     pub tid: u64,
     pub pid: i64,
+    /// A pidfd referring to the child, only set with `CLONE_PIDFD`.
+    pub pidfd: Option<RawFd>,
+}
+
+impl Child {
+    /// The pid as the type libc takes, pids always fit in a `pid_t`.
+    pub fn as_pid(&self) -> libc::pid_t {
+        self.pid as libc::pid_t
+    }
+}
+
+impl fmt::Display for Child {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "pid={} tid={}", self.pid, self.tid)
+    }
+}
+
+/// The arguments of a clone3 call, for the cases [`clone3`] doesn't cover.
+///
+/// The child gets `SIGCHLD` as its exit signal and the thread id is always
+/// reported back to the parent.
+#[derive(Debug, Clone, Copy)]
+pub struct Clone3 {
+    flags: u64,
+    cgroup: u64,
+    stack: u64,
+    stack_size: u64,
 }
 
-pub struct Clone3 {}
 pub enum CloneResult {
     Child,
     Parent(Child),
@@ -20,8 +52,11 @@ pub enum CloneResult {
 /// # Example
 ///
 /// ```no_run
+/// use curium::clone3::{CloneResult, clone3};
+///
 /// unsafe {
-///     let result = clone3(libc::CLONE_VM | libc::SIGCHLD).unwrap();
+///     // A fork-like clone, the child runs on a copy of our stack.
+///     let result = clone3(0).unwrap();
 ///     match result {
 ///         CloneResult::Child => {
 ///             println!("In the child");
@@ -33,45 +68,267 @@ pub enum CloneResult {
 /// }
 /// ```
 pub unsafe fn clone3(flags: u64) -> Result<CloneResult, std::io::Error> {
-    let flags = flags | libc::CLONE_PARENT_SETTID as u64;
-    let mut child_tid: mem::MaybeUninit<u64> = std::mem::MaybeUninit::uninit();
-
-    let clone_args = libc::clone_args {
-        flags,
-        pidfd: 0,
-        child_tid: 0,
-        parent_tid: child_tid.as_mut_ptr() as u64,
-        exit_signal: libc::SIGCHLD as u64,
-        stack: 0,
-        stack_size: 0,
-        tls: 0,
-        set_tid: 0,
-        set_tid_size: 0,
-        cgroup: 0,
-    };
+    // SAFETY: is the caller’s responsibility.
+    unsafe { Clone3::new(flags).spawn() }
+}
 
+/// Like [`clone3`], but the child is created in the cgroup (v2) directory
+/// referred to by `cgroup_fd`, see `CLONE_INTO_CGROUP` in clone(2).
+///
+/// # Safety
+/// See [`clone3`].
+pub unsafe fn clone3_into_cgroup(
+    flags: u64,
+    cgroup_fd: RawFd,
+) -> Result<CloneResult, std::io::Error> {
     // SAFETY: is the caller’s responsibility.
-    let pid = unsafe {
-        libc::syscall(
-            libc::SYS_clone3,
-            &clone_args as *const libc::clone_args,
-            size_of::<libc::clone_args>(),
-        )
-    };
-
-    if pid < 0 {
-        return Err(Error::last_os_error());
-    };
-
-    // SAFETY: The clone syscall finished successfuly and it initilized the
-    // variable.
-    let child_tid = unsafe { child_tid.assume_init() };
-
-    Ok(match pid {
-        0 => CloneResult::Child,
-        _ => CloneResult::Parent(Child {
-            tid: child_tid,
+    unsafe { Clone3::new(flags).cgroup(cgroup_fd).spawn() }
+}
+
+impl Clone3 {
+    pub fn new(flags: u64) -> Self {
+        Self {
+            flags: flags | libc::CLONE_PARENT_SETTID as u64,
+            cgroup: 0,
+            stack: 0,
+            stack_size: 0,
+        }
+    }
+
+    /// Create the child in the cgroup (v2) directory referred to by
+    /// `cgroup_fd`, see `CLONE_INTO_CGROUP` in clone(2).
+    pub fn cgroup(mut self, cgroup_fd: RawFd) -> Self {
+        self.flags |= CLONE_INTO_CGROUP;
+        self.cgroup = cgroup_fd as u64;
+        self
+    }
+
+    /// Run the child on the stack at `stack`, `size` bytes long.
+    ///
+    /// Without a stack the child runs on a copy of the parent's stack, which
+    /// is what a fork-like clone wants. With `CLONE_VM` there is no copy: the
+    /// parent and the child would run on the same stack and corrupt each
+    /// other, so the child needs a stack of its own.
+    ///
+    /// The kernel points the stack pointer of the child at the end of the
+    /// region since the stack grows down on every architecture we support.
+    /// The child then can't return into the frames of the parent, which is
+    /// why a child with its own stack must be started with
+    /// [`Clone3::spawn_with_entry`], [`Clone3::spawn`] refuses it.
+    ///
+    /// # Safety
+    /// - `stack` must be valid for writes of `size` bytes and stay allocated
+    ///   until the child exits or execs. With `CLONE_VM` the memory is shared,
+    ///   so the parent must not touch it in the meantime either.
+    /// - The region has no guard page: a child that overflows it silently
+    ///   writes over whatever is mapped below. Map it with mmap(2) and a
+    ///   `PROT_NONE` page at the bottom when that matters.
+    /// - The end of the region should be 16 byte aligned. The child entry
+    ///   aligns the stack pointer down, which wastes up to 15 bytes.
+    /// - With `CLONE_VM` the child shares the heap, the TLS and every lock of
+    ///   the parent, so it must stick to async-signal safe functions, like any
+    ///   child of a multithreaded program.
+    pub unsafe fn stack(mut self, stack: *mut u8, size: usize) -> Self {
+        self.stack = stack as u64;
+        self.stack_size = size as u64;
+        self
+    }
+
+    /// Clone the calling process, both the parent and the child return from
+    /// this function.
+    ///
+    /// Fails with `EINVAL` if a stack was set with [`Clone3::stack`].
+    ///
+    /// # Safety
+    /// See [`clone3`].
+    pub unsafe fn spawn(self) -> Result<CloneResult, Error> {
+        if self.stack != 0 {
+            return Err(Error::from_raw_os_error(libc::EINVAL));
+        }
+
+        let mut child_tid = mem::MaybeUninit::uninit();
+        let mut pidfd: RawFd = -1;
+        let clone_args = self.args(&mut child_tid, &mut pidfd);
+
+        // SAFETY: is the caller’s responsibility.
+        let pid = unsafe {
+            libc::syscall(
+                libc::SYS_clone3,
+                &clone_args as *const libc::clone_args,
+                size_of::<libc::clone_args>(),
+            )
+        };
+
+        if pid < 0 {
+            return Err(Error::last_os_error());
+        };
+
+        Ok(match pid {
+            0 => CloneResult::Child,
+            // SAFETY: The clone syscall finished successfuly and it
+            // initilized the variable.
+            _ => CloneResult::Parent(unsafe {
+                self.child(pid, child_tid, pidfd)
+            }),
+        })
+    }
+
+    /// Clone the calling process, the child calls `entry(arg)` on the stack
+    /// set with [`Clone3::stack`] and exits with its return value as the
+    /// status, only the parent returns from this function.
+    ///
+    /// This is the clone3 counterpart of the glibc clone(2) wrapper, meant
+    /// for `CLONE_VM` children that share the memory of the parent.
+    ///
+    /// Fails with `EINVAL` if no stack was set.
+    ///
+    /// # Safety
+    /// See [`clone3`] and [`Clone3::stack`]. `entry` runs with no Rust frame
+    /// below it, it must not unwind.
+    #[cfg(target_arch = "x86_64")]
+    pub unsafe fn spawn_with_entry(
+        self,
+        entry: extern "C" fn(usize) -> i32,
+        arg: usize,
+    ) -> Result<Child, Error> {
+        if self.stack == 0 {
+            return Err(Error::from_raw_os_error(libc::EINVAL));
+        }
+
+        let mut child_tid = mem::MaybeUninit::uninit();
+        let mut pidfd: RawFd = -1;
+        let clone_args = self.args(&mut child_tid, &mut pidfd);
+
+        let ret: i64;
+        // SAFETY: is the caller’s responsibility. The child keeps every
+        // register but rax and the stack pointer, so it finds `entry` and `arg`
+        // in r12 and r13 (the syscall itself clobbers rcx and r11). It never
+        // leaves the asm block: it calls `entry` on the new stack and exits.
+        unsafe {
+            std::arch::asm!(
+                "syscall",
+                "test rax, rax",
+                "jnz 2f",
+                "xor ebp, ebp",
+                "and rsp, -16",
+                "mov rdi, r13",
+                "call r12",
+                "mov edi, eax",
+                "mov eax, {exit}",
+                "syscall",
+                "ud2",
+                "2:",
+                exit = const libc::SYS_exit,
+                inlateout("rax") libc::SYS_clone3 => ret,
+                in("rdi") &clone_args as *const libc::clone_args,
+                in("rsi") size_of::<libc::clone_args>(),
+                in("r12") entry,
+                in("r13") arg,
+                lateout("rcx") _,
+                lateout("r11") _,
+                options(nostack),
+            );
+        }
+
+        if ret < 0 {
+            return Err(Error::from_raw_os_error(-ret as i32));
+        }
+
+        // SAFETY: The clone syscall finished successfuly and it initilized the
+        // variable.
+        Ok(unsafe { self.child(ret, child_tid, pidfd) })
+    }
+
+    fn args(
+        &self,
+        child_tid: &mut mem::MaybeUninit<u64>,
+        pidfd: &mut RawFd,
+    ) -> libc::clone_args {
+        libc::clone_args {
+            flags: self.flags,
+            pidfd: pidfd as *mut RawFd as u64,
+            child_tid: 0,
+            parent_tid: child_tid.as_mut_ptr() as u64,
+            exit_signal: libc::SIGCHLD as u64,
+            stack: self.stack,
+            stack_size: self.stack_size,
+            tls: 0,
+            set_tid: 0,
+            set_tid_size: 0,
+            cgroup: self.cgroup,
+        }
+    }
+
+    /// # Safety
+    /// The clone syscall must have succeeded, so `child_tid` is initialized.
+    unsafe fn child(
+        &self,
+        pid: i64,
+        child_tid: mem::MaybeUninit<u64>,
+        pidfd: RawFd,
+    ) -> Child {
+        Child {
+            tid: unsafe { child_tid.assume_init() },
             pid,
-        }),
-    })
+            pidfd: (self.flags & libc::CLONE_PIDFD as u64 != 0)
+                .then_some(pidfd),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    extern "C" fn entry(arg: usize) -> i32 {
+        let counter = unsafe { &*(arg as *const AtomicU32) };
+        counter.fetch_add(1, Ordering::SeqCst);
+        7
+    }
+
+    #[test]
+    fn clone_vm_with_stack() {
+        let counter = AtomicU32::new(0);
+        let mut stack = vec![0u8; 64 * 1024];
+
+        let child = unsafe {
+            Clone3::new(libc::CLONE_VM as u64)
+                .stack(stack.as_mut_ptr(), stack.len())
+                .spawn_with_entry(entry, &counter as *const AtomicU32 as usize)
+        }
+        .unwrap();
+
+        let mut status = 0;
+        let pid = unsafe { libc::waitpid(child.pid as i32, &mut status, 0) };
+        assert_eq!(pid as i64, child.pid);
+        assert!(libc::WIFEXITED(status));
+        assert_eq!(libc::WEXITSTATUS(status), 7);
+        // The child wrote to our memory, not a copy.
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn display_child() {
+        let child = Child {
+            tid: 42,
+            pid: 42,
+            pidfd: None,
+        };
+        assert_eq!(child.to_string(), "pid=42 tid=42");
+        assert_eq!(child.as_pid(), 42);
+    }
+
+    #[test]
+    fn spawn_rejects_a_stack() {
+        let mut stack = [0u8; 64];
+        let err = unsafe {
+            Clone3::new(libc::CLONE_VM as u64)
+                .stack(stack.as_mut_ptr(), stack.len())
+                .spawn()
+        }
+        .err()
+        .unwrap();
+        assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+    }
 }
@@ -33,7 +33,10 @@ impl CloseRangeBuilder {
     }
 
     /// Close the file descriptors from `first` to `last`
-    pub fn close(&self) -> Result<(), ()> {
+    ///
+    /// close_range(2) was added in Linux 5.9, on older kernels this falls back
+    /// to [`CloseRangeBuilder::close_fallback`].
+    pub fn close(&self) -> Result<(), std::io::Error> {
         let ret = unsafe {
             libc::syscall(
                 libc::SYS_close_range,
@@ -45,7 +48,82 @@ impl CloseRangeBuilder {
 
         match ret {
             0 => Ok(()),
-            _ => Err(()),
+            _ => {
+                let err = std::io::Error::last_os_error();
+                match err.raw_os_error() {
+                    Some(libc::ENOSYS) => self.close_fallback(),
+                    _ => Err(err),
+                }
+            }
         }
     }
+
+    /// Close the file descriptors one by one.
+    ///
+    /// This is best-effort: the range is capped by the soft `RLIMIT_NOFILE`,
+    /// so a descriptor opened before the limit was lowered is missed. Reading
+    /// `/proc/self/fd` would be exact but it isn't async-signal-safe.
+    ///
+    /// # Signal Safety
+    /// This function is signal safe.
+    fn close_fallback(&self) -> Result<(), std::io::Error> {
+        if self.flags & libc::CLOSE_RANGE_UNSHARE != 0
+            && unsafe { libc::unshare(libc::CLONE_FILES) } != 0
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let mut limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let last = self.last.min(limit.rlim_cur.saturating_sub(1) as u32);
+        for fd in self.first..=last {
+            // EBADF only means the fd isn't open.
+            unsafe {
+                if self.flags & libc::CLOSE_RANGE_CLOEXEC != 0 {
+                    libc::fcntl(fd as i32, libc::F_SETFD, libc::FD_CLOEXEC);
+                } else {
+                    libc::close(fd as i32);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn is_cloexec(fd: i32) -> bool {
+        unsafe { libc::fcntl(fd, libc::F_GETFD) & libc::FD_CLOEXEC != 0 }
+    }
+
+    #[test]
+    fn unshare_and_cloexec() {
+        let fd = unsafe { libc::dup(0) };
+        assert!(fd >= 0 && !is_cloexec(fd));
+
+        // The thread gets its own copy of the fd table, so the flag is only
+        // set in that copy.
+        std::thread::spawn(move || {
+            CloseRangeBuilder::new(fd as u32, fd as u32)
+                .close_on_exec()
+                .unshare_before_closing()
+                .close()
+                .unwrap();
+            assert!(is_cloexec(fd));
+        })
+        .join()
+        .unwrap();
+
+        assert!(!is_cloexec(fd));
+        unsafe { libc::close(fd) };
+    }
 }
@@ -1,207 +1,4607 @@
 use std::{
-    ffi::{CString, OsStr},
-    io::{Read, Write},
-    os::unix::net::UnixStream,
+    collections::HashMap,
+    ffi::{CStr, CString, OsStr, OsString},
+    fs::File,
+    io::{ErrorKind, Read, Write},
+    net::Shutdown,
+    os::{
+        fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd},
+        unix::{
+            ffi::OsStrExt,
+            fs::{FileTypeExt, MetadataExt},
+            net::UnixStream,
+        },
+    },
+    path::{Path, PathBuf},
     time::Duration,
 };
 
 use crate::{
-    clone3::{CloneResult, clone3},
+    FixedBufferWriter,
+    caps::{self, Capability},
+    cgroup::{Cgroup, CgroupBackend, IoLimit},
+    clone3::{
+        CLONE_INTO_CGROUP, Child, CloneResult, clone3, clone3_into_cgroup,
+    },
     close_range::CloseRangeBuilder,
-    error::Result,
-    mount::{Mount, MountPropagation, umount2},
+    criu, elf,
+    error::{CuriumError, Result, SetupStep},
+    fd_channel,
+    handle::{ContainerHandle, ExitStatus, Outcome},
+    landlock::{self, LandlockRuleset},
+    lsm,
+    mount::{
+        AtimeMode, Mount, MountPropagation, UmountFlags, attach_tree,
+        clone_tree, pivot_root, umount2,
+    },
+    mount_spec::{MountSpec, PreparedMount},
+    namespace::Namespaces,
+    net, oci,
+    plan::Operation,
+    pty, seccomp,
+    trace::event,
+    uid_map::{IdMap, write_id_maps},
 };
 
+/// Sent by the child right before exec, so EOF tells an exec from a death
+/// during the setup. The step isn't a [`SetupStep`].
+const READY_TO_EXEC: [u8; 8] = [0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0];
+
+/// The environment of [`Container::shell_defaults`].
+const SHELL_DEFAULTS: [&CStr; 3] = [
+    c"PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin",
+    c"HOME=/root",
+    c"PS1=\\u@\\h:\\w\\$ ",
+];
+
+/// The environment of [`Container::hermetic`].
+const HERMETIC_ENV: [&CStr; 2] = [c"TZ=UTC", c"SOURCE_DATE_EPOCH=0"];
+
+/// Host files bound into the container by [`Container::etc_from_host`].
+const HOST_ETC_FILES: [&CStr; 2] = [c"/etc/hosts", c"/etc/hostname"];
+
+/// Clone flags [`Container::clone_flags`] rejects: the child would share the
+/// parent's stack, threads or signal handlers, or clone3(2) would write
+/// through pointers spawn doesn't set up.
+const UNSAFE_CLONE_FLAGS: [(u64, &str); 10] = [
+    (libc::CLONE_VM as u64, "CLONE_VM"),
+    (libc::CLONE_THREAD as u64, "CLONE_THREAD"),
+    (libc::CLONE_SIGHAND as u64, "CLONE_SIGHAND"),
+    (libc::CLONE_VFORK as u64, "CLONE_VFORK"),
+    (libc::CLONE_SETTLS as u64, "CLONE_SETTLS"),
+    (libc::CLONE_PARENT as u64, "CLONE_PARENT"),
+    (libc::CLONE_PARENT_SETTID as u64, "CLONE_PARENT_SETTID"),
+    (libc::CLONE_CHILD_SETTID as u64, "CLONE_CHILD_SETTID"),
+    (libc::CLONE_CHILD_CLEARTID as u64, "CLONE_CHILD_CLEARTID"),
+    (CLONE_INTO_CGROUP, "CLONE_INTO_CGROUP"),
+];
+
+/// Clone flags [`Container::remove_clone_flags`] can't clear: the handle
+/// needs the pidfd, and the mount setup a mount namespace of its own.
+const REQUIRED_CLONE_FLAGS: [(u64, &str); 2] = [
+    (libc::CLONE_PIDFD as u64, "CLONE_PIDFD"),
+    (libc::CLONE_NEWNS as u64, "CLONE_NEWNS"),
+];
+
+/// The longest hostname or domain name the kernel takes, see sethostname(2).
+const HOST_NAME_MAX: usize = 64;
+
+/// proc files made read-only by [`Container::harden_proc`], the same as the
+/// OCI runtime defaults.
+const PROC_READONLY_PATHS: [&str; 6] = [
+    "/proc/asound",
+    "/proc/bus",
+    "/proc/fs",
+    "/proc/irq",
+    "/proc/sys",
+    "/proc/sysrq-trigger",
+];
+
+/// proc files hidden by [`Container::harden_proc`].
+const PROC_MASKED_PATHS: [&str; 8] = [
+    "/proc/acpi",
+    "/proc/kcore",
+    "/proc/keys",
+    "/proc/latency_stats",
+    "/proc/timer_list",
+    "/proc/timer_stats",
+    "/proc/sched_debug",
+    "/proc/scsi",
+];
+
+/// sys directories hidden by [`Container::harden_sys`], the firmware and
+/// power ones are masked by runc too.
+const SYS_MASKED_PATHS: [&str; 3] = [
+    "/sys/firmware",
+    "/sys/devices/virtual/powercap",
+    "/sys/fs/cgroup",
+];
+
+/// How the container's root directory is changed, see
+/// [`Container::root_mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RootMode {
+    /// pivot_root(2) into the rootfs and detach the host's root.
+    #[default]
+    PivotRoot,
+    /// chroot(2) into the rootfs. The host's root stays mounted in the
+    /// container's mount namespace, so a process with `CAP_SYS_CHROOT` can
+    /// escape it.
+    Chroot,
+}
+
+/// What is mounted at the container's `/proc`, see [`Container::proc_mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProcMode {
+    /// A new proc filesystem for the container's pid namespace.
+    #[default]
+    New,
+    /// A recursive bind of the host's `/proc`, which shows the host's
+    /// processes.
+    HostBind,
+    /// Nothing, `/proc` is left as it is in the rootfs.
+    None,
+}
+
+/// The network of the container, see [`Container::net_mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum NetMode {
+    /// A new network namespace with only the loopback interface, up.
+    #[default]
+    None,
+    /// The host's network namespace. The container can use (and with
+    /// `CAP_NET_ADMIN` in the host's user namespace, change) every host
+    /// interface.
+    ///
+    /// The container's `/sys` is then a read-only recursive bind of the
+    /// host's, since a user namespace can't mount a sysfs for a network
+    /// namespace it doesn't own.
+    Host,
+}
+
+/// A sysctl of the container's network namespace, written in the child
+/// once the namespace is set up, see [`Container::net_sysctls`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum NetSysctl {
+    /// The first port an unprivileged process can bind,
+    /// `net.ipv4.ip_unprivileged_port_start`. 0 lets any process bind any
+    /// port.
+    UnprivilegedPortStart(u16),
+    /// The range of group ids allowed to create ICMP echo sockets (e.g.
+    /// `ping` without privileges), `net.ipv4.ping_group_range`. The gids are
+    /// the container's, they must be mapped.
+    PingGroupRange(u32, u32),
+    /// The ports used for outgoing connections, `net.ipv4.ip_local_port_range`.
+    LocalPortRange(u16, u16),
+    /// Disable IPv6 on every interface, `net.ipv6.conf.all.disable_ipv6`.
+    DisableIpv6(bool),
+    /// Forward IPv4 packets between interfaces, `net.ipv4.ip_forward`.
+    IpForward(bool),
+    /// The longest queue of pending connections of a listening socket,
+    /// `net.core.somaxconn`.
+    Somaxconn(u32),
+}
+
+impl NetSysctl {
+    /// The path of the sysctl under `/proc/sys`.
+    pub fn path(&self) -> &'static str {
+        match self {
+            NetSysctl::UnprivilegedPortStart(_) => {
+                "/proc/sys/net/ipv4/ip_unprivileged_port_start"
+            }
+            NetSysctl::PingGroupRange(..) => {
+                "/proc/sys/net/ipv4/ping_group_range"
+            }
+            NetSysctl::LocalPortRange(..) => {
+                "/proc/sys/net/ipv4/ip_local_port_range"
+            }
+            NetSysctl::DisableIpv6(_) => {
+                "/proc/sys/net/ipv6/conf/all/disable_ipv6"
+            }
+            NetSysctl::IpForward(_) => "/proc/sys/net/ipv4/ip_forward",
+            NetSysctl::Somaxconn(_) => "/proc/sys/net/core/somaxconn",
+        }
+    }
+
+    /// The value written to [`NetSysctl::path`], after checking it.
+    fn value(&self) -> Result<String> {
+        let invalid = |reason| {
+            Err(CuriumError::InvalidConfig(format!("{self:?}: {reason}")))
+        };
+        match *self {
+            NetSysctl::UnprivilegedPortStart(port) => Ok(port.to_string()),
+            NetSysctl::PingGroupRange(low, high) => match low <= high {
+                // The kernel takes gids up to INT_MAX.
+                true if high <= i32::MAX as u32 => Ok(format!("{low}\t{high}")),
+                true => invalid("the gids go up to 2147483647"),
+                false => invalid("the range is reversed"),
+            },
+            NetSysctl::LocalPortRange(low, high) => match low <= high {
+                true if low > 0 => Ok(format!("{low}\t{high}")),
+                true => invalid("port 0 can't be used"),
+                false => invalid("the range is reversed"),
+            },
+            NetSysctl::DisableIpv6(disable) | NetSysctl::IpForward(disable) => {
+                Ok((disable as u8).to_string())
+            }
+            NetSysctl::Somaxconn(max) => match max <= i32::MAX as u32 {
+                true => Ok(max.to_string()),
+                false => invalid("the queue length goes up to 2147483647"),
+            },
+        }
+    }
+}
+
+/// A step of the parent in the container's lifecycle, see
+/// [`Container::after_clone`] and [`Container::after_start`].
+type ParentCallback = Box<dyn FnMut(&Child) -> std::io::Result<()> + Send>;
+
+/// Where an environment entry comes from, in increasing precedence, see
+/// [`Container::resolve_env`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum EnvSource {
+    Default,
+    Inherited,
+    File,
+    Explicit,
+}
+
 pub struct Container {
-    cmd: CString,
     root: String,
-    args: Vec<CString>,
-    env: Vec<CString>,
+    /// The argv is the entrypoint followed by the command, like an OCI
+    /// image's `ENTRYPOINT` and `CMD`.
+    entrypoint: Vec<CString>,
+    command: Vec<CString>,
+    env: Vec<(EnvSource, CString)>,
+    /// Why an explicit environment entry was rejected, reported by spawn.
+    invalid_env: Option<String>,
+    /// Why [`Container::hostname_random`] couldn't pick a name.
+    invalid_hostname: Option<String>,
+    landlock: Option<LandlockRuleset>,
+    seccomp_notify: Vec<i64>,
+    seccomp_default: bool,
+    working_dir_fd: Option<RawFd>,
+    etc_from_host: bool,
+    startup_timeout: Option<Duration>,
+    extra_mounts: Vec<MountSpec>,
+    dev_shm: Option<u64>,
+    /// The owner of `/run/user/<uid>`, if any, see [`Container::run_tmpfs`].
+    run_tmpfs: Option<Option<u32>>,
+    search_path: bool,
+    bind_executable: Option<bool>,
+    bind_dependencies: bool,
+    rootfs_fd: Option<RawFd>,
+    mount_namespace_fd: Option<RawFd>,
+    joined_namespaces: Option<(RawFd, Namespaces)>,
+    uid_maps: Vec<IdMap>,
+    gid_maps: Vec<IdMap>,
+    cgroup: Option<PathBuf>,
+    memory_limit: Option<u64>,
+    swap_limit: Option<u64>,
+    swappiness: Option<u8>,
+    io_limits: Vec<((u32, u32), IoLimit)>,
+    batch_mode: bool,
+    umask: Option<u32>,
+    disable_aslr: bool,
+    time_namespace: bool,
+    pids_limit: Option<u64>,
+    oom_group_kill: bool,
+    ambient_capabilities: Vec<Capability>,
+    dropped_bounding_caps: Vec<Capability>,
+    root_mode: RootMode,
+    old_root_dir: String,
+    harden_proc: bool,
+    harden_sys: bool,
+    extra_clone_flags: u64,
+    removed_clone_flags: u64,
+    unshare_fds: bool,
+    rlimit_nofile: Option<(u64, u64)>,
+    capture_output: bool,
+    apparmor_profile: Option<String>,
+    selinux_label: Option<String>,
+    mount_label: Option<String>,
+    hostname: Option<String>,
+    domainname: Option<String>,
+    hostname_file: bool,
+    proc_mode: ProcMode,
+    proc_options: Option<(u8, bool)>,
+    proc_target: PathBuf,
+    sys_target: PathBuf,
+    ephemeral_root: bool,
+    root_atime: Option<AtimeMode>,
+    readonly_root: bool,
+    stop_before_exec: bool,
+    net_mode: NetMode,
+    net_sysctls: Vec<NetSysctl>,
+    cgroup_namespace_root: bool,
+    stdin_null: bool,
+    tty: bool,
+    stdout_file: Option<PathBuf>,
+    stderr_file: Option<PathBuf>,
+    pidfile: Option<PathBuf>,
+    after_clone: Vec<ParentCallback>,
+    after_start: Vec<ParentCallback>,
 }
 
 impl Container {
+    /// Create a container running `cmd` in `root`, `cmd` is the entrypoint.
     pub fn new<C: AsRef<OsStr>>(root: String, cmd: C) -> Self {
         let cmd = CString::new(cmd.as_ref().as_encoded_bytes())
             .expect("Null in the command");
-        let mut args = Vec::with_capacity(2);
-        args.push(cmd.clone());
 
         Self {
-            cmd,
-            args,
             root,
+            entrypoint: vec![cmd],
+            command: vec![],
             env: vec![],
+            invalid_env: None,
+            invalid_hostname: None,
+            landlock: None,
+            seccomp_notify: vec![],
+            seccomp_default: false,
+            working_dir_fd: None,
+            etc_from_host: false,
+            startup_timeout: None,
+            extra_mounts: vec![],
+            dev_shm: None,
+            run_tmpfs: None,
+            search_path: false,
+            bind_executable: None,
+            bind_dependencies: false,
+            rootfs_fd: None,
+            mount_namespace_fd: None,
+            joined_namespaces: None,
+            uid_maps: vec![],
+            gid_maps: vec![],
+            cgroup: None,
+            memory_limit: None,
+            swap_limit: None,
+            swappiness: None,
+            io_limits: vec![],
+            batch_mode: false,
+            umask: None,
+            disable_aslr: false,
+            time_namespace: false,
+            pids_limit: None,
+            oom_group_kill: false,
+            ambient_capabilities: vec![],
+            dropped_bounding_caps: vec![],
+            root_mode: RootMode::default(),
+            old_root_dir: "old_root".to_owned(),
+            harden_proc: false,
+            harden_sys: false,
+            extra_clone_flags: 0,
+            removed_clone_flags: 0,
+            unshare_fds: false,
+            rlimit_nofile: None,
+            capture_output: false,
+            apparmor_profile: None,
+            selinux_label: None,
+            mount_label: None,
+            hostname: None,
+            domainname: None,
+            hostname_file: false,
+            proc_mode: ProcMode::default(),
+            proc_options: None,
+            proc_target: "/proc".into(),
+            sys_target: "/sys".into(),
+            ephemeral_root: false,
+            root_atime: None,
+            readonly_root: false,
+            stop_before_exec: false,
+            net_mode: NetMode::default(),
+            net_sysctls: vec![],
+            cgroup_namespace_root: false,
+            stdin_null: false,
+            tty: false,
+            stdout_file: None,
+            stderr_file: None,
+            pidfile: None,
+            after_clone: vec![],
+            after_start: vec![],
+        }
+    }
+
+    /// Create a container from the OCI bundle at `bundle`: the root is the
+    /// `root.path` of its `config.json` (`<bundle>/rootfs` without one), and
+    /// `process.args`, `process.env`, `hostname` and `root.readonly` are
+    /// applied. The other fields are ignored.
+    ///
+    /// Fails with [`CuriumError::InvalidConfig`] if `config.json` can't be
+    /// read or parsed, if the rootfs directory is missing or if
+    /// `process.args` is empty.
+    pub fn from_oci_bundle<P: AsRef<Path>>(bundle: P) -> Result<Self> {
+        let bundle = bundle.as_ref();
+        let path = bundle.join("config.json");
+        let invalid = |e: &dyn std::fmt::Display| -> CuriumError {
+            CuriumError::InvalidConfig(format!("{}: {e}", path.display()))
+        };
+        let contents = std::fs::read(&path).map_err(|e| invalid(&e))?;
+        let config: oci::Config =
+            serde_json::from_slice(&contents).map_err(|e| invalid(&e))?;
+
+        let (root, readonly) = match &config.root {
+            Some(root) => (bundle.join(&root.path), root.readonly),
+            None => (bundle.join("rootfs"), false),
+        };
+        if !root.is_dir() {
+            return Err(CuriumError::InvalidConfig(format!(
+                "{}: the rootfs directory is missing",
+                root.display()
+            )));
         }
+        let root = root.into_os_string().into_string().map_err(|_| {
+            CuriumError::InvalidConfig("the rootfs path isn't utf-8".into())
+        })?;
+
+        let args = &config.process.args;
+        let Some(cmd) = args.first() else {
+            return Err(invalid(&"process.args is empty"));
+        };
+        if args
+            .iter()
+            .chain(&config.process.env)
+            .any(|s| s.contains('\0'))
+        {
+            return Err(invalid(&"null in process.args or process.env"));
+        }
+
+        let mut container = Self::new(root, cmd)
+            .entrypoint(args)
+            .readonly_root(readonly);
+        for env in &config.process.env {
+            container = container.env(env);
+        }
+        if let Some(hostname) = &config.hostname {
+            container = container.hostname(hostname);
+        }
+        Ok(container)
     }
 
+    /// Append `arg` to the command.
     pub fn arg<C: AsRef<OsStr>>(mut self, arg: C) -> Self {
         let arg = CString::new(arg.as_ref().as_encoded_bytes())
             .expect("Null in the arg");
 
-        self.args.push(arg);
+        self.command.push(arg);
+        self
+    }
+
+    /// Replace the entrypoint, the start of the argv. The executable is the
+    /// first element of the entrypoint, or of the command if it's empty.
+    pub fn entrypoint<I, C>(mut self, entrypoint: I) -> Self
+    where
+        I: IntoIterator<Item = C>,
+        C: AsRef<OsStr>,
+    {
+        self.entrypoint = cstrings(entrypoint);
+        self
+    }
+
+    /// Replace the command, the arguments passed after the entrypoint. Like
+    /// `docker run IMAGE COMMAND`, this keeps the entrypoint.
+    pub fn command<I, C>(mut self, command: I) -> Self
+    where
+        I: IntoIterator<Item = C>,
+        C: AsRef<OsStr>,
+    {
+        self.command = cstrings(command);
         self
     }
 
+    /// Set an environment variable from a `KEY=VALUE` entry, which overrides
+    /// the inherited and file entries with the same key, see
+    /// [`Container::resolve_env`].
+    ///
+    /// An entry without a `=`, with an empty key or with a null byte would
+    /// reach the command cut or as is, spawning fails with
+    /// [`CuriumError::InvalidConfig`] instead.
     pub fn env<C: AsRef<OsStr>>(mut self, env: C) -> Self {
-        let arg = CString::new(env.as_ref().as_encoded_bytes())
-            .expect("Null in the env");
+        let Ok(arg) = CString::new(env.as_ref().as_encoded_bytes()) else {
+            self.invalid_env.get_or_insert_with(|| {
+                format!("the env entry {:?} has a null byte", env.as_ref())
+            });
+            return self;
+        };
 
-        self.env.push(arg);
+        let entry = arg.as_bytes();
+        if env_key(entry).is_empty() || !entry.contains(&b'=') {
+            self.invalid_env.get_or_insert_with(|| {
+                format!("the env entry {arg:?} isn't KEY=VALUE")
+            });
+            return self;
+        }
+        self.env.push((EnvSource::Explicit, arg));
         self
     }
 
-    /// Return a c-style null-terminated array for self.args
-    fn get_argv(&self) -> Vec<*const i8> {
-        let mut argv: Vec<*const i8> =
-            self.args.iter().map(|arg| arg.as_ptr()).collect();
+    /// Set the environment variable `key` to `value`, like
+    /// [`Container::env`] with `KEY=VALUE`.
+    ///
+    /// Spawning fails with [`CuriumError::InvalidConfig`] if `key` is empty
+    /// or contains a `=`, or if either has a null byte.
+    pub fn set_var<K: AsRef<OsStr>, V: AsRef<OsStr>>(
+        mut self,
+        key: K,
+        value: V,
+    ) -> Self {
+        let key = key.as_ref();
+        if key.is_empty() || key.as_encoded_bytes().contains(&b'=') {
+            self.invalid_env.get_or_insert_with(|| {
+                format!("the env key {key:?} is empty or contains a '='")
+            });
+            return self;
+        }
+        match env_entry(key.to_owned(), value.as_ref().to_owned()) {
+            Some(entry) => self.env.push((EnvSource::Explicit, entry)),
+            None => self.null_in_env(key),
+        }
+        self
+    }
 
-        argv.push(std::ptr::null());
-        argv
+    /// Record that the entry of `key` has a null byte, for spawn to fail.
+    fn null_in_env(&mut self, key: &OsStr) {
+        self.invalid_env.get_or_insert_with(|| {
+            format!("the env entry of {key:?} has a null byte")
+        });
     }
 
-    /// Return a c-style null-terminated array for self.env
-    fn get_envp(&self) -> Vec<*const i8> {
-        let mut envp: Vec<*const i8> =
-            self.env.iter().map(|env| env.as_ptr()).collect();
+    /// Append the environment of the current process.
+    pub fn inherit_env(mut self) -> Self {
+        for (key, value) in std::env::vars_os() {
+            // The environment of a process can't have null bytes.
+            if let Some(entry) = env_entry(key, value) {
+                self.env.push((EnvSource::Inherited, entry));
+            }
+        }
+        self
+    }
 
-        envp.push(std::ptr::null());
-        envp
+    /// Append the variables named in `keys` from the environment of the
+    /// current process, in the order of `keys`. The ones that aren't set are
+    /// skipped.
+    ///
+    /// Meant to pass `TERM` or `LANG` through without the rest of the
+    /// environment, and its secrets, like [`Container::inherit_env`] does.
+    pub fn inherit_env_only(mut self, keys: &[&str]) -> Self {
+        for key in keys {
+            if let Some(value) = std::env::var_os(key)
+                && let Some(entry) = env_entry(key.into(), value)
+            {
+                self.env.push((EnvSource::Inherited, entry));
+            }
+        }
+        self
     }
 
-    pub fn spawn(&mut self) -> Result<()> {
-        let argv = self.get_argv();
-        let envp = self.get_envp();
+    /// Give a shell the environment it expects: a `PATH` of the usual
+    /// directories, `HOME=/root`, a `PS1` prompt and the host's `TERM` (if
+    /// set).
+    ///
+    /// These are defaults, any other entry with the same key wins over them
+    /// whatever the order of the calls, see [`Container::resolve_env`].
+    pub fn shell_defaults(mut self) -> Self {
+        for entry in SHELL_DEFAULTS {
+            self.env.push((EnvSource::Default, entry.to_owned()));
+        }
+        if let Some(term) = std::env::var_os("TERM")
+            && let Some(entry) = env_entry("TERM".into(), term)
+        {
+            self.env.push((EnvSource::Default, entry));
+        }
+        self
+    }
 
-        let rootfs = CString::new(self.root.clone()).unwrap();
-        let procfs = CString::new(format!("{}/proc", self.root))
-            .expect("procfs will not include null bytes");
-        let sysfs = CString::new(format!("{}/sys", self.root))
-            .expect("sysfs will not include null bytes");
-        let old_root = CString::new(format!("{}/old_root", self.root))
-            .expect("old_root will not include null bytes");
+    /// Append the `KEY=VALUE` entries of the file at `path`, like a `.env`
+    /// file. Blank lines and lines starting with `#` are skipped, values are
+    /// taken as is (quotes aren't removed).
+    pub fn env_file<P: AsRef<Path>>(mut self, path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read(path).map_err(|e| {
+            CuriumError::InvalidConfig(format!("{}: {e}", path.display()))
+        })?;
 
-        let (mut parent_sock, mut child_sock) = match UnixStream::pair() {
-            Ok((sock1, sock2)) => (sock1, sock2),
-            Err(e) => {
-                panic!("Couldn't create a pair of sockets: {e:?}");
+        for (i, line) in contents.split(|&b| b == b'\n').enumerate() {
+            let line = line.trim_ascii();
+            if line.is_empty() || line.starts_with(b"#") {
+                continue;
+            }
+            if env_key(line).is_empty() || !line.contains(&b'=') {
+                return Err(CuriumError::InvalidConfig(format!(
+                    "{}:{}: expected KEY=VALUE",
+                    path.display(),
+                    i + 1
+                )));
             }
-        };
 
-        child_sock
-            .set_read_timeout(Some(Duration::from_secs(5)))
-            .unwrap();
+            let entry = CString::new(line).map_err(|_| {
+                CuriumError::InvalidConfig(format!(
+                    "{}:{}: null in the env",
+                    path.display(),
+                    i + 1
+                ))
+            })?;
+            self.env.push((EnvSource::File, entry));
+        }
+        Ok(self)
+    }
 
-        let mut read_buf = [0];
+    /// Remove every environment entry with the key `key`.
+    pub fn env_remove<K: AsRef<OsStr>>(mut self, key: K) -> Self {
+        let key = key.as_ref().as_encoded_bytes();
 
-        // SAFETY: The child will only run async-signal-safe functions
-        // See: signal-safety(7)
-        let clone = unsafe {
-            let flags = libc::CLONE_CLEAR_SIGHAND
-                | libc::CLONE_INTO_CGROUP
-                | libc::CLONE_NEWIPC
-                | libc::CLONE_NEWNET
-                | libc::CLONE_NEWUTS
-                | libc::CLONE_NEWNS
-                //| libc::CLONE_NEWUSER
-                | libc::CLONE_NEWPID;
+        self.env
+            .retain(|(_, entry)| env_key(entry.as_bytes()) != key);
+        self
+    }
 
-            clone3(flags as u64).expect("clone failed")
-        };
+    /// Remove all the environment entries added so far.
+    pub fn env_clear(mut self) -> Self {
+        self.env.clear();
+        self
+    }
 
-        match clone {
-            CloneResult::Parent(child) => {
-                drop(child_sock);
-                //map_uid(format!("/proc/{}/uid_map", child.pid), 0, 0)
-                //    .unwrap();
+    /// Set the environment variables in `vars`, replacing any existing entry
+    /// with the same key. They are explicit entries, like the ones of
+    /// [`Container::env`].
+    pub fn env_map(mut self, vars: HashMap<OsString, OsString>) -> Self {
+        let mut vars: Vec<_> = vars.into_iter().collect();
+        // HashMap iteration order is random, sort to keep envp deterministic.
+        vars.sort();
 
-                parent_sock.write_all(&[1]).unwrap(); // wake child
-                drop(parent_sock);
-                unsafe {
-                    libc::waitpid(child.pid as i32, std::ptr::null_mut(), 0)
-                };
+        self.env.retain(|(_, entry)| {
+            !vars.iter().any(|(key, _)| {
+                env_key(entry.as_bytes()) == key.as_encoded_bytes()
+            })
+        });
 
-                Ok(())
+        for (key, value) in vars {
+            match env_entry(key.clone(), value) {
+                Some(entry) => self.env.push((EnvSource::Explicit, entry)),
+                None => self.null_in_env(&key),
             }
-            CloneResult::Child => {
-                std::panic::always_abort();
+        }
+        self
+    }
 
-                drop(parent_sock);
-                // Ensure all file descriptors are closed when executing the
-                // child process so they are not inherited by
-                // the container.
-                //
-                // Note: From what I see, Rust opens all files with the
-                // close-on-exec flag on linux, but doing this
-                // here is just in case a file was opened outside of the std
-                // lib.
-                CloseRangeBuilder::new(3, u32::MAX)
-                    .close_on_exec()
-                    .close()
-                    .expect("should close all file descriptors");
-
-                match child_sock.read(&mut read_buf) {
-                    Ok(0) => panic!("Parent failed to initialize container"),
-                    Ok(_) => (),
-                    Err(_) => panic!("Error reading pipe"),
-                }
-
-                // Make sure the new root mount in the namespace is not shared
-                // with the host.
-                // See: https://lwn.net/Articles/689856/
-                Mount::new(c"/")
-                    .set_propagation(MountPropagation::Private)
-                    .recursive()
-                    .mount()
-                    .unwrap();
+    /// The environment of the command, with a single entry per key.
+    ///
+    /// An explicit entry ([`Container::env`], [`Container::env_map`]) wins
+    /// over one from an [`Container::env_file`], which wins over an inherited
+    /// one ([`Container::inherit_env`], [`Container::inherit_env_only`]),
+    /// which wins over a [`Container::shell_defaults`] one, whatever the
+    /// order of the calls. Among entries of the same kind the
+    /// last one wins. The entries keep the position of the first one with
+    /// their key.
+    pub fn resolve_env(&self) -> Vec<CString> {
+        let mut resolved: Vec<(EnvSource, &CString)> = vec![];
+        for (source, entry) in &self.env {
+            let key = env_key(entry.as_bytes());
+            match resolved
+                .iter_mut()
+                .find(|(_, other)| env_key(other.as_bytes()) == key)
+            {
+                Some(slot) if *source >= slot.0 => *slot = (*source, entry),
+                Some(_) => (),
+                None => resolved.push((*source, entry)),
+            }
+        }
+        resolved
+            .into_iter()
+            .map(|(_, entry)| entry.clone())
+            .collect()
+    }
 
-                // Make the container root a mount.
-                Mount::new(rootfs.as_c_str())
-                    .bind(rootfs.as_c_str())
-                    .mount()
-                    .unwrap();
-                Mount::new(procfs.as_c_str())
-                    .no_dev()
-                    .no_suid()
-                    .no_exec()
-                    .create(c"proc", c"proc")
-                    .mount()
-                    .unwrap();
+    /// Restrict the filesystem access of the container with Landlock.
+    ///
+    /// The ruleset is created in the parent and enforced in the child right
+    /// before exec, after the root has been pivoted.
+    pub fn landlock(mut self, rules: LandlockRuleset) -> Self {
+        self.landlock = Some(rules);
+        self
+    }
 
-                Mount::new(sysfs.as_c_str())
-                    .readonly()
-                    .no_dev()
-                    .no_suid()
-                    .no_exec()
-                    .create(c"sysfs", c"sys")
-                    .mount()
-                    .unwrap();
+    /// Handle the `syscalls` (like `libc::SYS_mount`) of the container in the
+    /// parent with seccomp user notifications, see seccomp_unotify(2). The
+    /// listener is taken with [`ContainerHandle::take_seccomp_listener`].
+    ///
+    /// The filter is loaded right before exec, with `no_new_privs` set like
+    /// for [`Container::landlock`], and the child sends the listener to the
+    /// parent before executing the command. The syscalls of the command (and
+    /// of its dynamic loader) wait for the parent from then on. Nothing
+    /// answers the child until spawn returns, so the syscalls it makes until
+    /// the exec succeeds (sending and exec) can't be listed. Syscalls of
+    /// another arch, and the x32 syscalls on x86_64, kill the container.
+    pub fn seccomp_notify<I: IntoIterator<Item = i64>>(
+        mut self,
+        syscalls: I,
+    ) -> Self {
+        self.seccomp_notify.extend(syscalls);
+        self
+    }
 
-                unsafe {
-                    libc::mkdir(old_root.as_ptr(), 0);
-                    libc::syscall(
-                        libc::SYS_pivot_root,
-                        rootfs.as_ptr(),
-                        old_root.as_ptr(),
-                    );
-                    umount2(c"/old_root", libc::MNT_DETACH).unwrap();
-                    libc::rmdir(c"/old_root".as_ptr());
-                    libc::chdir(c"/".as_ptr());
-                };
+    /// Confine the container with the default seccomp profile: an allowlist
+    /// of syscalls close to the default profile of Docker and Podman (see
+    /// [`crate::seccomp`]), the other syscalls fail with `EPERM`. Creating a
+    /// namespace, mounting, tracing the kernel or changing the system clock
+    /// are among the denied ones.
+    ///
+    /// The syscall numbers are the ones of the arch the crate is built for,
+    /// the syscalls of another arch kill the container. The profile is
+    /// loaded right before exec, with `no_new_privs` set, before the filter
+    /// of [`Container::seccomp_notify`]: a syscall it denies isn't notified.
+    pub fn seccomp_default(mut self) -> Self {
+        self.seccomp_default = true;
+        self
+    }
+
+    /// Change into the directory referred to by `fd` before exec.
+    ///
+    /// `fd` should be opened with `O_PATH | O_DIRECTORY` and must stay open
+    /// until `spawn` returns. Unlike a path, it's not re-resolved after the
+    /// root is pivoted, so it can't be swapped with a symlink in between.
+    pub fn working_dir_fd(mut self, fd: RawFd) -> Self {
+        self.working_dir_fd = Some(fd);
+        self
+    }
 
-                let Err(err) = self.do_exec(argv.as_ptr(), envp.as_ptr());
+    /// Bind the host's `/etc/hosts` and `/etc/hostname` read-only into the
+    /// container.
+    ///
+    /// The files are created in the rootfs if they don't exist.
+    pub fn etc_from_host(mut self) -> Self {
+        self.etc_from_host = true;
+        self
+    }
 
-                println!("exec failed: {}", err);
-                unsafe { libc::_exit(1) };
-            }
+    /// How long the child waits for the parent to finish setting it up before
+    /// aborting.
+    ///
+    /// There is no timeout by default, the child still exits if the parent
+    /// exits before waking it.
+    ///
+    /// The child only waits when the parent has to set it up first: write its
+    /// id maps or move it into a v1 cgroup. Otherwise it starts right after
+    /// clone, which saves a round trip on every spawn.
+    pub fn startup_timeout(mut self, timeout: Duration) -> Self {
+        self.startup_timeout = Some(timeout);
+        self
+    }
+
+    /// Set up `mounts` in order, after the default ones.
+    ///
+    /// They are mounted under the rootfs before the root is pivoted, so bind
+    /// sources are host paths.
+    pub fn extra_mounts(mut self, mounts: Vec<MountSpec>) -> Self {
+        self.extra_mounts.extend(mounts);
+        self
+    }
+
+    /// Bind the host directory `src` at `dst` with its submounts, see
+    /// [`MountSpec::RecursiveBind`], after the default mounts like
+    /// [`Container::extra_mounts`].
+    ///
+    /// Every mount of the tree is made private, so the host's later mounts
+    /// under `src` don't show up in the container. With `ro` they are all
+    /// read-only, which needs mount_setattr(2) (Linux 5.12).
+    pub fn bind_mount_recursive<S: Into<PathBuf>, D: Into<PathBuf>>(
+        mut self,
+        src: S,
+        dst: D,
+        ro: bool,
+    ) -> Self {
+        self.extra_mounts.push(MountSpec::RecursiveBind {
+            src: src.into(),
+            dst: dst.into(),
+            ro,
+        });
+        self
+    }
+
+    /// Mount the POSIX message queues of the container's IPC namespace at
+    /// `dst` (usually `/dev/mqueue`).
+    pub fn mount_mqueue<P: Into<PathBuf>>(mut self, dst: P) -> Self {
+        self.extra_mounts
+            .push(MountSpec::Mqueue { dst: dst.into() });
+        self
+    }
+
+    /// Mount a devpts filesystem at `dst` (usually `/dev/pts`) with the mount
+    /// options `opts`, e.g. `newinstance,ptmxmode=0666`.
+    pub fn mount_devpts<P: Into<PathBuf>>(
+        mut self,
+        dst: P,
+        opts: &str,
+    ) -> Self {
+        self.extra_mounts.push(MountSpec::Devpts {
+            dst: dst.into(),
+            options: opts.to_owned(),
+        });
+        self
+    }
+
+    /// Mount a tmpfs of `size` bytes with the mode 1777 at `/dev/shm`, for
+    /// POSIX shared memory (shm_open(3)).
+    ///
+    /// `/dev` and `/dev/shm` are created in the rootfs if they don't exist.
+    /// It's mounted before [`Container::extra_mounts`].
+    pub fn dev_shm(mut self, size: u64) -> Self {
+        self.dev_shm = Some(size);
+        self
+    }
+
+    /// Mount a tmpfs with the mode 0755 at `/run`, which service managers and
+    /// daemons expect to be writable.
+    ///
+    /// With `user`, the runtime directory `/run/user/<uid>` is created too,
+    /// with the mode 0700 and owned by the uid, and `XDG_RUNTIME_DIR` points
+    /// to it unless the environment sets it. In a user namespace the uid
+    /// must be mapped.
+    ///
+    /// `/run` is created in the rootfs if it doesn't exist. It's mounted
+    /// after [`Container::dev_shm`], before [`Container::extra_mounts`].
+    pub fn run_tmpfs(mut self, user: Option<u32>) -> Self {
+        self.run_tmpfs = Some(user);
+        if let Some(uid) = user {
+            let entry = format!("XDG_RUNTIME_DIR=/run/user/{uid}");
+            self.env
+                .push((EnvSource::Default, CString::new(entry).unwrap()));
         }
+        self
     }
 
-    fn do_exec(
-        &self,
-        argv: *const *const i8,
-        envp: *const *const i8,
-    ) -> std::result::Result<!, std::io::Error> {
-        unsafe { libc::execve(self.cmd.as_ptr(), argv, envp) };
-        Err(std::io::Error::last_os_error())
+    /// Look up a command without a `/` in the container's `PATH`, like
+    /// execvpe(3). Without a `PATH` in the environment, `/bin:/usr/bin` is
+    /// searched.
+    ///
+    /// The lookup doesn't allocate but it tries an execve for every directory
+    /// in `PATH`, which is slower and the reason it isn't the default.
+    pub fn search_path(mut self, search_path: bool) -> Self {
+        self.search_path = search_path;
+        self
+    }
+
+    /// Bind the executable read-only into the rootfs, at its host path, so a
+    /// single binary runs in an otherwise empty rootfs.
+    ///
+    /// A command without a `/` is looked up in the host's `PATH` and executed
+    /// by the path found. With `interpreter`, the ELF interpreter (the
+    /// dynamic loader) of the executable is bound too. The shared libraries
+    /// aren't unless [`Container::auto_bind_dependencies`] is used, a
+    /// dynamically linked executable still needs them in the rootfs.
+    ///
+    /// The missing mount points are created in the rootfs (and left there)
+    /// before the other mounts, use
+    /// [`Container::ephemeral_writable_root`] to keep the rootfs untouched.
+    pub fn bind_executable(mut self, interpreter: bool) -> Self {
+        self.bind_executable = Some(interpreter);
+        self
+    }
+
+    /// Bind the executable like [`Container::bind_executable`] with its
+    /// interpreter, along with every shared library it needs (its
+    /// `DT_NEEDED`, and theirs), so a dynamically linked program runs in an
+    /// empty rootfs.
+    ///
+    /// The libraries are resolved on the host when spawning, with the
+    /// `RPATH`, `RUNPATH` (`$ORIGIN` expanded) and the default library
+    /// directories, and bound read-only at the paths they were found at,
+    /// where the loader looks for them too. Spawning fails if one isn't
+    /// found. Libraries opened with dlopen(3) aren't known, bind them with
+    /// [`Container::extra_mounts`].
+    pub fn auto_bind_dependencies(mut self) -> Self {
+        self.bind_executable = Some(true);
+        self.bind_dependencies = true;
+        self
+    }
+
+    /// Use the directory referred to by `fd` as the container root instead of
+    /// the `root` path.
+    ///
+    /// The rootfs is then never resolved by path: the fd is cloned into a
+    /// bind mount, which is only attached over `root` in the container's
+    /// mount namespace, and every mount is done relative to it. So the rootfs
+    /// can't swap directories while the container is set up. `fd` should be
+    /// opened with `O_PATH | O_DIRECTORY` and must stay open until `spawn`
+    /// returns.
+    pub fn rootfs_fd(mut self, fd: RawFd) -> Self {
+        self.rootfs_fd = Some(fd);
+        self
+    }
+
+    /// Enter the mount namespace referred to by `fd` instead of setting up the
+    /// mounts, to spawn many identical containers faster. `fd` is opened
+    /// from `/proc/<pid>/ns/mnt` of a container which is already set up, e.g.
+    /// one stopped with [`Container::stop_before_exec`], and must stay open
+    /// until `spawn` returns.
+    ///
+    /// The root of the namespace becomes the container's root and every mount
+    /// option is ignored. The mounts are shared with the other containers in
+    /// the namespace, `/proc` included, which shows the pid namespace it was
+    /// mounted in.
+    ///
+    /// Entering a mount namespace needs `CAP_SYS_ADMIN` in the user namespace
+    /// owning it, which a new user namespace never has, so this can't be
+    /// combined with [`Container::uid_map`] or [`Container::rootfs_fd`].
+    pub fn mount_namespace_fd(mut self, fd: RawFd) -> Self {
+        self.mount_namespace_fd = Some(fd);
+        self
+    }
+
+    /// Join the namespaces `which` of the running process referred to by
+    /// `pidfd`, e.g. another container's [`ContainerHandle`], instead of
+    /// creating them. Unlike a pid, the pidfd can't refer to a new process
+    /// once the other one exits. `pidfd` must stay open until `spawn`
+    /// returns.
+    ///
+    /// The child enters the namespaces with setns(2) before setting anything
+    /// up, a joined mount namespace is used as is like with
+    /// [`Container::mount_namespace_fd`]. A pid namespace only applies to
+    /// the children of the caller, so the parent enters it for the clone
+    /// and goes back to its own: this needs `CAP_SYS_ADMIN` in our user
+    /// namespace.
+    ///
+    /// The container's own namespaces are created in our user namespace, so
+    /// spawning fails with [`CuriumError::InvalidConfig`] when they are
+    /// combined with a new [`Container::uid_map`] (join the user namespace
+    /// instead). So it does for a hostname with the UTS namespace or network
+    /// sysctls with the network namespace, which would change the other
+    /// container's.
+    pub fn join_namespaces_of(
+        mut self,
+        pidfd: BorrowedFd<'_>,
+        which: Namespaces,
+    ) -> Self {
+        self.joined_namespaces = Some((pidfd.as_raw_fd(), which));
+        self
+    }
+
+    /// Map `count` uids starting at `outside` to `inside` in a new user
+    /// namespace.
+    ///
+    /// The container gets its own user namespace once any uid or gid is
+    /// mapped. The maps are written by the parent before the child is woken,
+    /// so an unprivileged parent can map its own uid (e.g. to 0) and spawn a
+    /// rootless container.
+    pub fn uid_map(mut self, inside: u32, outside: u32, count: u32) -> Self {
+        self.uid_maps.push(IdMap {
+            inside,
+            outside,
+            count,
+        });
+        self
+    }
+
+    /// Map `count` gids starting at `outside` to `inside`, see
+    /// [`Container::uid_map`].
+    ///
+    /// `setgroups(2)` is denied in the container, which an unprivileged
+    /// parent needs to write the gid map.
+    pub fn gid_map(mut self, inside: u32, outside: u32, count: u32) -> Self {
+        self.gid_maps.push(IdMap {
+            inside,
+            outside,
+            count,
+        });
+        self
+    }
+
+    /// Run the container in the cgroup `name`, relative to the root of the
+    /// host's hierarchies. The cgroup is created if it doesn't exist, and it
+    /// isn't removed when the container exits.
+    ///
+    /// See [`CgroupBackend`] for how v1 and v2 hosts are handled.
+    pub fn cgroup<P: Into<PathBuf>>(mut self, name: P) -> Self {
+        self.cgroup = Some(name.into());
+        self
+    }
+
+    /// Limit the memory of the container's cgroup to `bytes`, a cgroup must
+    /// be set with [`Container::cgroup`].
+    ///
+    /// The container can still swap, use
+    /// [`Container::memory_limit_with_swap`] to limit it too.
+    pub fn memory_limit(mut self, bytes: u64) -> Self {
+        self.memory_limit = Some(bytes);
+        self
+    }
+
+    /// Limit the memory of the container's cgroup to `bytes` and its swap to
+    /// `swap`, like [`Container::memory_limit`] with a
+    /// [`Container::swap_limit`]. A `swap` of zero keeps the container from
+    /// swapping the host to death.
+    pub fn memory_limit_with_swap(self, bytes: u64, swap: u64) -> Self {
+        self.memory_limit(bytes).swap_limit(swap)
+    }
+
+    /// Limit the swap of the container's cgroup to `bytes` (`memory.swap.max`),
+    /// zero disables swap for the container.
+    ///
+    /// This needs a [`Container::cgroup`] on a v2 host with the memory
+    /// controller enabled, spawning fails otherwise.
+    pub fn swap_limit(mut self, bytes: u64) -> Self {
+        self.swap_limit = Some(bytes);
+        self
+    }
+
+    /// Set how eagerly the kernel swaps the container's memory out, from 0
+    /// to 100 like `vm.swappiness` (`memory.swappiness`).
+    ///
+    /// This needs a [`Container::cgroup`] on a v1 host, v2 dropped the
+    /// per-cgroup swappiness. Spawning fails otherwise.
+    pub fn swappiness(mut self, swappiness: u8) -> Self {
+        self.swappiness = Some(swappiness);
+        self
+    }
+
+    /// When the OOM killer kills a process of the container, kill all of
+    /// them with it instead of leaving the others running, see
+    /// `memory.oom.group` in the cgroup v2 documentation.
+    ///
+    /// This needs a [`Container::cgroup`] on a v2 host with the memory
+    /// controller enabled, spawning fails otherwise.
+    pub fn oom_group_kill(mut self, kill: bool) -> Self {
+        self.oom_group_kill = kill;
+        self
+    }
+
+    /// Limit the number of processes and threads of the container's cgroup
+    /// to `max` (`pids.max`), which stops a fork bomb. Zero means unlimited.
+    ///
+    /// This needs a [`Container::cgroup`] with the pids controller, enabled in
+    /// the parent's `cgroup.subtree_control` with v2. Spawning fails
+    /// otherwise.
+    pub fn pids_limit(mut self, max: u64) -> Self {
+        self.pids_limit = Some(max);
+        self
+    }
+
+    /// Throttle the block I/O of the container's cgroup on the device
+    /// `(major, minor)`, see `io.max` in the cgroup v2 documentation.
+    ///
+    /// This needs a [`Container::cgroup`], and with v2 the io controller
+    /// enabled for it. Spawning fails otherwise.
+    pub fn io_limit(mut self, device: (u32, u32), limit: IoLimit) -> Self {
+        self.io_limits.push((device, limit));
+        self
+    }
+
+    /// Like [`Container::io_limit`], for the device of `path`: the device
+    /// itself for a block device node, or the one holding the filesystem of
+    /// any other file.
+    ///
+    /// The kernel only throttles whole disks, the device holding a filesystem
+    /// is often a partition which it rejects.
+    pub fn io_limit_for_path<P: AsRef<Path>>(
+        self,
+        path: P,
+        limit: IoLimit,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let meta = std::fs::metadata(path).map_err(|e| {
+            CuriumError::InvalidConfig(format!("{}: {e}", path.display()))
+        })?;
+        let dev = match meta.file_type().is_block_device() {
+            true => meta.rdev(),
+            false => meta.dev(),
+        };
+        Ok(self.io_limit((libc::major(dev), libc::minor(dev)), limit))
+    }
+
+    /// Run the container only when the host is otherwise idle: the init is
+    /// scheduled with `SCHED_IDLE` (see sched(7)), inherited by every process
+    /// of the container, and a [`Container::cgroup`] gets the lowest I/O
+    /// weight.
+    ///
+    /// The weight is `io.weight` with v2 and BFQ's `blkio.bfq.weight` with
+    /// v1. It's skipped when the cgroup has neither, the CPU is still
+    /// yielded.
+    pub fn batch_mode(mut self) -> Self {
+        self.batch_mode = true;
+        self
+    }
+
+    /// Set the file mode creation mask of the command, see umask(2). The
+    /// child sets it right before exec, its own setup isn't affected.
+    pub fn umask(mut self, mask: u32) -> Self {
+        self.umask = Some(mask & 0o777);
+        self
+    }
+
+    /// Load the command's stack, heap and libraries at the same addresses on
+    /// every run, by setting `ADDR_NO_RANDOMIZE` in its personality(2).
+    pub fn disable_aslr(mut self) -> Self {
+        self.disable_aslr = true;
+        self
+    }
+
+    /// Set the container up for reproducible runs, giving the same command
+    /// the same inputs whatever the host and the time:
+    ///
+    /// - the environment entries added so far are cleared, and `TZ=UTC` and
+    ///   `SOURCE_DATE_EPOCH=0` are added (as defaults, any other entry with the
+    ///   same key wins),
+    /// - the hostname is `localhost`,
+    /// - ASLR is disabled, see [`Container::disable_aslr`],
+    /// - the umask is `022`,
+    /// - the monotonic and boot clocks start from zero, see
+    ///   [`Container::time_namespace`].
+    ///
+    /// The wall clock can't be namespaced, tools reading it instead of
+    /// `SOURCE_DATE_EPOCH` still see the host's time.
+    pub fn hermetic(self) -> Self {
+        let mut container = self
+            .env_clear()
+            .hostname("localhost")
+            .disable_aslr()
+            .umask(0o022)
+            .time_namespace();
+        for entry in HERMETIC_ENV {
+            container.env.push((EnvSource::Default, entry.to_owned()));
+        }
+        container
+    }
+
+    /// Keep `caps` across exec by raising them in the ambient set, which is
+    /// the only way for a non-root command to have them.
+    ///
+    /// The capabilities are raised right before exec, after the Landlock
+    /// ruleset (and its `no_new_privs`) is enforced.
+    pub fn ambient_capabilities(mut self, caps: &[Capability]) -> Self {
+        self.ambient_capabilities.extend_from_slice(caps);
+        self
+    }
+
+    /// Drop `caps` from the bounding set, so the command can't regain them,
+    /// not even by executing a setuid binary. Useful to limit a container
+    /// running as the real root, without a user namespace.
+    ///
+    /// The caps are dropped before the ambient capabilities are raised.
+    pub fn drop_bounding_caps(mut self, caps: &[Capability]) -> Self {
+        self.dropped_bounding_caps.extend_from_slice(caps);
+        self
+    }
+
+    /// Change how the container's root is set, [`RootMode::PivotRoot`] by
+    /// default.
+    ///
+    /// [`RootMode::Chroot`] is a fallback for environments where pivot_root
+    /// fails with `EINVAL` (e.g. a rootfs in an initramfs or some nested
+    /// containers). chroot is escapable and should only be used when
+    /// pivot_root isn't possible.
+    pub fn root_mode(mut self, mode: RootMode) -> Self {
+        self.root_mode = mode;
+        self
+    }
+
+    /// Set the directory of the rootfs the host's root is moved to by
+    /// pivot_root, `old_root` by default. It must be a single path component.
+    ///
+    /// The directory is created (0700) before the pivot and removed once the
+    /// host's root is detached. A rootfs which already has it fails the spawn
+    /// with `EEXIST` rather than reusing it, pick another name in that case.
+    pub fn old_root_dir(mut self, name: &str) -> Self {
+        self.old_root_dir = name.to_owned();
+        self
+    }
+
+    /// Make the sensitive parts of `/proc` read-only (`/proc/sys`,
+    /// `/proc/sysrq-trigger`, `/proc/irq`, ...) and mask the ones leaking
+    /// host information (`/proc/kcore`, `/proc/keys`, ...), like runc does by
+    /// default.
+    ///
+    /// Masked files are covered with `/dev/null` and masked directories with
+    /// an empty read-only tmpfs. Paths the kernel doesn't have are skipped.
+    pub fn harden_proc(mut self) -> Self {
+        self.harden_proc = true;
+        self
+    }
+
+    /// Mask the parts of `/sys` which are dangerous even read-only, like
+    /// `/sys/firmware` (EFI variables, ACPI tables) and the powercap
+    /// interface, as runc does by default.
+    ///
+    /// `/sys/fs/cgroup` is masked as well: without a cgroup namespace, a
+    /// recursive bind of the host's `/sys` ([`NetMode::Host`]) would show the
+    /// host's cgroup hierarchies, and a new sysfs mount only has an empty
+    /// directory there. With [`Container::cgroup_namespace_root`] it has the
+    /// container's cgroup2 instead.
+    pub fn harden_sys(mut self) -> Self {
+        self.harden_sys = true;
+        self
+    }
+
+    /// Give the container a cgroup namespace rooted at its cgroup, with a
+    /// new read-only cgroup2 mounted at `/sys/fs/cgroup`, so it sees its own
+    /// cgroup as `/` and none of its siblings.
+    ///
+    /// The child unshares the namespace once it's woken, after the parent
+    /// moved it into its cgroup (see [`Container::cgroup`] and
+    /// [`Container::after_clone`]). A namespace from
+    /// [`Container::clone_flags`] is created before that, so the cgroup2 is
+    /// mounted for it too, and the spawn fails if `/proc/self/cgroup` doesn't
+    /// show the container at the root of each hierarchy.
+    pub fn cgroup_namespace_root(mut self) -> Self {
+        self.cgroup_namespace_root = true;
+        self
+    }
+
+    /// Give the container a time namespace whose `CLOCK_MONOTONIC` and
+    /// `CLOCK_BOOTTIME` start from zero, so its uptime doesn't tell how long
+    /// the host has been running. See time_namespaces(7), Linux 5.6.
+    ///
+    /// Like the cgroup namespace, it's unshared once the child is woken, and
+    /// the command enters it on exec. The clocks are reset through
+    /// `/proc/self/timens_offsets`, which needs `CAP_SYS_TIME` in the
+    /// container's user namespace.
+    pub fn time_namespace(mut self) -> Self {
+        self.time_namespace = true;
+        self
+    }
+
+    /// Unshare the fd table before the child marks its file descriptors
+    /// close-on-exec, see `CLOSE_RANGE_UNSHARE` in close_range(2).
+    ///
+    /// The child is single-threaded so the table isn't shared after clone,
+    /// this only matters if that changes.
+    pub fn unshare_fds_before_close(mut self) -> Self {
+        self.unshare_fds = true;
+        self
+    }
+
+    /// Set the `soft` and `hard` `RLIMIT_NOFILE` of the container, which
+    /// bounds the fds the child marks close-on-exec instead of `u32::MAX`.
+    ///
+    /// The limit is set by the parent with prlimit(2) before waking the
+    /// child, since a child in a new user namespace can't raise its hard
+    /// limit. The bound is the larger of the new soft limit and the parent's
+    /// own, so an fd above the parent's soft limit (opened before it was
+    /// lowered) is inherited.
+    pub fn with_rlimit_nofile(mut self, soft: u64, hard: u64) -> Self {
+        self.rlimit_nofile = Some((soft, hard));
+        self
+    }
+
+    /// Capture the stdout and stderr of the container in pipes, read them
+    /// with [`ContainerHandle::wait_with_output`], stream them with
+    /// [`ContainerHandle::on_stdout`] and [`ContainerHandle::on_stderr`] or
+    /// take the read ends with [`ContainerHandle::take_stdout`] and
+    /// [`ContainerHandle::take_stderr`].
+    pub fn capture_output(mut self) -> Self {
+        self.capture_output = true;
+        self
+    }
+
+    /// Confine the container's command with the AppArmor profile `name`, like
+    /// the `apparmorProfile` of an OCI config.
+    ///
+    /// The profile is set for the next exec right before the command is
+    /// executed, and must be loaded on the host. Spawning fails if AppArmor
+    /// isn't enabled.
+    pub fn apparmor_profile(mut self, name: &str) -> Self {
+        self.apparmor_profile = Some(name.to_owned());
+        self
+    }
+
+    /// Run the container's command with the SELinux context `label` (e.g.
+    /// `system_u:system_r:container_t:s0`), set for the next exec right
+    /// before the command is executed. Spawning fails if SELinux isn't
+    /// enabled.
+    pub fn selinux_label(mut self, label: &str) -> Self {
+        self.selinux_label = Some(label.to_owned());
+        self
+    }
+
+    /// Label the tmpfs, mqueue and devpts mounts of [`MountSpec`] with the
+    /// SELinux context `label`, passed as the `context=` mount option.
+    pub fn mount_label(mut self, label: &str) -> Self {
+        self.mount_label = Some(label.to_owned());
+        self
+    }
+
+    /// Set the hostname of the container's UTS namespace.
+    ///
+    /// uname(2) and `/proc/sys/kernel/hostname` agree on it: the sysctl shows
+    /// the UTS namespace of the reading process, even through a
+    /// [`ProcMode::HostBind`] of the host's `/proc`.
+    ///
+    /// Spawning fails with [`CuriumError::InvalidConfig`] if `name` is longer
+    /// than 64 bytes or has a null byte.
+    pub fn hostname(mut self, name: &str) -> Self {
+        self.hostname = Some(name.to_owned());
+        self
+    }
+
+    /// Set the NIS domain name of the container's UTS namespace, validated
+    /// like the [`Container::hostname`].
+    pub fn domainname(mut self, name: &str) -> Self {
+        self.domainname = Some(name.to_owned());
+        self
+    }
+
+    /// Set the hostname and the domain name, and write the hostname to the
+    /// rootfs' `/etc/hostname` (which must be writable), so tools reading the
+    /// file agree with the kernel. This conflicts with
+    /// [`Container::etc_from_host`].
+    pub fn with_hostname_and_domain(
+        self,
+        hostname: &str,
+        domain: &str,
+    ) -> Self {
+        let mut container = self.hostname(hostname).domainname(domain);
+        container.hostname_file = true;
+        container
+    }
+
+    /// Set a random hostname of 12 hex characters (like a short container
+    /// id), so throwaway containers don't show up with the host's name.
+    ///
+    /// Spawning fails with [`CuriumError::InvalidConfig`] if getrandom(2)
+    /// fails, e.g. with `ENOSYS` under a seccomp filter.
+    pub fn hostname_random(mut self) -> Self {
+        let mut bytes = [0u8; 6];
+        let mut filled = 0;
+        while filled < bytes.len() {
+            let rest = &mut bytes[filled..];
+            let n = unsafe {
+                libc::getrandom(rest.as_mut_ptr().cast(), rest.len(), 0)
+            };
+            match n {
+                0.. => filled += n as usize,
+                _ => match std::io::Error::last_os_error() {
+                    err if err.kind() == ErrorKind::Interrupted => continue,
+                    err => {
+                        self.invalid_hostname = Some(format!(
+                            "no random hostname, getrandom failed: {err}"
+                        ));
+                        return self;
+                    }
+                },
+            }
+        }
+
+        let name: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+        self.hostname(&name)
+    }
+
+    /// Choose what is mounted at `/proc`, a new proc filesystem by default.
+    ///
+    /// [`ProcMode::HostBind`] is meant for debugging, it exposes every host
+    /// process to the container.
+    pub fn proc_mode(mut self, mode: ProcMode) -> Self {
+        self.proc_mode = mode;
+        self
+    }
+
+    /// Mount the container's proc with `hidepid` and, with `subset_pid`, only
+    /// the process directories (`subset=pid`), see proc(5). `hidepid` 1 hides
+    /// the details of the processes of other users and 2 the processes
+    /// altogether.
+    ///
+    /// `subset=pid` leaves out `/proc/sys` and the other global files, the
+    /// ones of [`Container::harden_proc`] are skipped then. Spawning fails
+    /// with [`CuriumError::InvalidConfig`] for a `hidepid` over 2 or without
+    /// [`ProcMode::New`].
+    pub fn proc_options(mut self, hidepid: u8, subset_pid: bool) -> Self {
+        self.proc_options = Some((hidepid, subset_pid));
+        self
+    }
+
+    /// Mount proc at `path` in the container instead of `/proc`.
+    pub fn proc_target<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.proc_target = path.into();
+        self
+    }
+
+    /// Mount sysfs at `path` in the container instead of `/sys`.
+    pub fn sys_target<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.sys_target = path.into();
+        self
+    }
+
+    /// Make the rootfs writable without changing it: an overlay with the
+    /// rootfs as the lower directory and the upper and work directories on a
+    /// tmpfs is mounted over the root, so every write is lost when the
+    /// container exits.
+    ///
+    /// Like the other [`MountSpec`] filesystems, the overlay is mounted
+    /// `nodev,nosuid`. The tmpfs is labelled with the
+    /// [`Container::mount_label`].
+    pub fn ephemeral_writable_root(mut self) -> Self {
+        self.ephemeral_root = true;
+        self
+    }
+
+    /// Remount the container root with the atime `mode` once its mounts are
+    /// set up, e.g. [`AtimeMode::Noatime`] to avoid writing an atime for
+    /// every read of the image.
+    ///
+    /// Only the root mount is changed, not the mounts under it.
+    /// [`AtimeMode::Lazytime`] remounts the filesystem of the root, so without
+    /// [`Container::ephemeral_writable_root`] it applies to the host's
+    /// rootfs filesystem too. In a user namespace the atime of a root bound
+    /// from the host is locked, and can only be changed on the ephemeral
+    /// root.
+    pub fn root_atime(mut self, mode: AtimeMode) -> Self {
+        self.root_atime = Some(mode);
+        self
+    }
+
+    /// Make the container root read-only once it's entered. The mounts under
+    /// it, like `/proc` and the [`Container::extra_mounts`], keep their own
+    /// flags.
+    ///
+    /// The root of a bind mounted rootfs only becomes read-only in the
+    /// container's mount namespace, the host's rootfs is left writable.
+    pub fn readonly_root(mut self, readonly: bool) -> Self {
+        self.readonly_root = readonly;
+        self
+    }
+
+    /// Stop the container right before it executes the command, traced by
+    /// the calling thread, so a debugger can set it up.
+    ///
+    /// The child calls `ptrace(PTRACE_TRACEME)` and stops itself with
+    /// `SIGSTOP` once the container is set up, and `spawn` returns when it's
+    /// stopped. The thread that called `spawn` is the tracer: it resumes the
+    /// container with `PTRACE_CONT` (or `PTRACE_DETACH`) and a zero signal,
+    /// and gets the usual `SIGTRAP` after exec while still attached. An exec
+    /// failure after the stop isn't reported, the container exits with 1.
+    pub fn stop_before_exec(mut self) -> Self {
+        self.stop_before_exec = true;
+        self
+    }
+
+    /// Choose the network of the container, an isolated network namespace
+    /// by default.
+    pub fn net_mode(mut self, mode: NetMode) -> Self {
+        self.net_mode = mode;
+        self
+    }
+
+    /// Set sysctls of the container's network namespace, written from the
+    /// child through `/proc/sys` right after the namespace is set up. A write
+    /// that fails (e.g. `DisableIpv6` on a host without IPv6) fails spawning
+    /// with [`SetupStep::NetSysctl`].
+    ///
+    /// Spawning fails with [`CuriumError::InvalidConfig`] for an invalid
+    /// value, or with [`NetMode::Host`] where the host's sysctls would be
+    /// changed.
+    pub fn net_sysctls<I: IntoIterator<Item = NetSysctl>>(
+        mut self,
+        sysctls: I,
+    ) -> Self {
+        self.net_sysctls.extend(sysctls);
+        self
+    }
+
+    /// Read the container's stdin from `/dev/null` instead of inheriting it.
+    pub fn stdin_null(mut self) -> Self {
+        self.stdin_null = true;
+        self
+    }
+
+    /// Run the command with a new pseudo-terminal as its controlling
+    /// terminal and standard streams, driven through the master end of
+    /// the handle: see [`ContainerHandle::attach`] and
+    /// [`ContainerHandle::take_pty`]. [`Container::run`] attaches our
+    /// terminal to it.
+    ///
+    /// This conflicts with [`Container::stdin_null`],
+    /// [`Container::capture_output`] and the redirections to files.
+    pub fn tty(mut self, tty: bool) -> Self {
+        self.tty = tty;
+        self
+    }
+
+    /// Set up the container for an interactive shell, like `docker run -it`:
+    /// it gets a [`Container::tty`] and our `TERM`, [`Container::run`]
+    /// attaches our terminal to it in raw mode and forwards resizes.
+    pub fn interactive(self) -> Self {
+        self.tty(true).inherit_env_only(&["TERM"])
+    }
+
+    /// Write the container's stdout to the file at `path`, created or
+    /// truncated by `spawn`.
+    ///
+    /// The file is opened by the parent, so `path` is a host path. This
+    /// conflicts with [`Container::capture_output`].
+    pub fn stdout_to_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.stdout_file = Some(path.into());
+        self
+    }
+
+    /// Write the container's stderr to the file at `path`, like
+    /// [`Container::stdout_to_file`].
+    pub fn stderr_to_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.stderr_file = Some(path.into());
+        self
+    }
+
+    /// Write the pid of the container (as seen from the host) to `path` once
+    /// it's started, for supervisors like systemd's `PIDFile=`.
+    ///
+    /// The pid is written to a temporary file next to `path` and renamed, so
+    /// the pidfile is never partially written. It's removed when the
+    /// container is reaped by its [`ContainerHandle`].
+    pub fn pidfile<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.pidfile = Some(path.as_ref().to_owned());
+        self
+    }
+
+    /// Call `callback` with the child once it's cloned, before it's woken:
+    /// after its ids are mapped (and it's moved into a v1 cgroup), before it
+    /// sets anything up. The callbacks are called in the order they were
+    /// added.
+    ///
+    /// An error aborts the spawn, the child exits without being woken and
+    /// `spawn` returns the error.
+    pub fn after_clone<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(&Child) -> std::io::Result<()> + Send + 'static,
+    {
+        self.after_clone.push(Box::new(callback));
+        self
+    }
+
+    /// Call `callback` with the child once it's woken, while it sets up the
+    /// container and before it executes the command. The callbacks are
+    /// called in the order they were added.
+    ///
+    /// An error aborts the spawn, the child is killed and reaped and `spawn`
+    /// returns the error.
+    pub fn after_start<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(&Child) -> std::io::Result<()> + Send + 'static,
+    {
+        self.after_start.push(Box::new(callback));
+        self
+    }
+
+    /// Return the namespaces the container is spawned in, which can be
+    /// unshared with [`crate::unshare_namespaces`] to test its setup.
+    pub fn namespaces(&self) -> Namespaces {
+        let mut namespaces =
+            Namespaces::IPC | Namespaces::UTS | Namespaces::PID;
+        // Without a new mount namespace, the child enters an existing one.
+        if self.mount_namespace_fd.is_none() {
+            namespaces |= Namespaces::MOUNT;
+        }
+        if self.net_mode == NetMode::None {
+            namespaces |= Namespaces::NET;
+        }
+        // The user namespace is created first, so the other namespaces are
+        // owned by it.
+        if !self.uid_maps.is_empty() || !self.gid_maps.is_empty() {
+            namespaces |= Namespaces::USER;
+        }
+        if self.cgroup_namespace_root {
+            namespaces |= Namespaces::CGROUP;
+        }
+        if self.time_namespace {
+            namespaces |= Namespaces::TIME;
+        }
+        match self.joined_namespaces {
+            Some((_, joined)) => namespaces.difference(joined),
+            None => namespaces,
+        }
+    }
+
+    /// The data of the proc mount for [`Container::proc_options`].
+    fn proc_data(&self) -> Option<String> {
+        self.proc_options
+            .map(|(hidepid, subset_pid)| match subset_pid {
+                true => format!("hidepid={hidepid},subset=pid"),
+                false => format!("hidepid={hidepid}"),
+            })
+    }
+
+    /// Does the container get a new cgroup namespace, which needs its own
+    /// cgroup2 mount?
+    fn has_cgroup_namespace(&self) -> bool {
+        self.cgroup_namespace_root
+            || self.raw_clone_flags() & libc::CLONE_NEWCGROUP as u64 != 0
+    }
+
+    /// Is `path` the cgroup mount point, covered by the cgroup2 of a cgroup
+    /// namespace instead of masked?
+    fn covered_by_cgroupfs(&self, path: &str) -> bool {
+        path == "/sys/fs/cgroup" && self.has_cgroup_namespace()
+    }
+
+    /// Is the namespace `ns` joined with [`Container::join_namespaces_of`]?
+    fn joins(&self, ns: Namespaces) -> bool {
+        self.joined_namespaces
+            .is_some_and(|(_, joined)| joined.contains(ns))
+    }
+
+    /// Add the raw clone3(2) `flags` to the ones computed from the
+    /// configuration, for a combination [`Namespaces`] doesn't model yet.
+    ///
+    /// Flags that would share the parent's stack, threads or signal handlers
+    /// with the child, like `CLONE_VM`, fail the spawn with
+    /// [`CuriumError::InvalidConfig`]. Nothing else checks that the setup
+    /// still works with them.
+    pub fn clone_flags(mut self, flags: u64) -> Self {
+        self.extra_clone_flags |= flags;
+        self
+    }
+
+    /// Clear the raw clone3(2) `flags`, after [`Container::clone_flags`] is
+    /// applied.
+    ///
+    /// Clearing `CLONE_NEWNS`, without which the child would mount in (and
+    /// pivot the root of) the parent's mount namespace, or `CLONE_PIDFD`,
+    /// which the handle needs, fails the spawn with
+    /// [`CuriumError::InvalidConfig`].
+    pub fn remove_clone_flags(mut self, flags: u64) -> Self {
+        self.removed_clone_flags |= flags;
+        self
+    }
+
+    /// Return the flags the child is cloned with.
+    fn raw_clone_flags(&self) -> u64 {
+        // The cgroup namespace is unshared once the child is in its cgroup,
+        // the time namespace before its clocks are set.
+        let namespaces = self
+            .namespaces()
+            .difference(Namespaces::CGROUP | Namespaces::TIME);
+        let flags = libc::CLONE_CLEAR_SIGHAND as u64
+            | libc::CLONE_PIDFD as u64
+            | namespaces.bits() as u64;
+
+        (flags | self.extra_clone_flags) & !self.removed_clone_flags
+    }
+
+    /// Return a c-style null-terminated array for the entrypoint followed by
+    /// the command.
+    fn get_argv(&self) -> Vec<*const i8> {
+        let mut argv: Vec<*const i8> = self
+            .entrypoint
+            .iter()
+            .chain(&self.command)
+            .map(|arg| arg.as_ptr())
+            .collect();
+
+        argv.push(std::ptr::null());
+        argv
+    }
+
+    /// Return a c-style null-terminated array for `env`
+    fn get_envp(env: &[CString]) -> Vec<*const i8> {
+        let mut envp: Vec<*const i8> =
+            env.iter().map(|env| env.as_ptr()).collect();
+
+        envp.push(std::ptr::null());
+        envp
+    }
+
+    /// Run the container to completion: spawn it, wait for it to exit and
+    /// return its exit status.
+    ///
+    /// This is the entry point to just run a command in a container, use
+    /// [`Container::spawn`] to keep a handle to a running container instead.
+    /// A container with a [`Container::tty`] is attached to our terminal
+    /// while it runs.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use curium::Container;
+    ///
+    /// let status = Container::new("/tmp/bbox".into(), "/bin/sh")
+    ///     .arg("-c")
+    ///     .arg("exit 3")
+    ///     .run()
+    ///     .unwrap();
+    /// assert_eq!(status.code, Some(3));
+    /// ```
+    pub fn run(&mut self) -> Result<ExitStatus> {
+        let mut handle = self.spawn()?;
+        match self.tty {
+            true => Ok(handle.attach()?),
+            false => Ok(handle.wait()?),
+        }
+    }
+
+    /// Run the container to completion like [`Container::run`], telling a
+    /// command that failed from one that never started.
+    ///
+    /// A failed spawn, including a missing command, is an
+    /// [`Outcome::StartupFailed`] rather than an error: only failing to wait
+    /// for the container is.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use curium::{Container, Outcome};
+    ///
+    /// let outcome = Container::new("/tmp/bbox".into(), "/bin/missing")
+    ///     .run_outcome()
+    ///     .unwrap();
+    /// assert!(matches!(outcome, Outcome::StartupFailed(_)));
+    /// ```
+    pub fn run_outcome(&mut self) -> std::io::Result<Outcome> {
+        let mut handle = match self.spawn() {
+            Ok(handle) => handle,
+            Err(err) => return Ok(Outcome::StartupFailed(err)),
+        };
+        let status = match self.tty {
+            true => handle.attach()?,
+            false => handle.wait()?,
+        };
+        Ok(status.into())
+    }
+
+    /// Restore a container checkpointed with [`ContainerHandle::checkpoint`]
+    /// from the images directory `dir`, with criu. criu must be installed
+    /// and we must be root.
+    ///
+    /// The processes, namespaces and mounts come from the images: only the
+    /// rootfs, the cgroup and the pidfile of this container are used, the
+    /// rootfs must be the one it was checkpointed with. The restored init is
+    /// a child of ours, like a spawned one. Why a restore failed is in
+    /// `restore.log` in `dir`.
+    pub fn restore_from_checkpoint<P: AsRef<Path>>(
+        &self,
+        dir: P,
+    ) -> Result<ContainerHandle> {
+        let dir = dir.as_ref();
+        // criu writes the pid once the tree is restored.
+        let pidfile = std::env::temp_dir().join(format!(
+            "curium-restore-{}-{}.pid",
+            std::process::id(),
+            dir.display().to_string().replace('/', "_"),
+        ));
+        let command = criu::restore_command(
+            dir,
+            Path::new(&self.root),
+            self.cgroup.as_deref(),
+            &pidfile,
+        );
+        let restored = criu::run(command, dir)
+            .and_then(|()| std::fs::read_to_string(&pidfile));
+        let _ = std::fs::remove_file(&pidfile);
+        let pid: i64 = restored?.trim().parse().map_err(|_| {
+            std::io::Error::other("criu wrote an invalid pidfile")
+        })?;
+
+        // The restored init is our child, so the pid can't be reused before
+        // we reap it.
+        let pidfd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) };
+        if pidfd < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        // SAFETY: pidfd_open returned a new fd.
+        let pidfd = unsafe { OwnedFd::from_raw_fd(pidfd as RawFd) };
+        let child = Child {
+            tid: pid as u64,
+            pid,
+            pidfd: Some(pidfd.as_raw_fd()),
+        };
+        event!(pid, ?dir, "restored container");
+
+        let mut handle = ContainerHandle::new(child, pidfd);
+        if let Some(path) = &self.pidfile {
+            if let Err(err) = write_pidfile(path, pid) {
+                let _ = handle.signal(libc::SIGKILL);
+                let _ = handle.wait();
+                return Err(err.into());
+            }
+            handle = handle.with_pidfile(path.clone());
+        }
+        Ok(handle)
+    }
+
+    /// List the operations `spawn` would make, without making them: the
+    /// cgroup, clone flags, id maps, mounts, prctls and exec, in order. Meant
+    /// to debug a configuration or to diff two of them.
+    ///
+    /// The configuration is checked like `spawn` does, and the host is only
+    /// read: to detect the cgroup version and find the executable of
+    /// [`Container::bind_executable`] and its libraries.
+    ///
+    /// ```
+    /// use curium::{Container, plan::Operation};
+    ///
+    /// let plan = Container::new("/rootfs".into(), "/bin/sh")
+    ///     .hostname("box")
+    ///     .dry_run()
+    ///     .unwrap();
+    /// assert!(plan.contains(&Operation::SetHostname("box".into())));
+    /// for operation in &plan {
+    ///     println!("{operation}");
+    /// }
+    /// ```
+    pub fn dry_run(&self) -> Result<Vec<Operation>> {
+        self.check()?;
+        let lossy = |s: &[u8]| String::from_utf8_lossy(s).into_owned();
+        let mut plan = vec![];
+
+        let backend = match &self.cgroup {
+            Some(name) => {
+                let mut limits = vec![];
+                if let Some(limit) = self.memory_limit {
+                    limits.push(("memory_limit", limit.to_string()));
+                }
+                if let Some(limit) = self.swap_limit {
+                    limits.push(("swap_limit", limit.to_string()));
+                }
+                if let Some(swappiness) = self.swappiness {
+                    limits.push(("swappiness", swappiness.to_string()));
+                }
+                if self.oom_group_kill {
+                    limits.push(("oom_group", "1".to_owned()));
+                }
+                if let Some(max) = self.pids_limit {
+                    limits.push(("pids_limit", max.to_string()));
+                }
+                for (device, limit) in &self.io_limits {
+                    limits.push(("io_limit", limit.io_max_line(*device)));
+                }
+                if self.batch_mode {
+                    limits.push(("io_weight", "lowest".to_owned()));
+                }
+                plan.push(Operation::CreateCgroup {
+                    name: name.clone(),
+                    limits,
+                });
+                Some(CgroupBackend::detect()?)
+            }
+            None => None,
+        };
+        if let Some((fd, joined)) = self.joined_namespaces
+            && joined.contains(Namespaces::PID)
+        {
+            plan.push(Operation::JoinNamespaces {
+                fd,
+                namespaces: Namespaces::PID,
+            });
+        }
+        plan.push(Operation::Clone {
+            flags: self.raw_clone_flags(),
+        });
+        if !self.uid_maps.is_empty() || !self.gid_maps.is_empty() {
+            plan.push(Operation::WriteIdMaps {
+                uid_maps: self.uid_maps.clone(),
+                gid_maps: self.gid_maps.clone(),
+            });
+        }
+        if let Some(name) = &self.cgroup
+            && backend == Some(CgroupBackend::V1)
+        {
+            plan.push(Operation::AddToCgroup { name: name.clone() });
+        }
+        if let Some((soft, hard)) = self.rlimit_nofile {
+            plan.push(Operation::SetRlimit {
+                resource: "RLIMIT_NOFILE",
+                soft,
+                hard,
+            });
+        }
+        for _ in &self.after_clone {
+            plan.push(Operation::AfterClone);
+        }
+        if self.waits_for_parent(backend) {
+            plan.push(Operation::Wake);
+        }
+        for _ in &self.after_start {
+            plan.push(Operation::AfterStart);
+        }
+
+        if let Some((fd, namespaces)) = self.joined_namespaces {
+            plan.push(Operation::JoinNamespaces { fd, namespaces });
+        }
+        if self.namespaces().contains(Namespaces::CGROUP) {
+            plan.push(Operation::Unshare {
+                namespaces: Namespaces::CGROUP,
+            });
+        }
+        if self.namespaces().contains(Namespaces::TIME) {
+            plan.push(Operation::Unshare {
+                namespaces: Namespaces::TIME,
+            });
+            plan.push(Operation::WriteFile {
+                path: "/proc/self/timens_offsets".into(),
+            });
+        }
+        if let Some(name) = &self.hostname {
+            plan.push(Operation::SetHostname(name.clone()));
+        }
+        if let Some(name) = &self.domainname {
+            plan.push(Operation::SetDomainname(name.clone()));
+        }
+        if self.net_mode == NetMode::None && !self.joins(Namespaces::NET) {
+            plan.push(Operation::LoopbackUp);
+        }
+        for sysctl in &self.net_sysctls {
+            plan.push(Operation::WriteFile {
+                path: sysctl.path().to_owned(),
+            });
+        }
+
+        let cmd = self.entrypoint.first().or(self.command.first());
+        let cmd = cmd.expect("the command is checked");
+        let (cmd, executable_binds) = match self.executable_binds(cmd)? {
+            None => (lossy(cmd.to_bytes()), vec![]),
+            Some(binds) => (binds[0].to_string_lossy().into_owned(), binds),
+        };
+
+        match self.mount_namespace_fd {
+            Some(fd) => plan.push(Operation::EnterMountNamespace { fd }),
+            None if self.joins(Namespaces::MOUNT) => (),
+            None => {
+                for step in
+                    self.rootfs_steps(&executable_binds, self.rootfs_fd, None)?
+                {
+                    step.describe(&mut plan);
+                }
+            }
+        }
+
+        if let Some(fd) = self.working_dir_fd {
+            plan.push(Operation::Fchdir { fd });
+        }
+        if self.batch_mode {
+            plan.push(Operation::SetScheduler {
+                policy: "SCHED_IDLE",
+            });
+        }
+        if let Some(mask) = self.umask {
+            plan.push(Operation::Umask { mask });
+        }
+        if self.disable_aslr {
+            plan.push(Operation::DisableAslr);
+        }
+        if let Some(name) = &self.apparmor_profile {
+            plan.push(Operation::SetExecLabel {
+                lsm: "apparmor",
+                label: format!("exec {name}"),
+            });
+        }
+        if let Some(label) = &self.selinux_label {
+            plan.push(Operation::SetExecLabel {
+                lsm: "selinux",
+                label: label.clone(),
+            });
+        }
+        if self.landlock.is_some() {
+            plan.push(Operation::RestrictLandlock);
+        }
+        if !self.dropped_bounding_caps.is_empty() {
+            plan.push(Operation::DropBoundingCaps(
+                self.dropped_bounding_caps.clone(),
+            ));
+        }
+        if !self.ambient_capabilities.is_empty() {
+            plan.push(Operation::RaiseAmbientCaps(
+                self.ambient_capabilities.clone(),
+            ));
+        }
+        if self.seccomp_default {
+            plan.push(Operation::LoadSeccompDefault);
+        }
+        if !self.seccomp_notify.is_empty() {
+            plan.push(Operation::LoadSeccompFilter {
+                syscalls: self.seccomp_notify.clone(),
+            });
+        }
+        if self.stop_before_exec {
+            plan.push(Operation::StopBeforeExec);
+        }
+        plan.push(Operation::Exec {
+            path: cmd,
+            argv: self
+                .entrypoint
+                .iter()
+                .chain(&self.command)
+                .map(|arg| lossy(arg.to_bytes()))
+                .collect(),
+            envp: self
+                .resolve_env()
+                .iter()
+                .map(|env| lossy(env.to_bytes()))
+                .collect(),
+        });
+
+        Ok(plan)
+    }
+
+    /// Prepare the mount setup of the child in a new mount namespace, which
+    /// spawn takes and dry_run lists. `tree` is the rootfs tree cloned from
+    /// the rootfs fd, `dir` the fd of the rootfs for an ephemeral root
+    /// without one.
+    fn rootfs_steps(
+        &self,
+        executable_binds: &[PathBuf],
+        tree: Option<RawFd>,
+        dir: Option<RawFd>,
+    ) -> Result<Vec<RootfsStep>> {
+        // With a rootfs fd, the child changes into the new root and mounts
+        // relative to it.
+        let root = match self.rootfs_fd {
+            Some(_) => ".",
+            None => self.root.as_str(),
+        };
+        let in_root =
+            |path: &str, what| config_cstring(format!("{root}{path}"), what);
+        let label = self.mount_label.as_deref();
+        let rootfs = config_cstring(self.root.clone(), "root")?;
+        let procfs = format!("{root}{}", self.proc_target.display());
+        let sysfs = format!("{root}{}", self.sys_target.display());
+        let mut steps = vec![RootfsStep::MakePrivate];
+
+        match tree {
+            // Make the container root a mount.
+            None => steps.push(RootfsStep::BindRoot(rootfs.clone())),
+            Some(tree) => steps.push(RootfsStep::AttachTree {
+                tree,
+                target: rootfs.clone(),
+            }),
+        }
+        if let Some(dir) = dir {
+            steps.push(RootfsStep::ReplaceRootDir {
+                root: rootfs.clone(),
+                dir,
+            });
+        }
+        if self.ephemeral_root {
+            let upper = format!("{}/upper", self.root);
+            let work = format!("{}/work", self.root);
+            let scratch = MountSpec::Tmpfs {
+                dst: self.root.clone().into(),
+                size: None,
+                mode: Some(0o755),
+            };
+            steps.push(RootfsStep::Mount(scratch.prepare("", label)?));
+            for dir in [&upper, &work] {
+                steps.push(RootfsStep::Mkdir {
+                    path: config_cstring(dir.as_str(), "root")?,
+                    mode: 0o755,
+                });
+            }
+            // Once the tmpfs covers the root the rootfs can't be reached by
+            // its path, and overlayfs only takes lower directories from the
+            // container's mount namespace.
+            let lower = match tree.or(dir) {
+                Some(fd) => format!("/proc/self/fd/{fd}"),
+                None => "<rootfs fd>".to_owned(),
+            };
+            let overlay = MountSpec::Overlay {
+                lower: vec![lower.into()],
+                upper: upper.into(),
+                work: work.into(),
+                dst: self.root.clone().into(),
+            };
+            steps.push(RootfsStep::Mount(overlay.prepare("", None)?));
+        }
+        // The overlay covers the tree, so it's entered by path.
+        match (tree, self.ephemeral_root) {
+            (Some(_), true) => steps.push(RootfsStep::Chdir(rootfs.clone())),
+            (Some(tree), false) => steps.push(RootfsStep::Fchdir(tree)),
+            (None, _) => (),
+        }
+
+        let procfs_path = |path: &str| {
+            config_cstring(format!("{procfs}{path}"), "proc target")
+        };
+        if self.proc_mode != ProcMode::None {
+            steps.push(RootfsStep::MountPoint {
+                path: procfs_path("")?,
+                step: SetupStep::ProcMountpoint,
+            });
+        }
+        match self.proc_mode {
+            ProcMode::New => steps.push(RootfsStep::Proc {
+                target: procfs_path("")?,
+                data: self
+                    .proc_data()
+                    .map(|data| config_cstring(data, "proc options"))
+                    .transpose()?,
+            }),
+            ProcMode::HostBind => {
+                steps.push(RootfsStep::BindProc(procfs_path("")?))
+            }
+            ProcMode::None => (),
+        }
+        if self.harden_proc {
+            for path in PROC_READONLY_PATHS {
+                let path = procfs_path(&path["/proc".len()..])?;
+                steps.push(RootfsStep::Readonly(path));
+            }
+            for path in PROC_MASKED_PATHS {
+                let path = procfs_path(&path["/proc".len()..])?;
+                steps.push(RootfsStep::Mask(path));
+            }
+        }
+
+        let sysfs_path =
+            |path: &str| config_cstring(format!("{sysfs}{path}"), "sys target");
+        steps.push(RootfsStep::MountPoint {
+            path: sysfs_path("")?,
+            step: SetupStep::SysMountpoint,
+        });
+        match self.net_mode {
+            NetMode::None => steps.push(RootfsStep::Sysfs(sysfs_path("")?)),
+            NetMode::Host => steps.push(RootfsStep::BindSys(sysfs_path("")?)),
+        }
+        if self.has_cgroup_namespace() {
+            steps.push(RootfsStep::Cgroupfs(sysfs_path("/fs/cgroup")?));
+        }
+        if self.harden_sys {
+            for path in SYS_MASKED_PATHS {
+                if self.covered_by_cgroupfs(path) {
+                    continue;
+                }
+                let path = sysfs_path(&path["/sys".len()..])?;
+                steps.push(RootfsStep::Mask(path));
+            }
+        }
+
+        if self.etc_from_host {
+            for file in HOST_ETC_FILES {
+                let target = in_root(&file.to_string_lossy(), "root")?;
+                steps.push(RootfsStep::BindFile {
+                    source: (*file).to_owned(),
+                    target,
+                });
+            }
+        }
+        // The file is written in the rootfs, where a bind of the host's file
+        // would be read-only or change the host.
+        if self.hostname_file {
+            let mut contents = self.hostname.clone().unwrap_or_default();
+            contents.push('\n');
+            steps.push(RootfsStep::WriteFile {
+                path: in_root("/etc/hostname", "root")?,
+                contents: contents.into_bytes(),
+            });
+        }
+
+        // Before the other mounts, which may bind host directories over the
+        // mount points. Each bind with the directories of its mount point,
+        // outermost first.
+        for path in executable_binds {
+            let in_root = |path: &Path| {
+                let mut target = root.as_bytes().to_vec();
+                target.extend_from_slice(path.as_os_str().as_bytes());
+                config_cstring(target, "executable path")
+            };
+            for dir in path
+                .ancestors()
+                .skip(1)
+                .filter(|dir| dir.parent().is_some())
+                .collect::<Vec<_>>()
+                .into_iter()
+                .rev()
+            {
+                steps.push(RootfsStep::Mkdir {
+                    path: in_root(dir)?,
+                    mode: 0o755,
+                });
+            }
+            steps.push(RootfsStep::BindFile {
+                source: config_cstring(
+                    path.as_os_str().as_bytes(),
+                    "executable path",
+                )?,
+                target: in_root(path)?,
+            });
+        }
+        if let Some(size) = self.dev_shm {
+            for dir in ["/dev", "/dev/shm"] {
+                steps.push(RootfsStep::Mkdir {
+                    path: in_root(dir, "root")?,
+                    mode: 0o755,
+                });
+            }
+            let dev_shm = MountSpec::Tmpfs {
+                dst: "/dev/shm".into(),
+                size: Some(size),
+                mode: Some(0o1777),
+            };
+            steps.push(RootfsStep::Mount(dev_shm.prepare(root, label)?));
+        }
+        // The mount point, the mount and the runtime directory of the user.
+        if let Some(user) = self.run_tmpfs {
+            steps.push(RootfsStep::Mkdir {
+                path: in_root("/run", "root")?,
+                mode: 0o755,
+            });
+            steps.push(RootfsStep::Mount(run_tmpfs().prepare(root, label)?));
+            if let Some(uid) = user {
+                let dir = in_root(&format!("/run/user/{uid}"), "root")?;
+                steps.push(RootfsStep::Mkdir {
+                    path: in_root("/run/user", "root")?,
+                    mode: 0o755,
+                });
+                steps.push(RootfsStep::Mkdir {
+                    path: dir.clone(),
+                    mode: 0o700,
+                });
+                steps.push(RootfsStep::Chown { path: dir, uid });
+            }
+        }
+        for spec in &self.extra_mounts {
+            steps.push(RootfsStep::Mount(spec.prepare(root, label)?));
+        }
+        if let Some(mode) = self.root_atime {
+            steps.push(RootfsStep::RemountRoot {
+                root: config_cstring(root, "root")?,
+                mode,
+            });
+        }
+
+        match (self.root_mode, tree) {
+            // With a rootfs fd the new root is the working directory.
+            (RootMode::Chroot, None) => steps.push(RootfsStep::Chroot(rootfs)),
+            (RootMode::Chroot, Some(_)) => {
+                steps.push(RootfsStep::Chroot(c".".to_owned()))
+            }
+            (RootMode::PivotRoot, None) => steps.push(RootfsStep::PivotRoot {
+                new_root: rootfs,
+                put_old: config_cstring(
+                    format!("{}/{}", self.root, self.old_root_dir),
+                    "old root directory",
+                )?,
+                put_old_in_root: config_cstring(
+                    format!("/{}", self.old_root_dir),
+                    "old root directory",
+                )?,
+            }),
+            (RootMode::PivotRoot, Some(_)) => {
+                steps.push(RootfsStep::PivotRootHere)
+            }
+        }
+        steps.push(RootfsStep::Chdir(c"/".to_owned()));
+        if self.readonly_root {
+            steps.push(RootfsStep::ReadonlyRoot);
+        }
+        Ok(steps)
+    }
+
+    /// Return true if the child waits for the parent to write its id maps
+    /// or move it into a v1 cgroup before going on.
+    fn waits_for_parent(&self, cgroup: Option<CgroupBackend>) -> bool {
+        !self.uid_maps.is_empty()
+            || !self.gid_maps.is_empty()
+            || cgroup == Some(CgroupBackend::V1)
+            || !self.after_clone.is_empty()
+            || self.rlimit_nofile.is_some()
+    }
+
+    /// Check the configuration for every problem found by spawn, and the
+    /// host for the paths it needs: the rootfs, the sources of the binds and
+    /// the bound executable. Unlike spawn, which fails on the first problem
+    /// (sometimes in the child, with only a [`SetupStep`] and an errno), all
+    /// of them are returned.
+    ///
+    /// Nothing is created or mounted, so a container that passes can still
+    /// fail to spawn, e.g. without the privileges for its namespaces.
+    pub fn validate(&self) -> std::result::Result<(), Vec<CuriumError>> {
+        let mut problems = self.problems();
+        if self.rootfs_fd.is_none() && !Path::new(&self.root).is_dir() {
+            problems.push(CuriumError::InvalidConfig(format!(
+                "the rootfs {:?} isn't a directory",
+                self.root
+            )));
+        }
+        for mount in &self.extra_mounts {
+            let (MountSpec::Bind { src, .. }
+            | MountSpec::RecursiveBind { src, .. }) = mount
+            else {
+                continue;
+            };
+            if let Err(err) = readable(src) {
+                problems.push(CuriumError::InvalidConfig(format!(
+                    "the bind source {} isn't readable: {err}",
+                    src.display()
+                )));
+            }
+        }
+        if let Some(cmd) = self.entrypoint.first().or(self.command.first())
+            && let Err(err) = self.executable_binds(cmd)
+        {
+            problems.push(err);
+        }
+        match problems.is_empty() {
+            true => Ok(()),
+            false => Err(problems),
+        }
+    }
+
+    /// Reject a configuration which can't be spawned, without touching the
+    /// host, with the first of [`Container::problems`].
+    fn check(&self) -> Result<()> {
+        match self.problems().into_iter().next() {
+            Some(problem) => Err(problem),
+            None => Ok(()),
+        }
+    }
+
+    /// Every reason the configuration can't be spawned, found without
+    /// touching the host.
+    fn problems(&self) -> Vec<CuriumError> {
+        let mut problems = vec![];
+        if self.entrypoint.is_empty() && self.command.is_empty() {
+            problems.push(CuriumError::InvalidConfig(
+                "the entrypoint and command are empty".into(),
+            ));
+        }
+        if let Some(reason) = &self.invalid_hostname {
+            problems.push(CuriumError::InvalidConfig(reason.clone()));
+        }
+        if let Some(reason) = &self.invalid_env {
+            problems.push(CuriumError::InvalidConfig(reason.clone()));
+        }
+        if !self.net_sysctls.is_empty() && self.net_mode == NetMode::Host {
+            problems.push(CuriumError::InvalidConfig(
+                "network sysctls would be set on the host's namespace".into(),
+            ));
+        }
+        for sysctl in &self.net_sysctls {
+            if let Err(err) = sysctl.value() {
+                problems.push(err);
+            }
+        }
+        for mount in &self.extra_mounts {
+            if let Err(err) = mount.prepare("", self.mount_label.as_deref()) {
+                problems.push(err);
+            }
+        }
+        if self.old_root_dir.is_empty()
+            || self.old_root_dir.contains('/')
+            || self.old_root_dir == "."
+            || self.old_root_dir == ".."
+        {
+            problems.push(CuriumError::InvalidConfig(format!(
+                "The old root directory {:?} isn't a single path component",
+                self.old_root_dir
+            )));
+        }
+        for (name, what) in [
+            (&self.hostname, "hostname"),
+            (&self.domainname, "domain name"),
+        ] {
+            if name.as_ref().is_some_and(|name| name.len() > HOST_NAME_MAX) {
+                problems.push(CuriumError::InvalidConfig(format!(
+                    "the {what} is longer than {HOST_NAME_MAX} bytes"
+                )));
+            }
+        }
+        if (self.seccomp_default || !self.seccomp_notify.is_empty())
+            && seccomp::AUDIT_ARCH.is_none()
+        {
+            problems.push(CuriumError::InvalidConfig(
+                "seccomp filters aren't supported on this arch".into(),
+            ));
+        }
+        if let Some(nr) = self
+            .seccomp_notify
+            .iter()
+            .find(|nr| seccomp::SPAWN_SYSCALLS.contains(nr))
+        {
+            problems.push(CuriumError::InvalidConfig(format!(
+                "syscall {nr} is made by the child before exec, it can't be \
+                 notified"
+            )));
+        }
+        if let Some(Some(uid)) = self.run_tmpfs
+            && self.namespaces().contains(Namespaces::USER)
+            && !self
+                .uid_maps
+                .iter()
+                .any(|map| (map.inside..map.inside + map.count).contains(&uid))
+        {
+            problems.push(CuriumError::InvalidConfig(format!(
+                "the uid {uid} of /run/user isn't mapped"
+            )));
+        }
+        if let Some((soft, hard)) = self.rlimit_nofile
+            && soft > hard
+        {
+            problems.push(CuriumError::InvalidConfig(format!(
+                "the soft nofile limit {soft} is above the hard limit {hard}"
+            )));
+        }
+        if self.hostname_file && self.etc_from_host {
+            problems.push(CuriumError::InvalidConfig(
+                "/etc/hostname is already bound from the host".into(),
+            ));
+        }
+        for (flag, name) in UNSAFE_CLONE_FLAGS {
+            if self.extra_clone_flags & flag != 0 {
+                problems.push(CuriumError::InvalidConfig(format!(
+                    "{name} can't be added to the clone flags"
+                )));
+            }
+        }
+        for (flag, name) in REQUIRED_CLONE_FLAGS {
+            if self.removed_clone_flags & flag != 0 {
+                problems.push(CuriumError::InvalidConfig(format!(
+                    "{name} can't be removed from the clone flags"
+                )));
+            }
+        }
+        if self.namespaces().contains(Namespaces::USER)
+            && self.raw_clone_flags() & libc::CLONE_NEWUSER as u64 == 0
+        {
+            problems.push(CuriumError::InvalidConfig(
+                "the id maps are written for a new user namespace, but \
+                 CLONE_NEWUSER is removed"
+                    .into(),
+            ));
+        }
+        if (!self.uid_maps.is_empty() || !self.gid_maps.is_empty())
+            && !self.namespaces().contains(Namespaces::USER)
+        {
+            problems.push(CuriumError::InvalidConfig(
+                "the id maps need a new user namespace, not a joined one"
+                    .into(),
+            ));
+        }
+        if self.proc_mode == ProcMode::New
+            && self.raw_clone_flags() & libc::CLONE_NEWPID as u64 == 0
+            && !self.joins(Namespaces::PID)
+        {
+            problems.push(CuriumError::InvalidConfig(
+                "a new proc mount needs a new or joined pid namespace, \
+                 CLONE_NEWPID is removed"
+                    .into(),
+            ));
+        }
+        if self.mount_namespace_fd.is_some()
+            && (self.namespaces().contains(Namespaces::USER)
+                || self.rootfs_fd.is_some())
+        {
+            problems.push(CuriumError::InvalidConfig(
+                "a mount namespace fd can't be entered from a new user \
+                 namespace or with a rootfs fd"
+                    .into(),
+            ));
+        }
+        if let Some((_, joined)) = self.joined_namespaces {
+            let conflict = if self.namespaces().contains(Namespaces::USER) {
+                Some("a new user namespace")
+            } else if joined.contains(Namespaces::MOUNT)
+                && (self.mount_namespace_fd.is_some()
+                    || self.rootfs_fd.is_some())
+            {
+                Some("a mount namespace fd or rootfs fd")
+            } else if joined.contains(Namespaces::UTS)
+                && (self.hostname.is_some() || self.domainname.is_some())
+            {
+                Some("a hostname or domain name")
+            } else if joined.contains(Namespaces::NET)
+                && (self.net_mode == NetMode::Host
+                    || !self.net_sysctls.is_empty())
+            {
+                Some("the host's network or network sysctls")
+            } else if joined.contains(Namespaces::CGROUP)
+                && self.cgroup_namespace_root
+            {
+                Some("a cgroup namespace root")
+            } else {
+                None
+            };
+            if let Some(conflict) = conflict {
+                problems.push(CuriumError::InvalidConfig(format!(
+                    "joined namespaces can't be combined with {conflict}"
+                )));
+            }
+        }
+        if self.memory_limit.is_some() && self.cgroup.is_none() {
+            problems.push(CuriumError::InvalidConfig(
+                "a memory limit needs a cgroup".into(),
+            ));
+        }
+        if self.oom_group_kill && self.cgroup.is_none() {
+            problems.push(CuriumError::InvalidConfig(
+                "killing the OOM group needs a cgroup".into(),
+            ));
+        }
+        if self.pids_limit.is_some() && self.cgroup.is_none() {
+            problems.push(CuriumError::InvalidConfig(
+                "a pids limit needs a cgroup".into(),
+            ));
+        }
+        if !self.io_limits.is_empty() && self.cgroup.is_none() {
+            problems.push(CuriumError::InvalidConfig(
+                "an I/O limit needs a cgroup".into(),
+            ));
+        }
+        if (self.swap_limit.is_some() || self.swappiness.is_some())
+            && self.cgroup.is_none()
+        {
+            problems.push(CuriumError::InvalidConfig(
+                "a swap setting needs a cgroup".into(),
+            ));
+        }
+        if self.swappiness.is_some_and(|swappiness| swappiness > 100) {
+            problems.push(CuriumError::InvalidConfig(
+                "the swappiness is over 100".into(),
+            ));
+        }
+        if let Some((hidepid, _)) = self.proc_options {
+            if hidepid > 2 {
+                problems.push(CuriumError::InvalidConfig(format!(
+                    "hidepid={hidepid} isn't 0, 1 or 2"
+                )));
+            }
+            if self.proc_mode != ProcMode::New {
+                problems.push(CuriumError::InvalidConfig(
+                    "proc options need a new proc mount".into(),
+                ));
+            }
+        }
+        if let Some(name) = &self.apparmor_profile {
+            if let Err(err) = config_cstring(name.as_str(), "AppArmor profile")
+            {
+                problems.push(err);
+            }
+            if !lsm::apparmor_enabled() {
+                problems.push(CuriumError::InvalidConfig(
+                    "AppArmor isn't enabled on the host".into(),
+                ));
+            }
+        }
+
+        if let Some(label) = &self.selinux_label
+            && let Err(err) = config_cstring(label.as_str(), "SELinux label")
+        {
+            problems.push(err);
+        }
+        if (self.selinux_label.is_some() || self.mount_label.is_some())
+            && !lsm::selinux_enabled()
+        {
+            problems.push(CuriumError::InvalidConfig(
+                "SELinux isn't enabled on the host".into(),
+            ));
+        }
+
+        if self.capture_output
+            && (self.stdout_file.is_some() || self.stderr_file.is_some())
+        {
+            problems.push(CuriumError::InvalidConfig(
+                "the output is captured and redirected to a file".into(),
+            ));
+        }
+        if self.tty
+            && (self.stdin_null
+                || self.capture_output
+                || self.stdout_file.is_some()
+                || self.stderr_file.is_some())
+        {
+            problems.push(CuriumError::InvalidConfig(
+                "the standard streams are the tty's, they can't be \
+                 redirected"
+                    .into(),
+            ));
+        }
+        problems
+    }
+
+    /// The host files [`Container::bind_executable`] binds into the rootfs,
+    /// the executable `cmd` first, `None` without it.
+    fn executable_binds(&self, cmd: &CStr) -> Result<Option<Vec<PathBuf>>> {
+        let Some(interpreter) = self.bind_executable else {
+            return Ok(None);
+        };
+        let path = host_executable(cmd)?;
+        let mut binds = vec![path.clone()];
+        if interpreter && let Some(interp) = elf::interpreter(&path)? {
+            binds.push(interp);
+        }
+        if self.bind_dependencies {
+            for lib in elf::dependencies(&path)? {
+                if !binds.contains(&lib) {
+                    binds.push(lib);
+                }
+            }
+        }
+        Ok(Some(binds))
+    }
+
+    /// Spawn the container and return a handle to it.
+    ///
+    /// This returns once the command has been executed, use
+    /// [`ContainerHandle::wait`] to wait for it to exit. If exec fails, the
+    /// container is reaped and the error is returned.
+    pub fn spawn(&mut self) -> Result<ContainerHandle> {
+        self.check()?;
+        let Some(cmd) = self.entrypoint.first().or(self.command.first()) else {
+            return Err(CuriumError::InvalidConfig(
+                "the entrypoint and command are empty".into(),
+            ));
+        };
+        let argv = self.get_argv();
+        let env = self.resolve_env();
+        let envp = Self::get_envp(&env);
+
+        let (cmd, executable_binds) = match self.executable_binds(cmd)? {
+            None => (cmd.clone(), vec![]),
+            Some(binds) => {
+                let cmd =
+                    config_cstring(binds[0].as_os_str().as_bytes(), "command")?;
+                (cmd, binds)
+            }
+        };
+
+        let uts_name = |name: &Option<String>, what| match name {
+            Some(name) => config_cstring(name.as_str(), what).map(Some),
+            None => Ok(None),
+        };
+        let hostname = uts_name(&self.hostname, "hostname")?;
+        let domainname = uts_name(&self.domainname, "domain name")?;
+        let net_sysctls = self
+            .net_sysctls
+            .iter()
+            .map(|sysctl| {
+                Ok((
+                    config_cstring(sysctl.path(), "network sysctl")?,
+                    sysctl.value()?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let rootfs_tree = self.rootfs_fd.map(clone_tree).transpose()?;
+        // The lower directory of an ephemeral root is the fd of the attached
+        // rootfs tree, or without one the fd of the rootfs, which the child
+        // replaces with the rootfs mount of its namespace.
+        let rootfs_dir = match (self.ephemeral_root, &rootfs_tree) {
+            (true, None) => {
+                Some(open_dir(&config_cstring(self.root.clone(), "root")?)?)
+            }
+            _ => None,
+        };
+        let rootfs_steps = self.rootfs_steps(
+            &executable_binds,
+            rootfs_tree.as_ref().map(AsRawFd::as_raw_fd),
+            rootfs_dir.as_ref().map(AsRawFd::as_raw_fd),
+        )?;
+
+        let cgroup = self
+            .cgroup
+            .as_ref()
+            .map(|name| -> Result<_> {
+                let cgroup = Cgroup::create(CgroupBackend::detect()?, name)?;
+                if let Some(limit) = self.memory_limit {
+                    cgroup.set_memory_limit(limit)?;
+                }
+                if let Some(limit) = self.swap_limit {
+                    cgroup.set_swap_limit(limit)?;
+                }
+                if let Some(swappiness) = self.swappiness {
+                    cgroup.set_swappiness(swappiness)?;
+                }
+                if self.oom_group_kill {
+                    cgroup.set_oom_group()?;
+                }
+                if let Some(max) = self.pids_limit {
+                    cgroup.set_pids_limit(max)?;
+                }
+                if self.batch_mode {
+                    match cgroup.set_lowest_io_weight() {
+                        Err(e) if e.kind() == ErrorKind::Unsupported => {}
+                        result => result?,
+                    }
+                }
+                for (device, limit) in &self.io_limits {
+                    cgroup.set_io_limit(*device, limit)?;
+                }
+                Ok(cgroup)
+            })
+            .transpose()?;
+        // With v2 the child is cloned into the cgroup, v1 only has per-thread
+        // `tasks` files, written once the child exists.
+        let cgroup_fd = cgroup
+            .as_ref()
+            .filter(|cgroup| cgroup.backend() == CgroupBackend::V2)
+            .map(Cgroup::open)
+            .transpose()?;
+
+        let apparmor_profile = self
+            .apparmor_profile
+            .as_ref()
+            .map(|name| {
+                config_cstring(format!("exec {name}"), "AppArmor profile")
+            })
+            .transpose()?;
+        let selinux_label = self
+            .selinux_label
+            .as_ref()
+            .map(|label| config_cstring(label.as_str(), "SELinux label"))
+            .transpose()?;
+        let landlock_ruleset = self
+            .landlock
+            .as_ref()
+            .map(LandlockRuleset::create)
+            .transpose()?;
+        let seccomp_filter = match self.seccomp_notify.is_empty() {
+            true => None,
+            false => seccomp::notify_filter(&self.seccomp_notify),
+        };
+        let seccomp_profile = match self.seccomp_default {
+            true => seccomp::default_filter(),
+            false => None,
+        };
+
+        // (read end, write end) of the stdout and stderr pipes.
+        let output_pipes = match self.capture_output {
+            true => Some((pipe()?, pipe()?)),
+            false => None,
+        };
+
+        // Kept open across close_range, which only marks them close-on-exec,
+        // until they are duplicated onto the standard streams.
+        let stdin = match self.stdin_null {
+            true => Some(File::open("/dev/null")?),
+            false => None,
+        };
+        let stdout = self.stdout_file.as_ref().map(File::create).transpose()?;
+        let stderr = self.stderr_file.as_ref().map(File::create).transpose()?;
+        // (master, slave), the master is ours once the slave is the child's.
+        let pty = self.tty.then(pty::open).transpose()?;
+
+        let (mut parent_sock, mut child_sock) = UnixStream::pair()?;
+
+        // A zero timeout is rejected.
+        child_sock
+            .set_read_timeout(self.startup_timeout)
+            .map_err(|e| {
+                CuriumError::InvalidConfig(format!("startup timeout: {e}"))
+            })?;
+
+        let mut read_buf = [0];
+
+        // The child only waits for the parent when it has something to set
+        // up first, the socket is still used to report a failure.
+        let wake = self.waits_for_parent(cgroup.as_ref().map(Cgroup::backend));
+
+        // No fd of ours is above our soft limit, unless it was lowered.
+        let last_fd = match self.rlimit_nofile {
+            Some((soft, _)) => {
+                let mut own = libc::rlimit {
+                    rlim_cur: 0,
+                    rlim_max: 0,
+                };
+                if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut own) }
+                    != 0
+                {
+                    return Err(std::io::Error::last_os_error().into());
+                }
+                soft.max(own.rlim_cur)
+                    .saturating_sub(1)
+                    .min(u32::MAX as u64) as u32
+            }
+            None => u32::MAX,
+        };
+
+        // Our pid namespace for children, to go back to after the clone.
+        let own_pid_ns = match self.joined_namespaces {
+            Some((pidfd, joined)) if joined.contains(Namespaces::PID) => {
+                Some(enter_pid_namespace(pidfd)?)
+            }
+            _ => None,
+        };
+
+        // SAFETY: The child will only run async-signal-safe functions
+        // See: signal-safety(7)
+        let clone = unsafe {
+            let flags = self.raw_clone_flags();
+
+            match &cgroup_fd {
+                Some(fd) => clone3_into_cgroup(flags, fd.as_raw_fd()),
+                None => clone3(flags),
+            }
+        };
+        // The child is in the joined namespace, which it can't leave.
+        if let Some(ns) = &own_pid_ns
+            && !matches!(clone, Ok(CloneResult::Child))
+            && unsafe { libc::setns(ns.as_raw_fd(), libc::CLONE_NEWPID) } != 0
+        {
+            let err = std::io::Error::last_os_error();
+            // Our next children would be cloned into the joined namespace
+            // too, the container isn't left running for the caller either.
+            if let Ok(CloneResult::Parent(child)) = &clone
+                && let Some(pidfd) = child.pidfd
+            {
+                // SAFETY: clone3 was called with CLONE_PIDFD, so the pidfd is
+                // owned by us.
+                let pidfd = unsafe { OwnedFd::from_raw_fd(pidfd) };
+                let mut handle = ContainerHandle::new(*child, pidfd);
+                let _ = handle.signal(libc::SIGKILL);
+                let _ = handle.wait();
+            }
+            return Err(CuriumError::Io(err));
+        }
+        let clone = clone?;
+
+        match clone {
+            CloneResult::Parent(child) => {
+                event!(pid = child.pid, tid = child.tid, "cloned container");
+                drop(child_sock);
+
+                let Some(pidfd) = child.pidfd else {
+                    return Err(std::io::Error::other(
+                        "clone3 didn't return a pidfd",
+                    )
+                    .into());
+                };
+                // SAFETY: clone3 was called with CLONE_PIDFD, so the pidfd is
+                // owned by us.
+                let pidfd = unsafe { OwnedFd::from_raw_fd(pidfd) };
+                // Nothing would supervise the container if spawn failed, the
+                // handle kills it until `started` returns it.
+                let mut handle =
+                    ContainerHandle::new(child, pidfd).kill_on_drop(true);
+                if let Some(source) =
+                    cgroup.as_ref().and_then(|cgroup| cgroup.oom_source().ok())
+                {
+                    handle = handle.with_oom_source(source);
+                }
+                // Our write ends are dropped when this returns, so the reads
+                // see EOF once the container exits.
+                if let Some(((stdout, _), (stderr, _))) = output_pipes {
+                    handle = handle.with_output(stdout, stderr);
+                }
+                if let Some((master, _)) = pty {
+                    handle = handle.with_pty(master);
+                }
+
+                // The child can't do anything in its user namespace until its
+                // ids are mapped.
+                if wake {
+                    let woken = (|| -> std::io::Result<()> {
+                        write_id_maps(
+                            handle.pid(),
+                            &self.uid_maps,
+                            &self.gid_maps,
+                        )?;
+                        if let Some(cgroup) = &cgroup
+                            && cgroup.backend() == CgroupBackend::V1
+                        {
+                            cgroup.add_task(handle.pid())?;
+                        }
+                        if let Some((soft, hard)) = self.rlimit_nofile {
+                            set_rlimit_nofile(handle.pid(), soft, hard)?;
+                        }
+                        for callback in &mut self.after_clone {
+                            callback(&child)?;
+                        }
+                        parent_sock.write_all(&[1])
+                    })();
+                    if let Err(err) = woken {
+                        // The child exits quietly when it reads EOF instead
+                        // of the wake byte. A failure it sent means it gave
+                        // up first, e.g. after the startup timeout.
+                        let _ = parent_sock.shutdown(Shutdown::Write);
+                        let _ = handle.wait();
+                        let mut failure = [0; 8];
+                        return match parent_sock.read_exact(&mut failure) {
+                            Ok(()) => Err(setup_error(failure)),
+                            Err(_) => Err(err.into()),
+                        };
+                    }
+                    event!(pid = handle.pid(), "woke container");
+                }
+
+                for callback in &mut self.after_start {
+                    callback(&child)?;
+                }
+
+                let started = |mut handle: ContainerHandle| -> Result<_> {
+                    if let Some(path) = &self.pidfile {
+                        write_pidfile(path, handle.pid())?;
+                        handle = handle.with_pidfile(path.clone());
+                    }
+                    Ok(handle.kill_on_drop(false))
+                };
+
+                // The listener comes before the stop and exec, a byte without
+                // it is the first of a failure.
+                let mut failure = [0u8; 8];
+                let mut read = 0;
+                if seccomp_filter.is_some() {
+                    let mut byte = [0];
+                    match fd_channel::recv_fd(&parent_sock, &mut byte)? {
+                        (_, Some(listener)) => {
+                            handle = handle.with_seccomp_listener(listener);
+                        }
+                        (len, None) => {
+                            failure[0] = byte[0];
+                            read = len;
+                        }
+                    }
+                }
+
+                // The socket is still open while the child is stopped, a setup
+                // failure makes it exit instead. The handle keeps it to tell
+                // when the child executes.
+                if self.stop_before_exec
+                    && read == 0
+                    && handle.wait_stopped()?
+                {
+                    return started(handle.with_exec_sock(parent_sock));
+                }
+
+                // The child sends READY_TO_EXEC once it's set up and its end
+                // of the socket is closed on exec. Anything else means it
+                // failed and sent the errno, EOF before READY_TO_EXEC that it
+                // died during the setup.
+                match parent_sock.read_exact(&mut failure[read..]) {
+                    Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                        Err(CuriumError::SetupAborted(handle.wait()?))
+                    }
+                    Err(e) => Err(e.into()),
+                    Ok(()) if failure == READY_TO_EXEC => {
+                        match parent_sock.read_exact(&mut failure) {
+                            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                                started(handle)
+                            }
+                            Err(e) => Err(e.into()),
+                            Ok(()) => {
+                                handle.wait()?;
+                                Err(setup_error(failure))
+                            }
+                        }
+                    }
+                    Ok(()) => {
+                        handle.wait()?;
+                        Err(setup_error(failure))
+                    }
+                }
+            }
+            CloneResult::Child => {
+                std::panic::always_abort();
+
+                drop(parent_sock);
+                // Ensure all file descriptors are closed when executing the
+                // child process so they are not inherited by
+                // the container.
+                //
+                // Note: From what I see, Rust opens all files with the
+                // close-on-exec flag on linux, but doing this
+                // here is just in case a file was opened outside of the std
+                // lib.
+                let mut close_range =
+                    CloseRangeBuilder::new(3, last_fd).close_on_exec();
+                if self.unshare_fds {
+                    close_range = close_range.unshare_before_closing();
+                }
+                reported(
+                    &mut child_sock,
+                    SetupStep::CloseFds,
+                    close_range.close(),
+                );
+
+                match wake.then(|| child_sock.read(&mut read_buf)) {
+                    None | Some(Ok(1..)) => (),
+                    // The parent failed to set us up, and returns its own
+                    // error.
+                    Some(Ok(0)) => unsafe { libc::_exit(1) },
+                    Some(Err(e)) if e.kind() == ErrorKind::WouldBlock => {
+                        report_failure(
+                            &mut child_sock,
+                            SetupStep::WaitForParent,
+                            libc::ETIMEDOUT,
+                        )
+                    }
+                    Some(Err(e)) => report_failure(
+                        &mut child_sock,
+                        SetupStep::WaitForParent,
+                        e.raw_os_error().unwrap_or(0),
+                    ),
+                }
+
+                // setns moves the root and the working directory with the
+                // mount namespace.
+                if let Some((pidfd, joined)) = self.joined_namespaces
+                    && unsafe { libc::setns(pidfd, joined.bits()) } != 0
+                {
+                    report_failure(
+                        &mut child_sock,
+                        SetupStep::JoinNamespaces,
+                        errno(),
+                    );
+                }
+                // Now that the parent moved the child into its cgroup.
+                if self.namespaces().contains(Namespaces::CGROUP)
+                    && unsafe { libc::unshare(libc::CLONE_NEWCGROUP) } != 0
+                {
+                    report_failure(
+                        &mut child_sock,
+                        SetupStep::CgroupNamespace,
+                        errno(),
+                    );
+                }
+                if self.namespaces().contains(Namespaces::TIME)
+                    && let Err(errno) = enter_time_namespace()
+                {
+                    report_failure(
+                        &mut child_sock,
+                        SetupStep::TimeNamespace,
+                        errno,
+                    );
+                }
+
+                if let Some(hostname) = &hostname
+                    && unsafe {
+                        libc::sethostname(
+                            hostname.as_ptr(),
+                            hostname.as_bytes().len(),
+                        )
+                    } != 0
+                {
+                    report_failure(&mut child_sock, SetupStep::Uts, errno());
+                }
+                if let Some(domainname) = &domainname
+                    && unsafe {
+                        libc::setdomainname(
+                            domainname.as_ptr(),
+                            domainname.as_bytes().len(),
+                        )
+                    } != 0
+                {
+                    report_failure(&mut child_sock, SetupStep::Uts, errno());
+                }
+                if self.net_mode == NetMode::None
+                    && !self.joins(Namespaces::NET)
+                {
+                    reported(
+                        &mut child_sock,
+                        SetupStep::Loopback,
+                        net::loopback_up(),
+                    );
+                }
+                // /proc/sys/net shows the network namespace of the writer,
+                // the host's /proc will do.
+                for (path, value) in &net_sysctls {
+                    if let Err(err) = write_file(path, value.as_bytes()) {
+                        report_failure(
+                            &mut child_sock,
+                            SetupStep::NetSysctl,
+                            err.raw_os_error().unwrap_or(0),
+                        );
+                    }
+                }
+
+                match self.mount_namespace_fd {
+                    // setns moves the root and the working directory to the
+                    // root of the namespace.
+                    Some(fd) => {
+                        if unsafe { libc::setns(fd, libc::CLONE_NEWNS) } != 0 {
+                            report_failure(
+                                &mut child_sock,
+                                SetupStep::MountNamespace,
+                                errno(),
+                            );
+                        }
+                    }
+                    None if self.joins(Namespaces::MOUNT) => (),
+                    None => {
+                        for step in &rootfs_steps {
+                            if let Err((step, errno)) = step.run() {
+                                report_failure(&mut child_sock, step, errno);
+                            }
+                        }
+                    }
+                }
+
+                // A new session, so the tty can be its controlling terminal.
+                if let Some((_, slave)) = &pty {
+                    let slave = slave.as_raw_fd();
+                    if unsafe { libc::setsid() } < 0
+                        || unsafe { libc::ioctl(slave, libc::TIOCSCTTY, 0) } < 0
+                        || (0..3).any(|fd| unsafe { libc::dup2(slave, fd) } < 0)
+                    {
+                        report_failure(
+                            &mut child_sock,
+                            SetupStep::StandardStreams,
+                            errno(),
+                        );
+                    }
+                }
+                // dup2 clears close-on-exec on the new descriptors.
+                if let Some(((_, stdout), (_, stderr))) = &output_pipes
+                    && (unsafe { libc::dup2(stdout.as_raw_fd(), 1) } < 0
+                        || unsafe { libc::dup2(stderr.as_raw_fd(), 2) } < 0)
+                {
+                    report_failure(
+                        &mut child_sock,
+                        SetupStep::StandardStreams,
+                        errno(),
+                    );
+                }
+                for (file, fd) in [(&stdin, 0), (&stdout, 1), (&stderr, 2)] {
+                    if let Some(file) = file
+                        && unsafe { libc::dup2(file.as_raw_fd(), fd) } < 0
+                    {
+                        report_failure(
+                            &mut child_sock,
+                            SetupStep::StandardStreams,
+                            errno(),
+                        );
+                    }
+                }
+
+                if let Some(fd) = self.working_dir_fd
+                    && unsafe { libc::fchdir(fd) } != 0
+                {
+                    report_failure(
+                        &mut child_sock,
+                        SetupStep::WorkingDir,
+                        errno(),
+                    );
+                }
+
+                if self.batch_mode {
+                    let param = libc::sched_param { sched_priority: 0 };
+                    if unsafe {
+                        libc::sched_setscheduler(0, libc::SCHED_IDLE, &param)
+                    } != 0
+                    {
+                        report_failure(
+                            &mut child_sock,
+                            SetupStep::Scheduler,
+                            errno(),
+                        );
+                    }
+                }
+
+                if let Some(mask) = self.umask {
+                    unsafe { libc::umask(mask) };
+                }
+                // The personality is kept across exec, ADDR_NO_RANDOMIZE is
+                // applied by it.
+                if self.disable_aslr {
+                    let persona = unsafe { libc::personality(0xffff_ffff) };
+                    if persona < 0
+                        || unsafe {
+                            libc::personality(
+                                (persona | libc::ADDR_NO_RANDOMIZE) as _,
+                            )
+                        } < 0
+                    {
+                        report_failure(
+                            &mut child_sock,
+                            SetupStep::Personality,
+                            errno(),
+                        );
+                    }
+                }
+
+                // Before Landlock, which may deny writing to /proc.
+                if let Some(profile) = &apparmor_profile {
+                    reported(
+                        &mut child_sock,
+                        SetupStep::Lsm,
+                        lsm::write_attr(&lsm::APPARMOR_EXEC_ATTR, profile),
+                    );
+                }
+                if let Some(label) = &selinux_label {
+                    reported(
+                        &mut child_sock,
+                        SetupStep::Lsm,
+                        lsm::write_attr(&lsm::SELINUX_EXEC_ATTR, label),
+                    );
+                }
+
+                // The ruleset fd survives close_range because it's only
+                // marked close-on-exec.
+                if let Some(ruleset) = &landlock_ruleset {
+                    reported(
+                        &mut child_sock,
+                        SetupStep::Landlock,
+                        landlock::restrict_self(ruleset.as_raw_fd()),
+                    );
+                }
+
+                if !self.dropped_bounding_caps.is_empty() {
+                    reported(
+                        &mut child_sock,
+                        SetupStep::Capabilities,
+                        caps::drop_bounding(&self.dropped_bounding_caps),
+                    );
+                }
+                if !self.ambient_capabilities.is_empty() {
+                    reported(
+                        &mut child_sock,
+                        SetupStep::Capabilities,
+                        caps::raise_ambient(&self.ambient_capabilities),
+                    );
+                }
+
+                if let Some(filter) = &seccomp_profile
+                    && let Err(err) = seccomp::load(filter)
+                {
+                    report_failure(
+                        &mut child_sock,
+                        SetupStep::Seccomp,
+                        err.raw_os_error().unwrap_or(0),
+                    );
+                }
+                // Last, the syscalls of the filter would wait for a parent
+                // still waiting for the listener.
+                if let Some(filter) = &seccomp_filter {
+                    let sent = seccomp::load_with_listener(filter).and_then(
+                        |listener| fd_channel::send_fd(&child_sock, &listener),
+                    );
+                    if let Err(err) = sent {
+                        report_failure(
+                            &mut child_sock,
+                            SetupStep::Seccomp,
+                            err.raw_os_error().unwrap_or(0),
+                        );
+                    }
+                }
+
+                if self.stop_before_exec {
+                    if unsafe { libc::ptrace(libc::PTRACE_TRACEME, 0, 0, 0) }
+                        != 0
+                    {
+                        report_failure(
+                            &mut child_sock,
+                            SetupStep::StopBeforeExec,
+                            errno(),
+                        );
+                    }
+                    // As the init of its pid namespace, the child only gets
+                    // its own SIGSTOP because it's traced.
+                    unsafe { libc::raise(libc::SIGSTOP) };
+                }
+
+                // The parent may have dropped the handle of the stopped child.
+                unsafe {
+                    libc::send(
+                        child_sock.as_raw_fd(),
+                        READY_TO_EXEC.as_ptr().cast(),
+                        READY_TO_EXEC.len(),
+                        libc::MSG_NOSIGNAL,
+                    )
+                };
+                let Err(err) =
+                    self.do_exec(&cmd, &env, argv.as_ptr(), envp.as_ptr());
+                report_failure(
+                    &mut child_sock,
+                    SetupStep::Exec,
+                    err.raw_os_error().unwrap_or(0),
+                );
+            }
+        }
+    }
+
+    fn do_exec(
+        &self,
+        cmd: &CStr,
+        env: &[CString],
+        argv: *const *const i8,
+        envp: *const *const i8,
+    ) -> std::result::Result<!, std::io::Error> {
+        if !self.search_path || cmd.to_bytes().contains(&b'/') {
+            unsafe { libc::execve(cmd.as_ptr(), argv, envp) };
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let path = env
+            .iter()
+            .find(|entry| env_key(entry.as_bytes()) == b"PATH")
+            .map(|entry| &entry.as_bytes()[b"PATH=".len()..])
+            .unwrap_or(b"/bin:/usr/bin");
+
+        // Like execvp, report EACCES if any candidate was found but couldn't
+        // be executed.
+        let mut err = std::io::Error::from_raw_os_error(libc::ENOENT);
+        for dir in path.split(|&b| b == b':') {
+            let mut candidate = FixedBufferWriter::<4096>::new();
+            let dir = if dir.is_empty() { b"." } else { dir };
+            if candidate.write_all(dir).is_err()
+                || candidate.write_all(b"/").is_err()
+                || candidate.write_all(cmd.to_bytes()).is_err()
+                || candidate.write_all(&[0]).is_err()
+            {
+                continue;
+            }
+
+            unsafe {
+                libc::execve(candidate.buffer().as_ptr().cast(), argv, envp)
+            };
+            let last = std::io::Error::last_os_error();
+            if last.raw_os_error() == Some(libc::EACCES) {
+                err = last;
+            }
+        }
+        Err(err)
+    }
+}
+
+/// A step of the child's mount setup in a new mount namespace, from the
+/// propagation of `/` to the read-only root. The parent prepares them before
+/// the clone, the child takes them in order and [`Container::dry_run`] lists
+/// them.
+enum RootfsStep {
+    /// Make every mount of the namespace private, so the new root mount is
+    /// not shared with the host. See: https://lwn.net/Articles/689856/
+    MakePrivate,
+    /// Bind the rootfs over itself, to make it a mount.
+    BindRoot(CString),
+    /// Attach the `tree` cloned from the rootfs fd at `target`.
+    AttachTree {
+        tree: RawFd,
+        target: CString,
+    },
+    /// Replace the rootfs fd `dir` with the rootfs mount of the namespace.
+    ReplaceRootDir {
+        root: CString,
+        dir: RawFd,
+    },
+    Mount(PreparedMount),
+    /// Create the directory `path` with the `mode`, unless it exists.
+    Mkdir {
+        path: CString,
+        mode: libc::mode_t,
+    },
+    /// Check that the mount point of `step` exists.
+    MountPoint {
+        path: CString,
+        step: SetupStep,
+    },
+    Proc {
+        target: CString,
+        data: Option<CString>,
+    },
+    /// Bind the host's /proc with its submounts, those of a more privileged
+    /// mount namespace can't be left out.
+    BindProc(CString),
+    /// Bind `path` read-only over itself, if it exists.
+    Readonly(CString),
+    Mask(CString),
+    Sysfs(CString),
+    /// Bind the host's /sys read-only, only the owner of the network
+    /// namespace can mount a sysfs, which shows its interfaces.
+    BindSys(CString),
+    /// Mount the cgroup2 of the new cgroup namespace on the path.
+    Cgroupfs(CString),
+    /// Create the file `target` and bind `source` read-only over it.
+    BindFile {
+        source: CString,
+        target: CString,
+    },
+    WriteFile {
+        path: CString,
+        contents: Vec<u8>,
+    },
+    Chown {
+        path: CString,
+        uid: u32,
+    },
+    RemountRoot {
+        root: CString,
+        mode: AtimeMode,
+    },
+    Chdir(CString),
+    Fchdir(RawFd),
+    Chroot(CString),
+    /// pivot_root(2) into `new_root`, with the old root on the new directory
+    /// `put_old` (`put_old_in_root` once pivoted), then detach it.
+    PivotRoot {
+        new_root: CString,
+        put_old: CString,
+        put_old_in_root: CString,
+    },
+    /// pivot_root(".", ".") stacks the old root on top of the new one, so it
+    /// can be unmounted without a put_old directory. See: pivot_root(2)
+    PivotRootHere,
+    ReadonlyRoot,
+}
+
+impl RootfsStep {
+    /// Take the step, returning the failed setup step with the errno.
+    ///
+    /// # Signal Safety
+    /// This function is signal safe.
+    fn run(&self) -> std::result::Result<(), (SetupStep, i32)> {
+        let failed = |step| {
+            move |err: std::io::Error| (step, err.raw_os_error().unwrap_or(0))
+        };
+        let mounted = failed(SetupStep::Mount);
+        let entered = |ret| match ret {
+            0 => Ok(()),
+            _ => Err((SetupStep::EnterRoot, errno())),
+        };
+        match self {
+            Self::MakePrivate => Mount::new(c"/")
+                .set_propagation(MountPropagation::Private)
+                .recursive()
+                .mount()
+                .map_err(mounted),
+            Self::BindRoot(root) => {
+                Mount::new(root).bind(root).mount().map_err(mounted)
+            }
+            Self::AttachTree { tree, target } => {
+                attach_tree(*tree, target).map_err(mounted)
+            }
+            Self::ReplaceRootDir { root, dir } => {
+                let root = open_dir(root).map_err(mounted)?;
+                match unsafe {
+                    libc::dup3(root.as_raw_fd(), *dir, libc::O_CLOEXEC)
+                } {
+                    0.. => Ok(()),
+                    _ => Err((SetupStep::Mount, errno())),
+                }
+            }
+            Self::Mount(prepared) => prepared.mount().map_err(mounted),
+            Self::Mkdir { path, mode } => {
+                mkdir_exists_ok(path, *mode).map_err(mounted)
+            }
+            Self::MountPoint { path, step } => {
+                access(path).map_err(|errno| (*step, errno))
+            }
+            Self::Proc { target, data } => {
+                let mut mount = Mount::new(target)
+                    .no_dev()
+                    .no_suid()
+                    .no_exec()
+                    .create(c"proc", c"proc");
+                if let Some(data) = data {
+                    mount = mount.data(data);
+                }
+                mount.mount().map_err(mounted)
+            }
+            Self::BindProc(target) => Mount::new(target)
+                .bind(c"/proc")
+                .recursive()
+                .mount()
+                .map_err(mounted),
+            Self::Readonly(path) => readonly_path(path).map_err(mounted),
+            Self::Mask(path) => mask_path(path).map_err(mounted),
+            Self::Sysfs(target) => Mount::new(target)
+                .readonly()
+                .no_dev()
+                .no_suid()
+                .no_exec()
+                .create(c"sysfs", c"sys")
+                .mount()
+                .map_err(mounted),
+            Self::BindSys(target) => {
+                Mount::new(target).rbind_readonly(c"/sys").map_err(mounted)
+            }
+            // Over the host's hierarchies of a bound /sys.
+            Self::Cgroupfs(target) => match in_cgroup_root() {
+                true => Mount::new(target)
+                    .readonly()
+                    .no_dev()
+                    .no_suid()
+                    .no_exec()
+                    .create(c"cgroup2", c"cgroup2")
+                    .mount()
+                    .map_err(failed(SetupStep::CgroupNamespace)),
+                false => Err((SetupStep::CgroupNamespace, libc::EINVAL)),
+            },
+            Self::BindFile { source, target } => create_file(target)
+                .and_then(|_| {
+                    Mount::new(target).readonly().bind(source).mount()
+                })
+                .map_err(mounted),
+            Self::WriteFile { path, contents } => {
+                write_file(path, contents).map_err(mounted)
+            }
+            Self::Chown { path, uid } => {
+                match unsafe { libc::chown(path.as_ptr(), *uid, u32::MAX) } {
+                    0 => Ok(()),
+                    _ => Err((SetupStep::Mount, errno())),
+                }
+            }
+            Self::RemountRoot { root, mode } => Mount::new(root)
+                .remount(*mode != AtimeMode::Lazytime)
+                .atime(*mode)
+                .mount()
+                .map_err(mounted),
+            Self::Chdir(path) => entered(unsafe { libc::chdir(path.as_ptr()) }),
+            Self::Fchdir(fd) => entered(unsafe { libc::fchdir(*fd) }),
+            Self::Chroot(path) => {
+                entered(unsafe { libc::chroot(path.as_ptr()) })
+            }
+            Self::PivotRoot {
+                new_root,
+                put_old,
+                put_old_in_root,
+            } => pivot_and_detach(new_root, put_old, put_old_in_root),
+            Self::PivotRootHere => pivot_root(c".", c".")
+                .and_then(|_| {
+                    umount2(c".", UmountFlags::DETACH)
+                        .map_err(std::io::Error::from)
+                })
+                .map_err(failed(SetupStep::PivotRoot)),
+            // After pivot_root, which creates the old root directory in the
+            // new root.
+            Self::ReadonlyRoot => {
+                Mount::new(c"/").make_readonly().map_err(mounted)
+            }
+        }
+    }
+
+    /// Append the operations of the step to `plan`.
+    fn describe(&self, plan: &mut Vec<Operation>) {
+        let lossy = |s: &CStr| s.to_string_lossy().into_owned();
+        let mount = |source: &str,
+                     target: &CStr,
+                     fs_type: Option<&str>,
+                     options: &[&str]| {
+            Operation::Mount {
+                source: source.to_owned(),
+                target: lossy(target),
+                fs_type: fs_type.map(str::to_owned),
+                options: options
+                    .iter()
+                    .map(|&option| option.to_owned())
+                    .collect(),
+            }
+        };
+        let hardened = ["nodev", "nosuid", "noexec"];
+        match self {
+            Self::MakePrivate => {
+                plan.push(mount("none", c"/", None, &["rec", "private"]))
+            }
+            Self::BindRoot(root) => {
+                plan.push(mount(&lossy(root), root, None, &["bind"]))
+            }
+            Self::AttachTree { tree, target } => plan.push(mount(
+                &format!("fd {tree}"),
+                target,
+                None,
+                &["rbind", "move"],
+            )),
+            Self::ReplaceRootDir { .. } | Self::MountPoint { .. } => (),
+            Self::Mount(prepared) => plan.push(prepared.operation()),
+            Self::Mkdir { path, .. } => {
+                plan.push(Operation::Mkdir { path: lossy(path) })
+            }
+            Self::Proc { target, data } => {
+                let data = data.as_deref().map(lossy);
+                let mut options = hardened.to_vec();
+                options.extend(data.as_deref());
+                plan.push(mount("proc", target, Some("proc"), &options))
+            }
+            Self::BindProc(target) => {
+                plan.push(mount("/proc", target, None, &["rbind"]))
+            }
+            Self::Readonly(path) => plan.push(mount(
+                &lossy(path),
+                path,
+                None,
+                &["bind", "ro", "nodev", "nosuid", "noexec"],
+            )),
+            Self::Mask(path) => {
+                plan.push(Operation::Mask { path: lossy(path) })
+            }
+            Self::Sysfs(target) => plan.push(mount(
+                "sys",
+                target,
+                Some("sysfs"),
+                &["ro", "nodev", "nosuid", "noexec"],
+            )),
+            Self::BindSys(target) => plan.push(mount(
+                "/sys",
+                target,
+                None,
+                &["rbind", "ro", "private"],
+            )),
+            Self::Cgroupfs(target) => plan.push(mount(
+                "cgroup2",
+                target,
+                Some("cgroup2"),
+                &["ro", "nodev", "nosuid", "noexec"],
+            )),
+            Self::BindFile { source, target } => {
+                plan.push(Operation::WriteFile {
+                    path: lossy(target),
+                });
+                plan.push(mount(&lossy(source), target, None, &["bind", "ro"]));
+            }
+            Self::WriteFile { path, .. } => {
+                plan.push(Operation::WriteFile { path: lossy(path) })
+            }
+            Self::Chown { path, uid } => plan.push(Operation::Chown {
+                path: lossy(path),
+                uid: *uid,
+            }),
+            Self::RemountRoot { root, mode } => {
+                let atime = format!("{mode:?}").to_lowercase();
+                let mut options = vec!["remount", &atime];
+                if *mode != AtimeMode::Lazytime {
+                    options.push("bind");
+                }
+                plan.push(mount(&lossy(root), root, None, &options));
+            }
+            Self::Chdir(path) => {
+                plan.push(Operation::Chdir { path: lossy(path) })
+            }
+            Self::Fchdir(fd) => plan.push(Operation::Fchdir { fd: *fd }),
+            Self::Chroot(path) => {
+                plan.push(Operation::Chroot { path: lossy(path) })
+            }
+            Self::PivotRoot {
+                new_root,
+                put_old,
+                put_old_in_root,
+            } => {
+                plan.push(Operation::Mkdir {
+                    path: lossy(put_old),
+                });
+                plan.push(Operation::PivotRoot {
+                    new_root: lossy(new_root),
+                    put_old: lossy(put_old),
+                });
+                plan.push(Operation::Unmount {
+                    target: lossy(put_old_in_root),
+                });
+                plan.push(Operation::Rmdir {
+                    path: lossy(put_old_in_root),
+                });
+            }
+            Self::PivotRootHere => {
+                plan.push(Operation::PivotRoot {
+                    new_root: ".".into(),
+                    put_old: ".".into(),
+                });
+                plan.push(Operation::Unmount { target: ".".into() });
+            }
+            Self::ReadonlyRoot => {
+                plan.push(mount("/", c"/", None, &["setattr", "ro"]))
+            }
+        }
+    }
+}
+
+/// Create an empty file at `path` if it doesn't exist, to be used as a mount
+/// point.
+///
+/// # Signal Safety
+/// This function is signal safe.
+fn create_file(path: &CStr) -> std::result::Result<(), std::io::Error> {
+    let fd = unsafe {
+        libc::open(
+            path.as_ptr(),
+            libc::O_CREAT | libc::O_WRONLY | libc::O_CLOEXEC,
+            0o644,
+        )
+    };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    unsafe { libc::close(fd) };
+    Ok(())
+}
+
+/// Make the pid namespace of the process referred to by `pidfd` the one of
+/// the next children of this thread, returning the current one.
+fn enter_pid_namespace(
+    pidfd: RawFd,
+) -> std::result::Result<OwnedFd, std::io::Error> {
+    let own = File::open("/proc/thread-self/ns/pid_for_children")?;
+    if unsafe { libc::setns(pidfd, libc::CLONE_NEWPID) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(own.into())
+}
+
+/// Replace the contents of the file at `path`, creating it if it doesn't
+/// exist.
+///
+/// # Signal Safety
+/// This function is signal safe.
+fn write_file(
+    path: &CStr,
+    contents: &[u8],
+) -> std::result::Result<(), std::io::Error> {
+    let fd = unsafe {
+        libc::open(
+            path.as_ptr(),
+            libc::O_CREAT | libc::O_TRUNC | libc::O_WRONLY | libc::O_CLOEXEC,
+            0o644,
+        )
+    };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let n =
+        unsafe { libc::write(fd, contents.as_ptr().cast(), contents.len()) };
+    let result = match n {
+        n if n < 0 => Err(std::io::Error::last_os_error()),
+        _ => Ok(()),
+    };
+    unsafe { libc::close(fd) };
+    result
+}
+
+/// Write `pid` to a temporary file next to `path` and rename it to `path`.
+fn write_pidfile(
+    path: &Path,
+    pid: i64,
+) -> std::result::Result<(), std::io::Error> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".{}.tmp", std::process::id()));
+
+    std::fs::write(&tmp, format!("{pid}\n"))?;
+    std::fs::rename(&tmp, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&tmp);
+    })
+}
+
+/// Convert the configured `what` for the child, a null byte in it is a
+/// configuration error.
+fn config_cstring<S: Into<Vec<u8>>>(s: S, what: &str) -> Result<CString> {
+    CString::new(s)
+        .map_err(|_| CuriumError::InvalidConfig(format!("Null in the {what}")))
+}
+
+fn cstrings<I, C>(args: I) -> Vec<CString>
+where
+    I: IntoIterator<Item = C>,
+    C: AsRef<OsStr>,
+{
+    args.into_iter()
+        .map(|arg| {
+            CString::new(arg.as_ref().as_encoded_bytes())
+                .expect("Null in the arg")
+        })
+        .collect()
+}
+
+/// The error of a `failure` sent with [`report_failure`].
+fn setup_error(failure: [u8; 8]) -> CuriumError {
+    let [s0, s1, s2, s3, e0, e1, e2, e3] = failure;
+    let Some(step) = SetupStep::from_u32(u32::from_ne_bytes([s0, s1, s2, s3]))
+    else {
+        return std::io::Error::new(
+            ErrorKind::InvalidData,
+            "the child sent an unknown setup step",
+        )
+        .into();
+    };
+    let err =
+        std::io::Error::from_raw_os_error(i32::from_ne_bytes([e0, e1, e2, e3]));
+    match (step, err.kind()) {
+        (SetupStep::Exec, ErrorKind::NotFound) => CuriumError::CommandNotFound,
+        (SetupStep::Exec, _) => CuriumError::ExecFailed(err),
+        (SetupStep::WaitForParent, ErrorKind::TimedOut) => {
+            CuriumError::StartupTimedOut
+        }
+        (step, _) => CuriumError::SetupFailed { step, err },
+    }
+}
+
+/// Send the failed setup `step` and `errno` to the parent and exit, there's
+/// nothing else to do if sending fails too.
+///
+/// # Signal Safety
+/// This function is signal safe.
+fn report_failure(sock: &mut UnixStream, step: SetupStep, errno: i32) -> ! {
+    let mut failure = [0u8; 8];
+    failure[..4].copy_from_slice(&(step as u32).to_ne_bytes());
+    failure[4..].copy_from_slice(&errno.to_ne_bytes());
+
+    let _ = sock.write_all(&failure);
+    unsafe { libc::_exit(1) };
+}
+
+/// The value of `result`, or report its error as the failure of `step`.
+///
+/// # Signal Safety
+/// This function is signal safe.
+fn reported<T>(
+    sock: &mut UnixStream,
+    step: SetupStep,
+    result: std::io::Result<T>,
+) -> T {
+    match result {
+        Ok(value) => value,
+        Err(err) => report_failure(sock, step, err.raw_os_error().unwrap_or(0)),
+    }
+}
+
+/// pivot_root(2) into `new_root`, moving the old root to the new directory
+/// `put_old` (`put_old_in_root` once pivoted) and detaching it. The failed
+/// step is returned with the errno.
+///
+/// # Signal Safety
+/// This function is signal safe.
+fn pivot_and_detach(
+    new_root: &CStr,
+    put_old: &CStr,
+    put_old_in_root: &CStr,
+) -> std::result::Result<(), (SetupStep, i32)> {
+    // No reuse of an existing directory, it may already be a mount point.
+    if unsafe { libc::mkdir(put_old.as_ptr(), 0o700) } != 0 {
+        return Err((SetupStep::OldRootDir, errno()));
+    }
+    if let Err(err) = pivot_root(new_root, put_old) {
+        unsafe { libc::rmdir(put_old.as_ptr()) };
+        return Err((SetupStep::PivotRoot, err.raw_os_error().unwrap_or(0)));
+    }
+    // The host's root must not stay reachable, a failed unmount is fatal.
+    if unsafe { libc::umount2(put_old_in_root.as_ptr(), libc::MNT_DETACH) } != 0
+    {
+        return Err((SetupStep::PivotRoot, errno()));
+    }
+    if unsafe { libc::rmdir(put_old_in_root.as_ptr()) } != 0 {
+        return Err((SetupStep::OldRootDir, errno()));
+    }
+    Ok(())
+}
+
+/// The errno of the last failed call of the calling thread.
+///
+/// # Signal Safety
+/// This function is signal safe.
+fn errno() -> i32 {
+    std::io::Error::last_os_error().raw_os_error().unwrap_or(0)
+}
+
+/// Unshare a time namespace and move its monotonic and boot clocks back to
+/// zero, to be entered on exec. Returns the errno on failure.
+///
+/// # Signal Safety
+/// This function is signal safe.
+fn enter_time_namespace() -> std::result::Result<(), i32> {
+    if unsafe { libc::unshare(libc::CLONE_NEWTIME) } != 0 {
+        return Err(errno());
+    }
+    // Whole seconds, so the clocks start at the fraction and never go
+    // negative.
+    let mut offsets = FixedBufferWriter::<64>::new();
+    for (clock, name) in [
+        (libc::CLOCK_MONOTONIC, "monotonic"),
+        (libc::CLOCK_BOOTTIME, "boottime"),
+    ] {
+        let mut now: libc::timespec = unsafe { std::mem::zeroed() };
+        if unsafe { libc::clock_gettime(clock, &mut now) } != 0 {
+            return Err(errno());
+        }
+        writeln!(offsets, "{name} {} 0", -now.tv_sec)
+            .map_err(|_| libc::E2BIG)?;
+    }
+
+    let fd = unsafe {
+        libc::open(
+            c"/proc/self/timens_offsets".as_ptr(),
+            libc::O_WRONLY | libc::O_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return Err(errno());
+    }
+    let buf = offsets.buffer();
+    let written = unsafe { libc::write(fd, buf.as_ptr().cast(), buf.len()) };
+    let err = errno();
+    unsafe { libc::close(fd) };
+    match written == buf.len() as isize {
+        true => Ok(()),
+        false => Err(err),
+    }
+}
+
+/// Fail unless we can read `path`, see access(2).
+fn readable(path: &Path) -> std::io::Result<()> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    if unsafe { libc::access(path.as_ptr(), libc::R_OK) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Resolve `cmd` on the host, a command without a `/` is looked up in the
+/// host's `PATH` like execvp(3).
+fn host_executable(cmd: &CStr) -> Result<PathBuf> {
+    let cmd = Path::new(OsStr::from_bytes(cmd.to_bytes()));
+    if cmd.as_os_str().as_bytes().contains(&b'/') {
+        return Ok(std::path::absolute(cmd)?);
+    }
+
+    let path = std::env::var_os("PATH").unwrap_or("/bin:/usr/bin".into());
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(cmd))
+        .find(|candidate| {
+            candidate
+                .metadata()
+                .is_ok_and(|meta| meta.is_file() && meta.mode() & 0o111 != 0)
+        })
+        .map(|candidate| std::path::absolute(candidate).map_err(Into::into))
+        .unwrap_or_else(|| {
+            Err(CuriumError::InvalidConfig(format!(
+                "{} isn't in the host's PATH",
+                cmd.display()
+            )))
+        })
+}
+
+/// Create the directory `path` with the `mode`, unless it exists.
+///
+/// # Signal Safety
+/// This function is signal safe.
+fn mkdir_exists_ok(path: &CStr, mode: libc::mode_t) -> std::io::Result<()> {
+    if unsafe { libc::mkdir(path.as_ptr(), mode) } != 0 {
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::EEXIST) {
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// The tmpfs of [`Container::run_tmpfs`].
+fn run_tmpfs() -> MountSpec {
+    MountSpec::Tmpfs {
+        dst: "/run".into(),
+        size: None,
+        mode: Some(0o755),
+    }
+}
+
+/// Check that `path` exists, returning the errno otherwise.
+///
+/// # Signal Safety
+/// This function is signal safe.
+fn access(path: &CStr) -> std::result::Result<(), i32> {
+    let ret = unsafe {
+        libc::faccessat(libc::AT_FDCWD, path.as_ptr(), libc::F_OK, 0)
+    };
+    match ret {
+        0 => Ok(()),
+        _ => Err(errno()),
+    }
+}
+
+/// Set the `RLIMIT_NOFILE` of the process `pid`.
+fn set_rlimit_nofile(pid: i64, soft: u64, hard: u64) -> std::io::Result<()> {
+    let limit = libc::rlimit {
+        rlim_cur: soft,
+        rlim_max: hard,
+    };
+    let ret = unsafe {
+        libc::prlimit(
+            pid as libc::pid_t,
+            libc::RLIMIT_NOFILE,
+            &limit,
+            std::ptr::null_mut(),
+        )
+    };
+    match ret {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+/// Does `/proc/self/cgroup` show the calling process at the root of every
+/// hierarchy, as it is right after creating a cgroup namespace?
+///
+/// # Signal Safety
+/// This function is signal safe.
+fn in_cgroup_root() -> bool {
+    let fd = unsafe {
+        libc::open(
+            c"/proc/self/cgroup".as_ptr(),
+            libc::O_RDONLY | libc::O_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return false;
+    }
+    // A line per hierarchy, a few hundred bytes at the root.
+    let mut buf = [0u8; 4096];
+    let mut len = 0;
+    while len < buf.len() {
+        let read = unsafe {
+            libc::read(fd, buf[len..].as_mut_ptr().cast(), buf.len() - len)
+        };
+        if read <= 0 {
+            break;
+        }
+        len += read as usize;
+    }
+    unsafe { libc::close(fd) };
+    len < buf.len() && cgroups_are_root(&buf[..len])
+}
+
+/// Is the path of every line (`id:controllers:path`) of a `/proc/*/cgroup`
+/// file `/`?
+fn cgroups_are_root(contents: &[u8]) -> bool {
+    contents
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.is_empty())
+        .all(|line| line.splitn(3, |&b| b == b':').nth(2) == Some(b"/"))
+}
+
+/// Open the directory `path` with `O_PATH`.
+///
+/// # Signal Safety
+/// This function is signal safe.
+fn open_dir(path: &CStr) -> std::result::Result<OwnedFd, std::io::Error> {
+    let fd = unsafe {
+        libc::open(
+            path.as_ptr(),
+            libc::O_PATH | libc::O_DIRECTORY | libc::O_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: open returned a new file descriptor.
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Create a close-on-exec pipe, returning the read and write ends.
+fn pipe() -> std::result::Result<(OwnedFd, OwnedFd), std::io::Error> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: pipe2 returned two new file descriptors.
+    Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+}
+
+/// Bind `path` read-only over itself, skipping it if it doesn't exist.
+///
+/// # Signal Safety
+/// This function is signal safe.
+fn readonly_path(path: &CStr) -> std::io::Result<()> {
+    match Mount::new(path)
+        .readonly()
+        .no_dev()
+        .no_suid()
+        .no_exec()
+        .bind(path)
+        .mount()
+    {
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Hide `path`: a file is covered by `/dev/null` and a directory by an empty
+/// read-only tmpfs. A missing path is skipped.
+///
+/// # Signal Safety
+/// This function is signal safe.
+fn mask_path(path: &CStr) -> std::io::Result<()> {
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::stat(path.as_ptr(), &mut stat) } != 0 {
+        return Ok(());
+    }
+
+    match stat.st_mode & libc::S_IFMT {
+        libc::S_IFDIR => Mount::new(path)
+            .readonly()
+            .no_dev()
+            .no_suid()
+            .no_exec()
+            .create(c"tmpfs", c"tmpfs")
+            .mount(),
+        _ => Mount::new(path).bind(c"/dev/null").mount(),
+    }
+}
+
+/// Format a `KEY=VALUE` environment entry.
+fn env_entry(key: OsString, value: OsString) -> Option<CString> {
+    let mut entry = key;
+    entry.push("=");
+    entry.push(value);
+
+    CString::new(entry.into_encoded_bytes()).ok()
+}
+
+/// Return the key of a `KEY=VALUE` environment entry.
+fn env_key(entry: &[u8]) -> &[u8] {
+    match entry.iter().position(|&b| b == b'=') {
+        Some(pos) => &entry[..pos],
+        None => entry,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write_env_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir()
+            .join(format!("curium-{name}-{}.env", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn namespaces() {
+        let container = Container::new("/".into(), "/bin/true");
+        assert_eq!(
+            container.namespaces(),
+            Namespaces::IPC
+                | Namespaces::UTS
+                | Namespaces::MOUNT
+                | Namespaces::PID
+                | Namespaces::NET
+        );
+
+        let container = container.uid_map(0, 1000, 1).net_mode(NetMode::Host);
+        assert!(container.namespaces().contains(Namespaces::USER));
+        assert!(!container.namespaces().contains(Namespaces::NET));
+    }
+
+    #[test]
+    fn raw_clone_flags() {
+        let container = Container::new("/".into(), "/bin/true")
+            .clone_flags(libc::CLONE_NEWCGROUP as u64)
+            .remove_clone_flags(libc::CLONE_NEWIPC as u64);
+        let flags = container.raw_clone_flags();
+
+        assert_ne!(flags & libc::CLONE_NEWCGROUP as u64, 0);
+        assert_eq!(flags & libc::CLONE_NEWIPC as u64, 0);
+        assert_ne!(flags & libc::CLONE_PIDFD as u64, 0);
+
+        // The id maps would be written for the parent's user namespace.
+        let container = Container::new("/".into(), "/bin/true")
+            .uid_map(0, 1000, 1)
+            .remove_clone_flags(libc::CLONE_NEWUSER as u64);
+        assert!(matches!(
+            container.check(),
+            Err(CuriumError::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn memory_limit_with_swap() {
+        let plan = Container::new("/".into(), "/bin/true")
+            .cgroup("curium-test")
+            .memory_limit_with_swap(1 << 20, 0)
+            .dry_run()
+            .unwrap();
+        assert!(matches!(
+            &plan[0],
+            Operation::CreateCgroup { limits, .. } if limits[..] == [
+                ("memory_limit", "1048576".to_owned()),
+                ("swap_limit", "0".to_owned()),
+            ]
+        ));
+    }
+
+    #[test]
+    fn validate_returns_every_problem() {
+        assert!(Container::new("/".into(), "/bin/true").validate().is_ok());
+
+        let problems = Container::new("/nonexistent".into(), "/bin/true")
+            .memory_limit(1 << 20)
+            .pids_limit(16)
+            .extra_mounts(vec![MountSpec::Bind {
+                src: "/nonexistent-src".into(),
+                dst: "/mnt".into(),
+                ro: false,
+            }])
+            .remove_clone_flags(libc::CLONE_NEWPID as u64)
+            .validate()
+            .unwrap_err();
+        assert_eq!(problems.len(), 5);
+        assert!(
+            problems
+                .iter()
+                .all(|err| matches!(err, CuriumError::InvalidConfig(_)))
+        );
+    }
+
+    #[test]
+    fn hostname_is_checked() {
+        let long =
+            Container::new("/".into(), "/bin/true").hostname(&"a".repeat(65));
+        assert!(matches!(long.check(), Err(CuriumError::InvalidConfig(_))));
+        let mut null = Container::new("/".into(), "/bin/true").hostname("b\0x");
+        assert!(matches!(null.spawn(), Err(CuriumError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn cgroup_settings_need_a_cgroup() {
+        let container = || Container::new("/".into(), "/bin/true");
+        let limit = IoLimit {
+            wbps: Some(1 << 20),
+            ..Default::default()
+        };
+        for container in [
+            container().oom_group_kill(true),
+            container().swap_limit(0),
+            container().swappiness(10),
+            container().pids_limit(16),
+            container().io_limit((8, 0), limit),
+            container().cgroup("curium-test").swappiness(101),
+        ] {
+            assert!(matches!(
+                container.check(),
+                Err(CuriumError::InvalidConfig(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn mount_namespace_fd_needs_the_host_user_namespace() {
+        // A new user namespace can't enter any existing mount namespace.
+        let container = Container::new("/".into(), "/bin/true")
+            .uid_map(0, 1000, 1)
+            .mount_namespace_fd(0);
+        assert!(matches!(
+            container.check(),
+            Err(CuriumError::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn setup_steps_are_decoded() {
+        let steps: Vec<_> = (0..).map_while(SetupStep::from_u32).collect();
+        assert_eq!(steps.last(), Some(&SetupStep::WaitForParent));
+        for (i, step) in steps.into_iter().enumerate() {
+            assert_eq!(step as u32, i as u32, "{step:?}");
+        }
+    }
+
+    #[test]
+    fn unsafe_clone_flags_are_rejected() {
+        let container = || Container::new("/".into(), "/bin/true");
+        for (flag, _) in UNSAFE_CLONE_FLAGS {
+            assert!(
+                matches!(
+                    container().clone_flags(flag).check(),
+                    Err(CuriumError::InvalidConfig(_))
+                ),
+                "{flag:#x}"
+            );
+        }
+        for (flag, _) in REQUIRED_CLONE_FLAGS {
+            assert!(
+                matches!(
+                    container().remove_clone_flags(flag).check(),
+                    Err(CuriumError::InvalidConfig(_))
+                ),
+                "{flag:#x}"
+            );
+        }
+    }
+
+    #[test]
+    fn config_values_are_checked() {
+        let container = || Container::new("/".into(), "/bin/true");
+        for container in [
+            // Only 0 is mapped.
+            container().uid_map(0, 1000, 1).run_tmpfs(Some(1)),
+            container().old_root_dir("a/b"),
+            container().proc_options(3, false),
+            container().with_rlimit_nofile(2, 1),
+            // The child would wait for itself.
+            container().seccomp_notify([libc::SYS_execve]),
+        ] {
+            assert!(matches!(
+                container.check(),
+                Err(CuriumError::InvalidConfig(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn null_in_an_apparmor_profile_is_a_config_error() {
+        let container =
+            Container::new("/".into(), "/bin/true").apparmor_profile("a\0b");
+        assert!(container.validate().unwrap_err().iter().any(|err| matches!(
+            err,
+            CuriumError::InvalidConfig(reason)
+                if reason == "Null in the AppArmor profile"
+        )));
+    }
+
+    #[test]
+    fn null_in_a_selinux_label_is_a_config_error() {
+        let container =
+            Container::new("/".into(), "/bin/true").selinux_label("a\0b");
+        assert!(container.validate().unwrap_err().iter().any(|err| matches!(
+            err,
+            CuriumError::InvalidConfig(reason)
+                if reason == "Null in the SELinux label"
+        )));
+    }
+
+    #[test]
+    fn null_in_a_mount_is_a_config_error() {
+        let mut container = Container::new("/".into(), "/bin/true")
+            .extra_mounts(vec![MountSpec::Bind {
+                src: "/a\0b".into(),
+                dst: "/mnt".into(),
+                ro: false,
+            }]);
+        assert!(matches!(
+            container.dry_run(),
+            Err(CuriumError::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            container.validate().unwrap_err()[..],
+            [CuriumError::InvalidConfig(_), ..]
+        ));
+        assert!(matches!(
+            container.spawn(),
+            Err(CuriumError::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn env_file_entries() {
+        let path = write_env_file(
+            "entries",
+            "# comment\n\nA=1\n  B=two words  \nC=x=y\nEMPTY=\n",
+        );
+        let container = Container::new("/".into(), "/bin/true")
+            .env_file(&path)
+            .unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            container.resolve_env(),
+            [c"A=1", c"B=two words", c"C=x=y", c"EMPTY="]
+        );
+    }
+
+    #[test]
+    fn inherit_env_only_skips_missing_variables() {
+        let path = std::env::var_os("PATH").expect("PATH should be set");
+        let container = Container::new("/".into(), "/bin/true")
+            .inherit_env_only(&["CURIUM_NOT_SET", "PATH"])
+            .env("A=1");
+
+        assert_eq!(
+            container.resolve_env(),
+            [env_entry("PATH".into(), path).unwrap(), c"A=1".to_owned()]
+        );
+    }
+
+    #[test]
+    fn explicit_env_overrides_inherited_in_any_order() {
+        let before = Container::new("/".into(), "/bin/true")
+            .env("PATH=/explicit")
+            .inherit_env_only(&["PATH"]);
+        let after = Container::new("/".into(), "/bin/true")
+            .inherit_env_only(&["PATH"])
+            .env("PATH=/explicit");
+
+        for container in [before, after] {
+            assert_eq!(container.resolve_env(), [c"PATH=/explicit"]);
+        }
+    }
+
+    #[test]
+    fn env_precedence() {
+        let path = write_env_file("precedence", "A=file\nB=file\n");
+        let container = Container::new("/".into(), "/bin/true")
+            .env("A=explicit")
+            .env_file(&path)
+            .unwrap()
+            .env("C=1")
+            .env("C=2");
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(container.resolve_env(), [c"A=explicit", c"B=file", c"C=2"]);
+    }
+
+    #[test]
+    fn shell_defaults_are_overridden() {
+        let container = Container::new("/".into(), "/bin/sh")
+            .env("HOME=/home/user")
+            .shell_defaults()
+            .env_remove("TERM");
+
+        let env = container.resolve_env();
+        assert_eq!(
+            env[..2],
+            [
+                c"HOME=/home/user",
+                c"PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin"
+            ]
+        );
+        assert_eq!(env[2].to_bytes(), br"PS1=\u@\h:\w\$ ");
+        assert_eq!(env.len(), 3);
+    }
+
+    #[test]
+    fn malformed_env_is_rejected() {
+        let container = Container::new("/".into(), "/bin/true")
+            .set_var("A", "x=y")
+            .set_var("B", "");
+        assert_eq!(container.resolve_env(), [c"A=x=y", c"B="]);
+        assert!(container.check().is_ok());
+
+        for container in [
+            Container::new("/".into(), "/bin/true").env("FOO"),
+            Container::new("/".into(), "/bin/true").env("=1"),
+            Container::new("/".into(), "/bin/true").set_var("A=B", "1"),
+            Container::new("/".into(), "/bin/true").set_var("", "1"),
+            Container::new("/".into(), "/bin/true").env("A=\0b"),
+            Container::new("/".into(), "/bin/true").set_var("A\0", "1"),
+            Container::new("/".into(), "/bin/true").set_var("A", "\0"),
+        ] {
+            assert!(container.resolve_env().is_empty());
+            assert!(matches!(
+                container.check(),
+                Err(CuriumError::InvalidConfig(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn env_file_reports_the_bad_line() {
+        let path = write_env_file("bad", "A=1\n# comment\nNOT_AN_ENTRY\n");
+        let err = Container::new("/".into(), "/bin/true").env_file(&path);
+        std::fs::remove_file(path).unwrap();
+
+        match err {
+            Err(CuriumError::InvalidConfig(msg)) => {
+                assert!(msg.ends_with(":3: expected KEY=VALUE"), "{msg}")
+            }
+            _ => panic!("the env file should be rejected"),
+        }
+    }
+
+    #[test]
+    fn oci_bundle_needs_a_rootfs() {
+        let bundle = std::env::temp_dir()
+            .join(format!("curium-bundle-{}", std::process::id()));
+        std::fs::create_dir(&bundle).unwrap();
+        std::fs::write(
+            bundle.join("config.json"),
+            r#"{"process":{"args":["/bin/sh","-c","exit"],"env":["A=1"]}}"#,
+        )
+        .unwrap();
+
+        let missing = Container::from_oci_bundle(&bundle);
+        std::fs::create_dir(bundle.join("rootfs")).unwrap();
+        let container = Container::from_oci_bundle(&bundle);
+        std::fs::remove_dir_all(&bundle).unwrap();
+
+        match missing {
+            Err(CuriumError::InvalidConfig(msg)) => assert!(
+                msg.ends_with("rootfs: the rootfs directory is missing"),
+                "{msg}"
+            ),
+            _ => panic!("the missing rootfs should be rejected"),
+        }
+        let container = container.unwrap();
+        assert_eq!(container.root, format!("{}/rootfs", bundle.display()));
+        assert_eq!(container.entrypoint, [c"/bin/sh", c"-c", c"exit"]);
+        assert_eq!(container.resolve_env(), [c"A=1"]);
+        assert!(!container.readonly_root);
+    }
+
+    #[test]
+    fn dry_run_follows_the_spawn_order() {
+        let plan = Container::new("/rootfs".into(), "/bin/sh")
+            .arg("-c")
+            .uid_map(0, 1000, 1)
+            .root_mode(RootMode::Chroot)
+            .extra_mounts(vec![MountSpec::Bind {
+                src: "/data".into(),
+                dst: "/mnt".into(),
+                ro: true,
+            }])
+            .dry_run()
+            .unwrap();
+
+        let position = |operation: &Operation| {
+            plan.iter().position(|op| op == operation).unwrap()
+        };
+        let clone = position(&Operation::Clone {
+            flags: Container::new("/".into(), "/bin/true")
+                .uid_map(0, 1000, 1)
+                .raw_clone_flags(),
+        });
+        let wake = position(&Operation::Wake);
+        let bind = position(&Operation::Mount {
+            source: "/data".into(),
+            target: "/rootfs/mnt".into(),
+            fs_type: None,
+            options: vec!["bind".into(), "ro".into()],
+        });
+        let chroot = position(&Operation::Chroot {
+            path: "/rootfs".into(),
+        });
+        assert!(clone < wake && wake < bind && bind < chroot);
+        assert_eq!(
+            plan.last(),
+            Some(&Operation::Exec {
+                path: "/bin/sh".into(),
+                argv: vec!["/bin/sh".into(), "-c".into()],
+                envp: vec![],
+            })
+        );
+
+        let plan = Container::new("/".into(), "/bin/true")
+            .after_start(|_| Ok(()))
+            .after_clone(|_| Ok(()))
+            .dry_run()
+            .unwrap();
+        let position =
+            |operation| plan.iter().position(|op| *op == operation).unwrap();
+        // The child waits for the after_clone callbacks.
+        assert!(
+            position(Operation::AfterClone) < position(Operation::Wake)
+                && position(Operation::Wake) < position(Operation::AfterStart)
+        );
+
+        // Checked like spawn.
+        let err = Container::new("/".into(), "/bin/true")
+            .pids_limit(10)
+            .dry_run();
+        assert!(matches!(err, Err(CuriumError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn net_sysctls_are_checked() {
+        let check = |sysctl| {
+            Container::new("/".into(), "/bin/true")
+                .net_sysctls([sysctl])
+                .check()
+        };
+        assert!(check(NetSysctl::LocalPortRange(1024, 65535)).is_ok());
+        assert!(check(NetSysctl::LocalPortRange(2000, 1000)).is_err());
+        assert!(check(NetSysctl::LocalPortRange(0, 1000)).is_err());
+        assert!(check(NetSysctl::PingGroupRange(1, u32::MAX)).is_err());
+        assert_eq!(NetSysctl::PingGroupRange(0, 10).value().unwrap(), "0\t10");
+
+        let host = Container::new("/".into(), "/bin/true")
+            .net_mode(NetMode::Host)
+            .net_sysctls([NetSysctl::IpForward(true)]);
+        assert!(matches!(host.check(), Err(CuriumError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn joined_namespaces_are_not_created() {
+        use std::os::fd::AsFd;
+
+        // Only checked, never entered.
+        let pidfd = File::open("/proc/self/stat").unwrap();
+        let join = |which| {
+            Container::new("/".into(), "/bin/true")
+                .join_namespaces_of(pidfd.as_fd(), which)
+        };
+
+        let container = join(Namespaces::PID | Namespaces::NET);
+        assert_eq!(
+            container.namespaces(),
+            Namespaces::IPC | Namespaces::UTS | Namespaces::MOUNT
+        );
+        assert!(container.check().is_ok());
+
+        for container in [
+            join(Namespaces::NET).uid_map(0, 1000, 1),
+            join(Namespaces::UTS).hostname("box"),
+            join(Namespaces::NET).net_sysctls([NetSysctl::IpForward(true)]),
+            join(Namespaces::MOUNT).mount_namespace_fd(3),
+        ] {
+            assert!(matches!(
+                container.check(),
+                Err(CuriumError::InvalidConfig(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn cgroup_namespace_root_is_unshared_after_the_clone() {
+        let container =
+            Container::new("/".into(), "/bin/true").cgroup_namespace_root();
+        assert!(container.namespaces().contains(Namespaces::CGROUP));
+        assert_eq!(
+            container.raw_clone_flags() & libc::CLONE_NEWCGROUP as u64,
+            0
+        );
+        let plan = container.harden_sys().dry_run().unwrap();
+        assert!(plan.contains(&Operation::Unshare {
+            namespaces: Namespaces::CGROUP
+        }));
+        assert!(!plan.contains(&Operation::Mask {
+            path: "//sys/fs/cgroup".into()
+        }));
+
+        assert!(cgroups_are_root(b"0::/\n"));
+        assert!(cgroups_are_root(b"4:memory:/\n1:name=systemd:/\n0::/\n"));
+        assert!(!cgroups_are_root(b"4:memory:/web\n0::/\n"));
     }
 }
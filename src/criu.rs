@@ -0,0 +1,120 @@
+//! Checkpoint and restore of containers by running `criu`, see criu(8).
+
+use std::{
+    io::{self, ErrorKind},
+    path::Path,
+    process::Command,
+};
+
+/// The options shared by dump and restore: the container's stdio is the
+/// host's (`--shell-job`), and the binds from the host are external mounts
+/// criu resolves by itself.
+const COMMON_ARGS: [&str; 5] = [
+    "--shell-job",
+    "--manage-cgroups",
+    "--ext-mount-map=auto",
+    "--enable-external-sharing",
+    "--enable-external-masters",
+];
+
+/// The command dumping the process tree of `pid` to the images directory
+/// `dir`, which kills the tree once dumped.
+pub(crate) fn dump_command(pid: i64, dir: &Path) -> Command {
+    let mut command = Command::new("criu");
+    command
+        .arg("dump")
+        .args(["--tree", &pid.to_string()])
+        .arg("--images-dir")
+        .arg(dir)
+        .args(COMMON_ARGS)
+        .args(["--log-file", "dump.log"]);
+    command
+}
+
+/// The command restoring the images of `dir` with `root` as the rootfs. The
+/// root task is created as a sibling of criu, so a child of ours, and its pid
+/// written to `pidfile`.
+pub(crate) fn restore_command(
+    dir: &Path,
+    root: &Path,
+    cgroup: Option<&Path>,
+    pidfile: &Path,
+) -> Command {
+    let mut command = Command::new("criu");
+    command
+        .arg("restore")
+        .arg("--images-dir")
+        .arg(dir)
+        .arg("--root")
+        .arg(root)
+        .args(COMMON_ARGS)
+        .args(["--restore-detached", "--restore-sibling"])
+        .arg("--pidfile")
+        .arg(pidfile)
+        .args(["--log-file", "restore.log"]);
+    if let Some(cgroup) = cgroup {
+        command
+            .arg("--cgroup-root")
+            .arg(Path::new("/").join(cgroup));
+    }
+    command
+}
+
+/// Run a criu `command`, pointing at its log in `dir` when it fails.
+pub(crate) fn run(mut command: Command, dir: &Path) -> Result<(), io::Error> {
+    let action = command.get_args().next().unwrap_or_default().display();
+    let action = action.to_string();
+    let log = dir.join(format!("{action}.log"));
+
+    match command.status() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(io::Error::other(format!(
+            "criu {action} failed: {status}, see {}",
+            log.display()
+        ))),
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            Err(io::Error::new(ErrorKind::NotFound, "criu isn't installed"))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(command: &Command) -> Vec<&str> {
+        command
+            .get_args()
+            .map(|arg| arg.to_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn dump_args() {
+        let command = dump_command(42, Path::new("/images"));
+        assert_eq!(
+            args(&command)[..5],
+            ["dump", "--tree", "42", "--images-dir", "/images"]
+        );
+        assert!(args(&command).contains(&"--shell-job"));
+    }
+
+    #[test]
+    fn restore_args() {
+        let command = restore_command(
+            Path::new("/images"),
+            Path::new("/rootfs"),
+            Some(Path::new("web")),
+            Path::new("/run/web.pid"),
+        );
+        let args = args(&command);
+        let after =
+            |arg| args[args.iter().position(|a| *a == arg).unwrap() + 1];
+        assert_eq!(after("--images-dir"), "/images");
+        assert_eq!(after("--root"), "/rootfs");
+        assert_eq!(after("--pidfile"), "/run/web.pid");
+        assert_eq!(after("--cgroup-root"), "/web");
+        assert!(args.contains(&"--restore-sibling"));
+    }
+}
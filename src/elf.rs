@@ -0,0 +1,431 @@
+//! Just enough of an ELF reader to find the interpreter and the shared
+//! libraries of an executable, see elf(5) and ld.so(8).
+
+use std::{
+    collections::{HashSet, VecDeque},
+    ffi::{OsStr, OsString},
+    fs::File,
+    io::{Error, ErrorKind},
+    os::unix::{
+        ffi::{OsStrExt, OsStringExt},
+        fs::FileExt,
+    },
+    path::{Path, PathBuf},
+};
+
+const ELFMAG: [u8; 4] = *b"\x7fELF";
+const ELFCLASS32: u8 = 1;
+const ELFCLASS64: u8 = 2;
+const ELFDATA2MSB: u8 = 2;
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+const DT_NULL: u64 = 0;
+const DT_NEEDED: u64 = 1;
+const DT_STRTAB: u64 = 5;
+const DT_STRSZ: u64 = 10;
+const DT_RPATH: u64 = 15;
+const DT_RUNPATH: u64 = 29;
+
+/// The directories searched after the `RPATH` and `RUNPATH`, the ones of the
+/// loader and of the usual `ld.so.cache` (multiarch included). A library of
+/// another class or machine is skipped, like the loader does.
+const DEFAULT_LIB_DIRS: [&str; 8] = [
+    "/lib/x86_64-linux-gnu",
+    "/usr/lib/x86_64-linux-gnu",
+    "/lib/aarch64-linux-gnu",
+    "/usr/lib/aarch64-linux-gnu",
+    "/lib64",
+    "/usr/lib64",
+    "/lib",
+    "/usr/lib",
+];
+
+/// A program header, the fields we use of it.
+struct Segment {
+    kind: u32,
+    offset: u64,
+    vaddr: u64,
+    filesz: u64,
+}
+
+/// The headers of an ELF file.
+struct Elf {
+    file: File,
+    class: u8,
+    big_endian: bool,
+    machine: u16,
+    segments: Vec<Segment>,
+}
+
+/// The entries of the dynamic section we resolve libraries with.
+#[derive(Default)]
+struct Dynamic {
+    needed: Vec<OsString>,
+    rpath: Option<OsString>,
+    runpath: Option<OsString>,
+}
+
+fn invalid() -> Error {
+    Error::new(ErrorKind::InvalidData, "bad ELF header")
+}
+
+impl Elf {
+    /// Read the headers of the file at `path`, `None` if it isn't ELF.
+    fn open(path: &Path) -> Result<Option<Self>, Error> {
+        let file = File::open(path)?;
+        let mut header = [0u8; 64];
+        let len = file.read_at(&mut header, 0)?;
+        if len < 16 || header[..4] != ELFMAG {
+            return Ok(None);
+        }
+
+        let mut elf = Self {
+            file,
+            class: header[4],
+            big_endian: header[5] == ELFDATA2MSB,
+            machine: 0,
+            segments: vec![],
+        };
+        // The sizes of the ELF header and of a program header.
+        let (header_len, phdr_len) = match elf.class {
+            ELFCLASS32 => (52, 32),
+            ELFCLASS64 => (64, 56),
+            _ => return Err(invalid()),
+        };
+        if len < header_len {
+            return Err(invalid());
+        }
+        elf.machine = elf.u16_at(&header, 18);
+        let (phoff, phentsize, phnum) = match elf.class {
+            ELFCLASS32 => (
+                elf.u32_at(&header, 28) as u64,
+                elf.u16_at(&header, 42),
+                elf.u16_at(&header, 44),
+            ),
+            _ => (
+                elf.u64_at(&header, 32),
+                elf.u16_at(&header, 54),
+                elf.u16_at(&header, 56),
+            ),
+        };
+        if (phentsize as usize) < phdr_len {
+            return Err(invalid());
+        }
+
+        let mut phdr = vec![0u8; phdr_len];
+        for i in 0..phnum as u64 {
+            elf.file
+                .read_exact_at(&mut phdr, phoff + i * phentsize as u64)?;
+            let segment = match elf.class {
+                ELFCLASS32 => Segment {
+                    kind: elf.u32_at(&phdr, 0),
+                    offset: elf.u32_at(&phdr, 4) as u64,
+                    vaddr: elf.u32_at(&phdr, 8) as u64,
+                    filesz: elf.u32_at(&phdr, 16) as u64,
+                },
+                _ => Segment {
+                    kind: elf.u32_at(&phdr, 0),
+                    offset: elf.u64_at(&phdr, 8),
+                    vaddr: elf.u64_at(&phdr, 16),
+                    filesz: elf.u64_at(&phdr, 32),
+                },
+            };
+            elf.segments.push(segment);
+        }
+        Ok(Some(elf))
+    }
+
+    fn u16_at(&self, buf: &[u8], at: usize) -> u16 {
+        let bytes = [buf[at], buf[at + 1]];
+        match self.big_endian {
+            true => u16::from_be_bytes(bytes),
+            false => u16::from_le_bytes(bytes),
+        }
+    }
+
+    fn u32_at(&self, buf: &[u8], at: usize) -> u32 {
+        let bytes = buf[at..at + 4].try_into().unwrap();
+        match self.big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        }
+    }
+
+    fn u64_at(&self, buf: &[u8], at: usize) -> u64 {
+        let bytes = buf[at..at + 8].try_into().unwrap();
+        match self.big_endian {
+            true => u64::from_be_bytes(bytes),
+            false => u64::from_le_bytes(bytes),
+        }
+    }
+
+    /// Read the contents of `segment`, refusing ones larger than `max`.
+    fn read_segment(
+        &self,
+        segment: &Segment,
+        max: u64,
+    ) -> Result<Vec<u8>, Error> {
+        if segment.filesz > max {
+            return Err(invalid());
+        }
+        let mut buf = vec![0u8; segment.filesz as usize];
+        self.file.read_exact_at(&mut buf, segment.offset)?;
+        Ok(buf)
+    }
+
+    /// The file offset of the virtual address `vaddr`.
+    fn offset_of(&self, vaddr: u64) -> Option<u64> {
+        self.segments
+            .iter()
+            .filter(|segment| segment.kind == PT_LOAD)
+            .find(|segment| {
+                (segment.vaddr..segment.vaddr + segment.filesz).contains(&vaddr)
+            })
+            .map(|segment| vaddr - segment.vaddr + segment.offset)
+    }
+
+    fn interpreter(&self) -> Result<Option<PathBuf>, Error> {
+        let Some(segment) = self.segments.iter().find(|s| s.kind == PT_INTERP)
+        else {
+            return Ok(None);
+        };
+        let mut interp = self.read_segment(segment, libc::PATH_MAX as u64)?;
+        // The path is null terminated.
+        if let Some(end) = interp.iter().position(|&b| b == 0) {
+            interp.truncate(end);
+        }
+        if interp.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Some(OsString::from_vec(interp).into()))
+    }
+
+    /// Read the dynamic section, empty for a static executable.
+    fn dynamic(&self) -> Result<Dynamic, Error> {
+        let Some(segment) = self.segments.iter().find(|s| s.kind == PT_DYNAMIC)
+        else {
+            return Ok(Dynamic::default());
+        };
+        let entries = self.read_segment(segment, 1 << 20)?;
+        let entry_len = match self.class {
+            ELFCLASS32 => 8,
+            _ => 16,
+        };
+
+        let (mut strtab, mut strsz) = (None, 0);
+        let (mut needed, mut rpath, mut runpath) = (vec![], None, None);
+        for entry in entries.chunks_exact(entry_len) {
+            let (tag, val) = match self.class {
+                ELFCLASS32 => {
+                    (self.u32_at(entry, 0) as u64, self.u32_at(entry, 4) as u64)
+                }
+                _ => (self.u64_at(entry, 0), self.u64_at(entry, 8)),
+            };
+            match tag {
+                DT_NULL => break,
+                DT_NEEDED => needed.push(val),
+                DT_STRTAB => strtab = Some(val),
+                DT_STRSZ => strsz = val,
+                DT_RPATH => rpath = Some(val),
+                DT_RUNPATH => runpath = Some(val),
+                _ => (),
+            }
+        }
+        if needed.is_empty() && rpath.is_none() && runpath.is_none() {
+            return Ok(Dynamic::default());
+        }
+
+        let offset = strtab.and_then(|addr| self.offset_of(addr));
+        let Some(offset) = offset else {
+            return Err(invalid());
+        };
+        let strings = self.read_segment(
+            &Segment {
+                kind: 0,
+                offset,
+                vaddr: 0,
+                filesz: strsz,
+            },
+            16 << 20,
+        )?;
+        let string = |at: u64| -> Result<OsString, Error> {
+            let bytes = strings.get(at as usize..).ok_or_else(invalid)?;
+            let end = bytes.iter().position(|&b| b == 0).ok_or_else(invalid)?;
+            Ok(OsString::from_vec(bytes[..end].to_vec()))
+        };
+        Ok(Dynamic {
+            needed: needed.into_iter().map(string).collect::<Result<_, _>>()?,
+            rpath: rpath.map(string).transpose()?,
+            runpath: runpath.map(string).transpose()?,
+        })
+    }
+}
+
+/// Return the interpreter (the dynamic loader) requested by the executable
+/// at `path`, or `None` for a static executable or a file that isn't ELF.
+pub(crate) fn interpreter(path: &Path) -> Result<Option<PathBuf>, Error> {
+    match Elf::open(path)? {
+        Some(elf) => elf.interpreter(),
+        None => Ok(None),
+    }
+}
+
+/// Resolve the shared libraries the executable at `path` needs, and the ones
+/// they need in turn, to their host paths, like ldd(1) without running the
+/// loader. Empty for a static executable or a file that isn't ELF.
+///
+/// A library is looked up in the `RPATH` of the object needing it and of the
+/// executable (unless the object has a `RUNPATH`), then in its `RUNPATH`,
+/// then in [`DEFAULT_LIB_DIRS`]. `LD_LIBRARY_PATH` and `ld.so.cache` are left
+/// out: neither is in the container. `$ORIGIN` is expanded, a directory with
+/// another dynamic string token (`$LIB`, `$PLATFORM`) is skipped. Fails with
+/// `NotFound` if a library isn't found.
+pub(crate) fn dependencies(path: &Path) -> Result<Vec<PathBuf>, Error> {
+    let Some(exe) = Elf::open(path)? else {
+        return Ok(vec![]);
+    };
+    let (class, machine) = (exe.class, exe.machine);
+    let exe_rpath = search_dirs(exe.dynamic()?.rpath.as_deref(), path);
+
+    let mut libs = vec![];
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::from([(path.to_owned(), exe)]);
+    while let Some((object, elf)) = queue.pop_front() {
+        let dynamic = elf.dynamic()?;
+        let mut dirs = vec![];
+        if dynamic.runpath.is_none() {
+            dirs.extend(search_dirs(dynamic.rpath.as_deref(), &object));
+            dirs.extend(exe_rpath.iter().cloned());
+        }
+        dirs.extend(search_dirs(dynamic.runpath.as_deref(), &object));
+        dirs.extend(DEFAULT_LIB_DIRS.iter().map(PathBuf::from));
+
+        for name in dynamic.needed {
+            if !seen.insert(name.clone()) {
+                continue;
+            }
+            let candidates: Vec<PathBuf> = match name.as_bytes().contains(&b'/')
+            {
+                true => expand_origin(&name, &object).into_iter().collect(),
+                false => dirs.iter().map(|dir| dir.join(&name)).collect(),
+            };
+            let found = candidates.into_iter().find_map(|candidate| {
+                match Elf::open(&candidate) {
+                    Ok(Some(lib))
+                        if lib.class == class && lib.machine == machine =>
+                    {
+                        Some((candidate, lib))
+                    }
+                    _ => None,
+                }
+            });
+            let Some((lib_path, lib)) = found else {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!(
+                        "the shared library {} of {} isn't found",
+                        name.display(),
+                        object.display()
+                    ),
+                ));
+            };
+            libs.push(lib_path.clone());
+            queue.push_back((lib_path, lib));
+        }
+    }
+    Ok(libs)
+}
+
+/// The directories of a colon separated `RPATH` or `RUNPATH` of the object
+/// at `object`, with `$ORIGIN` expanded.
+fn search_dirs(list: Option<&OsStr>, object: &Path) -> Vec<PathBuf> {
+    let Some(list) = list else {
+        return vec![];
+    };
+    list.as_bytes()
+        .split(|&b| b == b':')
+        .filter(|dir| !dir.is_empty())
+        .filter_map(|dir| expand_origin(OsStr::from_bytes(dir), object))
+        .collect()
+}
+
+/// Replace `$ORIGIN` (or `${ORIGIN}`) in `path` with the directory of the
+/// object at `object`, `None` if `path` has another dynamic string token.
+fn expand_origin(path: &OsStr, object: &Path) -> Option<PathBuf> {
+    let path = path.as_bytes();
+    let origin = std::fs::canonicalize(object)
+        .ok()
+        .and_then(|object| object.parent().map(Path::to_owned))
+        .unwrap_or_else(|| object.parent().unwrap_or(object).to_owned());
+
+    let mut expanded = vec![];
+    let mut rest = path;
+    while let Some(at) = rest.iter().position(|&b| b == b'$') {
+        expanded.extend_from_slice(&rest[..at]);
+        rest = &rest[at..];
+        let token = [b"$ORIGIN".as_slice(), b"${ORIGIN}"]
+            .into_iter()
+            .find(|token| rest.starts_with(token))?;
+        expanded.extend_from_slice(origin.as_os_str().as_bytes());
+        rest = &rest[token.len()..];
+    }
+    expanded.extend_from_slice(rest);
+    Some(OsString::from_vec(expanded).into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn interpreter_of_the_test_binary() {
+        // Test binaries are dynamically linked, unless built with
+        // `+crt-static`.
+        let exe = std::env::current_exe().unwrap();
+        if let Some(interp) = interpreter(&exe).unwrap() {
+            assert!(interp.is_absolute());
+            assert!(interp.exists());
+        }
+    }
+
+    #[test]
+    fn not_elf() {
+        let path = std::env::temp_dir()
+            .join(format!("curium-elf-{}.sh", std::process::id()));
+        std::fs::write(&path, "#!/bin/sh\n").unwrap();
+        let interp = interpreter(&path);
+        let libs = dependencies(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(interp.unwrap(), None);
+        assert_eq!(libs.unwrap(), Vec::<PathBuf>::new());
+    }
+
+    #[test]
+    fn dependencies_of_the_test_binary() {
+        let exe = std::env::current_exe().unwrap();
+        if interpreter(&exe).unwrap().is_none() {
+            return;
+        }
+        let libs = dependencies(&exe).unwrap();
+        assert!(
+            libs.iter()
+                .any(|lib| lib.file_name().unwrap() == "libc.so.6"),
+            "{libs:?}"
+        );
+        assert!(libs.iter().all(|lib| lib.is_absolute() && lib.exists()));
+    }
+
+    #[test]
+    fn origin_is_expanded() {
+        let object = Path::new("/nonexistent/bin/tool");
+        assert_eq!(
+            expand_origin(OsStr::new("$ORIGIN/../lib"), object),
+            Some("/nonexistent/bin/../lib".into())
+        );
+        assert_eq!(
+            search_dirs(Some(OsStr::new("${ORIGIN}:/opt/lib::$LIB")), object),
+            [PathBuf::from("/nonexistent/bin"), "/opt/lib".into()]
+        );
+    }
+}
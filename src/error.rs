@@ -1,10 +1,154 @@
+use crate::handle::ExitStatus;
+
 #[derive(Debug)]
 pub enum CuriumError {
-    InvalidConfig,
+    /// The container is configured wrong, with the reason.
+    InvalidConfig(String),
     ContainerNotFound,
     ContainerIdAlreadyInUse,
     ContainerIsNotCreated,
     ContainerIsNotStopped,
+    /// The command doesn't exist in the container.
+    CommandNotFound,
+    /// The command couldn't be executed.
+    ExecFailed(std::io::Error),
+    /// The child failed to set up the container before exec.
+    SetupFailed {
+        step: SetupStep,
+        err: std::io::Error,
+    },
+    /// The child gave up waiting for the parent to set it up, after
+    /// [`Container::startup_timeout`](crate::Container::startup_timeout).
+    StartupTimedOut,
+    /// The child died while setting up the container without reporting a
+    /// [`SetupStep`], e.g. it was killed, with its status.
+    SetupAborted(ExitStatus),
+    /// A system call of the parent failed.
+    Io(std::io::Error),
+}
+
+impl From<std::io::Error> for CuriumError {
+    fn from(err: std::io::Error) -> Self {
+        CuriumError::Io(err)
+    }
+}
+
+/// A step of the container setup in the child, reported to the parent with
+/// the errno when it fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum SetupStep {
+    Exec = 0,
+    /// The `/proc` mount point is missing from the rootfs.
+    ProcMountpoint = 1,
+    /// The `/sys` mount point is missing from the rootfs.
+    SysMountpoint = 2,
+    /// pivot_root into the rootfs failed, or the old root couldn't be
+    /// detached.
+    PivotRoot = 3,
+    /// The old root directory of pivot_root couldn't be created (`EEXIST` if
+    /// the rootfs has one) or removed.
+    OldRootDir = 4,
+    /// Changing into the new root (chroot or chdir) failed.
+    EnterRoot = 5,
+    /// setns into the mount namespace of
+    /// [`Container::mount_namespace_fd`](crate::Container::mount_namespace_fd)
+    /// failed.
+    MountNamespace = 6,
+    /// A sysctl of [`Container::net_sysctls`](crate::Container::net_sysctls)
+    /// couldn't be written.
+    NetSysctl = 7,
+    /// setns into the namespaces of
+    /// [`Container::join_namespaces_of`](crate::Container::join_namespaces_of)
+    /// failed.
+    JoinNamespaces = 8,
+    /// The filter of
+    /// [`Container::seccomp_default`](crate::Container::seccomp_default) or
+    /// [`Container::seccomp_notify`](crate::Container::seccomp_notify)
+    /// couldn't be loaded, or the listener sent to the parent.
+    Seccomp = 9,
+    /// The cgroup namespace of
+    /// [`Container::cgroup_namespace_root`](crate::Container::cgroup_namespace_root)
+    /// couldn't be created or its cgroup2 mounted, or (with `EINVAL`)
+    /// `/proc/self/cgroup` shows the container outside its root.
+    CgroupNamespace = 10,
+    /// The `SCHED_IDLE` policy of
+    /// [`Container::batch_mode`](crate::Container::batch_mode) couldn't be
+    /// set.
+    Scheduler = 11,
+    /// The time namespace of
+    /// [`Container::time_namespace`](crate::Container::time_namespace)
+    /// couldn't be created or its clocks reset.
+    TimeNamespace = 12,
+    /// ASLR couldn't be disabled for
+    /// [`Container::disable_aslr`](crate::Container::disable_aslr).
+    Personality = 13,
+    /// Our file descriptors couldn't be marked close-on-exec.
+    CloseFds = 14,
+    /// The hostname or the domain name couldn't be set.
+    Uts = 15,
+    /// The loopback interface of the new network namespace couldn't be
+    /// brought up.
+    Loopback = 16,
+    /// A mount of the rootfs setup failed, or a mount point or file it needs
+    /// couldn't be created: the rootfs itself, `/proc` and `/sys` with their
+    /// masked and read-only paths, the files and executable bound from the
+    /// host, `/dev/shm`, `/run`, the extra mounts and the remounts of the
+    /// root.
+    Mount = 17,
+    /// The tty or a redirection of the standard streams couldn't be set up.
+    StandardStreams = 18,
+    /// fchdir into
+    /// [`Container::working_dir_fd`](crate::Container::working_dir_fd)
+    /// failed.
+    WorkingDir = 19,
+    /// The AppArmor profile or the SELinux label couldn't be set.
+    Lsm = 20,
+    /// The Landlock ruleset couldn't be enforced.
+    Landlock = 21,
+    /// The bounding capabilities couldn't be dropped or the ambient ones
+    /// raised.
+    Capabilities = 22,
+    /// The child couldn't be traced by the parent for
+    /// [`Container::stop_before_exec`](crate::Container::stop_before_exec).
+    StopBeforeExec = 23,
+    /// Reading the wake byte of the parent failed, with `ETIMEDOUT` after
+    /// [`Container::startup_timeout`](crate::Container::startup_timeout).
+    WaitForParent = 24,
+}
+
+impl SetupStep {
+    /// The step the child sent `as u32`.
+    pub(crate) fn from_u32(step: u32) -> Option<Self> {
+        Some(match step {
+            0 => Self::Exec,
+            1 => Self::ProcMountpoint,
+            2 => Self::SysMountpoint,
+            3 => Self::PivotRoot,
+            4 => Self::OldRootDir,
+            5 => Self::EnterRoot,
+            6 => Self::MountNamespace,
+            7 => Self::NetSysctl,
+            8 => Self::JoinNamespaces,
+            9 => Self::Seccomp,
+            10 => Self::CgroupNamespace,
+            11 => Self::Scheduler,
+            12 => Self::TimeNamespace,
+            13 => Self::Personality,
+            14 => Self::CloseFds,
+            15 => Self::Uts,
+            16 => Self::Loopback,
+            17 => Self::Mount,
+            18 => Self::StandardStreams,
+            19 => Self::WorkingDir,
+            20 => Self::Lsm,
+            21 => Self::Landlock,
+            22 => Self::Capabilities,
+            23 => Self::StopBeforeExec,
+            24 => Self::WaitForParent,
+            _ => return None,
+        })
+    }
 }
 
 pub type Result<T> = std::result::Result<T, CuriumError>;
@@ -0,0 +1,161 @@
+//! Passing file descriptors over a `UnixStream`, like the socket shared by
+//! the parent and the child during spawn, see unix(7) for `SCM_RIGHTS`.
+
+use std::{
+    io::{self, Error},
+    mem,
+    os::{
+        fd::{AsFd, AsRawFd, FromRawFd, OwnedFd, RawFd},
+        unix::net::UnixStream,
+    },
+};
+
+/// The space of a control message carrying one fd.
+const FD_SPACE: usize =
+    unsafe { libc::CMSG_SPACE(mem::size_of::<RawFd>() as u32) } as usize;
+
+/// A control buffer for one fd, aligned like a `cmsghdr`.
+type ControlBuf = [u64; FD_SPACE.div_ceil(8)];
+
+/// Make a `msghdr` of the single `iov` with the control buffer `control`.
+fn msghdr(iov: &mut libc::iovec, control: &mut ControlBuf) -> libc::msghdr {
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = FD_SPACE as _;
+    msg
+}
+
+/// Send a duplicate of `fd` over `sock`, along a single zero byte: a stream
+/// socket only carries an fd with data.
+///
+/// # Signal Safety
+/// This function is signal safe.
+pub fn send_fd<F: AsFd>(sock: &UnixStream, fd: &F) -> Result<(), Error> {
+    let mut byte = [0u8];
+    let mut iov = libc::iovec {
+        iov_base: byte.as_mut_ptr().cast(),
+        iov_len: 1,
+    };
+    let mut control = ControlBuf::default();
+    let msg = msghdr(&mut iov, &mut control);
+    unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<RawFd>() as u32) as _;
+        libc::CMSG_DATA(cmsg)
+            .cast::<RawFd>()
+            .write_unaligned(fd.as_fd().as_raw_fd());
+    }
+
+    loop {
+        match unsafe { libc::sendmsg(sock.as_raw_fd(), &msg, 0) } {
+            1 => return Ok(()),
+            -1 if Error::last_os_error().kind()
+                == io::ErrorKind::Interrupted => {}
+            -1 => return Err(Error::last_os_error()),
+            _ => return Err(Error::from(io::ErrorKind::WriteZero)),
+        }
+    }
+}
+
+/// Read up to `buf.len()` bytes from `sock` like read(2), with the fd sent
+/// along them by [`send_fd`] if there is one. Returns the number of bytes
+/// read, 0 at EOF.
+///
+/// The fd is received close-on-exec. A message stops at the bytes sent with
+/// an fd, so reading a single byte tells an fd from other data.
+pub fn recv_fd(
+    sock: &UnixStream,
+    buf: &mut [u8],
+) -> Result<(usize, Option<OwnedFd>), Error> {
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr().cast(),
+        iov_len: buf.len(),
+    };
+    let mut control = ControlBuf::default();
+    let mut msg = msghdr(&mut iov, &mut control);
+
+    let len = loop {
+        match unsafe {
+            libc::recvmsg(sock.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC)
+        } {
+            -1 if Error::last_os_error().kind()
+                == io::ErrorKind::Interrupted => {}
+            -1 => return Err(Error::last_os_error()),
+            len => break len as usize,
+        }
+    };
+
+    let cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+    if cmsg.is_null()
+        || unsafe { (*cmsg).cmsg_level } != libc::SOL_SOCKET
+        || unsafe { (*cmsg).cmsg_type } != libc::SCM_RIGHTS
+    {
+        return Ok((len, None));
+    }
+    let fd = unsafe { libc::CMSG_DATA(cmsg).cast::<RawFd>().read_unaligned() };
+    // SAFETY: The fd was just received, nothing else owns it.
+    Ok((len, Some(unsafe { OwnedFd::from_raw_fd(fd) })))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        fs::File,
+        io::{Read, Seek, Write},
+        os::unix::fs::MetadataExt,
+    };
+
+    use super::*;
+
+    #[test]
+    fn sent_fd_refers_to_the_same_file() {
+        let (parent, child) = UnixStream::pair().unwrap();
+        let path = std::env::temp_dir()
+            .join(format!("curium-fd-channel-{}", std::process::id()));
+        let mut file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        send_fd(&child, &file).unwrap();
+
+        let mut byte = [1];
+        let (len, fd) = recv_fd(&parent, &mut byte).unwrap();
+        assert_eq!((len, byte), (1, [0]));
+        let mut received = File::from(fd.unwrap());
+        assert_ne!(received.as_raw_fd(), file.as_raw_fd());
+        let (meta, received_meta) =
+            (file.metadata().unwrap(), received.metadata().unwrap());
+        assert_eq!(
+            (meta.dev(), meta.ino()),
+            (received_meta.dev(), received_meta.ino())
+        );
+
+        // The file description is shared, with its offset.
+        received.write_all(b"hi").unwrap();
+        file.rewind().unwrap();
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "hi");
+    }
+
+    #[test]
+    fn data_without_an_fd() {
+        let (parent, mut child) = UnixStream::pair().unwrap();
+        child.write_all(b"ab").unwrap();
+        drop(child);
+
+        let mut buf = [0; 4];
+        let (len, fd) = recv_fd(&parent, &mut buf).unwrap();
+        assert_eq!(&buf[..len], b"ab");
+        assert!(fd.is_none());
+        assert_eq!(recv_fd(&parent, &mut buf).unwrap().0, 0);
+    }
+}
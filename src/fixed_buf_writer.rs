@@ -18,16 +18,59 @@ use std::io::{ErrorKind, Write};
 pub struct FixedBufferWriter<const COUNT: usize> {
     buffer: [u8; COUNT],
     pos: usize,
+    policy: OverflowPolicy,
+}
+
+/// What [`FixedBufferWriter`]'s `write` does with bytes that don't fit.
+///
+/// `push_byte` and `push_str` always report the overflow instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Write nothing and fail with `ErrorKind::WriteZero`.
+    #[default]
+    Error,
+    /// Write the bytes that fit and silently drop the rest, meant for logs
+    /// where a cut message beats none.
+    Truncate,
+    /// Panic in debug builds, [`OverflowPolicy::Error`] in release builds.
+    DebugPanic,
 }
 
 impl<const COUNT: usize> FixedBufferWriter<COUNT> {
     pub fn new() -> Self {
+        Self::with_policy(OverflowPolicy::default())
+    }
+
+    /// A writer with the `policy` on overflow.
+    pub fn with_policy(policy: OverflowPolicy) -> Self {
         Self {
             pos: 0,
             buffer: [0u8; COUNT],
+            policy,
         }
     }
 
+    /// A writer dropping the bytes that don't fit, see
+    /// [`OverflowPolicy::Truncate`].
+    pub fn truncating() -> Self {
+        Self::with_policy(OverflowPolicy::Truncate)
+    }
+
+    /// Write into `buffer` from its start, the bytes after the written ones
+    /// are left as they are.
+    pub fn from_buffer(buffer: [u8; COUNT]) -> Self {
+        Self {
+            pos: 0,
+            buffer,
+            policy: OverflowPolicy::default(),
+        }
+    }
+
+    /// Return the underlying buffer and the number of written bytes.
+    pub fn into_inner(self) -> ([u8; COUNT], usize) {
+        (self.buffer, self.pos)
+    }
+
     /// Return a reference to a slice of the underlying buffer.
     pub fn buffer(&self) -> &[u8] {
         &self.buffer[0..self.pos]
@@ -42,6 +85,42 @@ impl<const COUNT: usize> FixedBufferWriter<COUNT> {
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
+
+    /// Append a single byte, returning false if the buffer is full.
+    ///
+    /// Unlike `write`, this doesn't go through `std::io` or `core::fmt`.
+    pub fn push_byte(&mut self, b: u8) -> bool {
+        if self.pos == COUNT {
+            return false;
+        }
+
+        self.buffer[self.pos] = b;
+        self.pos += 1;
+        true
+    }
+
+    /// Append `s`, returning false (and writing nothing) if it doesn't fit.
+    pub fn push_str(&mut self, s: &str) -> bool {
+        let end = self.pos + s.len();
+        if end > COUNT {
+            return false;
+        }
+
+        self.buffer[self.pos..end].copy_from_slice(s.as_bytes());
+        self.pos = end;
+        true
+    }
+
+    /// Append as much of `data` as fits, returning the number of bytes
+    /// appended, 0 once the buffer is full.
+    ///
+    /// Unlike `write`, this never fails whatever the [`OverflowPolicy`].
+    pub fn extend_from_slice(&mut self, data: &[u8]) -> usize {
+        let len = data.len().min(COUNT - self.pos);
+        self.buffer[self.pos..self.pos + len].copy_from_slice(&data[..len]);
+        self.pos += len;
+        len
+    }
 }
 
 impl<const COUNT: usize> Default for FixedBufferWriter<COUNT> {
@@ -55,7 +134,18 @@ impl<const COUNT: usize> Write for FixedBufferWriter<COUNT> {
         let end = self.pos + buf.len();
 
         if end > COUNT {
-            return Err(std::io::Error::from(ErrorKind::WriteZero));
+            match self.policy {
+                OverflowPolicy::Truncate => {
+                    let fits = COUNT - self.pos;
+                    self.buffer[self.pos..].copy_from_slice(&buf[..fits]);
+                    self.pos = COUNT;
+                    return Ok(buf.len());
+                }
+                OverflowPolicy::DebugPanic if cfg!(debug_assertions) => {
+                    panic!("FixedBufferWriter<{COUNT}> overflowed");
+                }
+                _ => return Err(std::io::Error::from(ErrorKind::WriteZero)),
+            }
         }
 
         self.buffer[self.pos..end].copy_from_slice(buf);
@@ -74,7 +164,7 @@ mod test {
     use std::io::ErrorKind;
     use std::io::Write;
 
-    use super::FixedBufferWriter;
+    use super::{FixedBufferWriter, OverflowPolicy};
 
     #[test]
     fn writes_formatted_bytes_into_buffer() {
@@ -118,4 +208,94 @@ mod test {
         assert_eq!(w.pos, 4);
         assert_eq!(&w.buffer, b"1234");
     }
+
+    #[test]
+    fn truncating_drops_the_overflow() {
+        let mut w = FixedBufferWriter::<4>::truncating();
+
+        write!(&mut w, "{}", 123456).unwrap();
+        assert_eq!(w.buffer(), b"1234");
+        // Full, later writes are dropped too.
+        w.write_all(b"x").unwrap();
+        assert_eq!(w.buffer(), b"1234");
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "overflowed")]
+    fn debug_panic_panics_in_debug_builds() {
+        let mut w =
+            FixedBufferWriter::<2>::with_policy(OverflowPolicy::DebugPanic);
+        let _ = w.write(b"abc");
+    }
+
+    #[test]
+    fn from_buffer_overwrites_from_the_start() {
+        let mut w = FixedBufferWriter::from_buffer(*b"xxxxx");
+
+        w.write_all(b"ab").unwrap();
+        assert_eq!(w.buffer(), b"ab");
+        assert_eq!(w.into_inner(), (*b"abxxx", 2));
+    }
+
+    #[test]
+    fn into_inner_returns_the_written_length() {
+        let w = FixedBufferWriter::<4>::new();
+        assert_eq!(w.into_inner(), ([0; 4], 0));
+
+        let mut w = FixedBufferWriter::<4>::new();
+        write!(&mut w, "{}", 123).unwrap();
+        let (buffer, len) = w.into_inner();
+        assert_eq!(&buffer[..len], b"123");
+    }
+
+    #[test]
+    fn push_byte_reports_fit() {
+        let mut w = FixedBufferWriter::<2>::new();
+
+        assert!(w.push_byte(b'a'));
+        assert!(w.push_byte(b'b'));
+        assert!(!w.push_byte(b'c'));
+        assert_eq!(w.buffer(), b"ab");
+    }
+
+    #[test]
+    fn push_str_reports_fit() {
+        let mut w = FixedBufferWriter::<5>::new();
+
+        assert!(w.push_str("abc"));
+        // doesn't fit, nothing is written
+        assert!(!w.push_str("def"));
+        assert_eq!(w.buffer(), b"abc");
+        assert!(w.push_str("de"));
+        assert_eq!(w.buffer(), b"abcde");
+    }
+
+    #[test]
+    fn extend_from_slice_fits() {
+        let mut w = FixedBufferWriter::<4>::new();
+
+        assert_eq!(w.extend_from_slice(b"ab"), 2);
+        assert_eq!(w.extend_from_slice(b"cd"), 2);
+        assert_eq!(w.buffer(), b"abcd");
+    }
+
+    #[test]
+    fn extend_from_slice_partially_fits() {
+        let mut w = FixedBufferWriter::<4>::new();
+
+        w.write_all(b"ab").unwrap();
+        assert_eq!(w.extend_from_slice(b"cdef"), 2);
+        assert_eq!(w.buffer(), b"abcd");
+    }
+
+    #[test]
+    fn extend_from_slice_when_full() {
+        let mut w = FixedBufferWriter::<2>::new();
+
+        w.write_all(b"ab").unwrap();
+        assert_eq!(w.extend_from_slice(b"c"), 0);
+        assert_eq!(w.extend_from_slice(b""), 0);
+        assert_eq!(w.buffer(), b"ab");
+    }
 }
@@ -0,0 +1,843 @@
+//! Handles to spawned containers.
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{Error, ErrorKind, Read},
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd},
+        unix::net::UnixStream,
+    },
+    path::{Path, PathBuf},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    cgroup::{OomCounts, OomSource},
+    clone3::Child,
+    criu,
+    error::CuriumError,
+    oci::{self, Status},
+    pty,
+    trace::event,
+};
+
+/// The exit status of a container's init process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExitStatus {
+    /// The exit code, if the process exited normally.
+    pub code: Option<i32>,
+    /// The signal that terminated the process, if it was killed.
+    pub signal: Option<i32>,
+}
+
+impl ExitStatus {
+    /// Was termination successful?
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+
+    fn from_siginfo(info: &libc::siginfo_t) -> Self {
+        // SAFETY: waitid filled the siginfo for a SIGCHLD.
+        let status = unsafe { info.si_status() };
+
+        match info.si_code {
+            libc::CLD_EXITED => Self {
+                code: Some(status),
+                signal: None,
+            },
+            _ => Self {
+                code: None,
+                signal: Some(status),
+            },
+        }
+    }
+}
+
+/// The resources used by a container, see [`ContainerHandle::wait_with_usage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    /// The CPU time spent in user mode.
+    pub user_time: Duration,
+    /// The CPU time spent in the kernel.
+    pub sys_time: Duration,
+    /// The largest resident set size in bytes, of the init process or of
+    /// the largest of the descendants it waited for.
+    pub max_rss: u64,
+}
+
+impl Usage {
+    fn from_rusage(usage: &libc::rusage) -> Self {
+        let duration = |time: libc::timeval| {
+            Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000)
+        };
+
+        Self {
+            user_time: duration(usage.ru_utime),
+            sys_time: duration(usage.ru_stime),
+            // In kilobytes, see getrusage(2).
+            max_rss: usage.ru_maxrss as u64 * 1024,
+        }
+    }
+}
+
+/// The exit status and captured output of a container, see
+/// [`ContainerHandle::wait_with_output`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Output {
+    pub status: ExitStatus,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+/// How [`ContainerHandle::stop`] stopped the container.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopOutcome {
+    /// The container exited within the grace period.
+    Graceful(ExitStatus),
+    /// The container had to be killed with `SIGKILL`.
+    Killed(ExitStatus),
+}
+
+/// Why a container run with [`Container::run_outcome`] ended: its command
+/// failing is told from it never starting.
+///
+/// [`Container::run_outcome`]: crate::Container::run_outcome
+#[derive(Debug)]
+pub enum Outcome {
+    /// The command ran and exited with the code.
+    Exited(i32),
+    /// The command ran and was killed by the signal.
+    Signaled(i32),
+    /// The command didn't start: setting up the container or exec failed.
+    StartupFailed(CuriumError),
+}
+
+impl From<ExitStatus> for Outcome {
+    fn from(status: ExitStatus) -> Self {
+        match status {
+            ExitStatus {
+                code: Some(code), ..
+            } => Outcome::Exited(code),
+            ExitStatus { signal, .. } => {
+                Outcome::Signaled(signal.unwrap_or_default())
+            }
+        }
+    }
+}
+
+/// Something that happened to a container, see [`ContainerHandle::events`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// The cgroup hit its memory limit and reclaim failed.
+    Oom,
+    /// The OOM killer killed a process of the cgroup.
+    OomKill,
+    /// The container exited and was reaped, the last event.
+    Exited(ExitStatus),
+}
+
+/// An iterator blocking for the next [`Event`] of a container, returned by
+/// [`ContainerHandle::events`].
+///
+/// It ends after [`Event::Exited`], or after the first error.
+pub struct Events<'a> {
+    handle: &'a mut ContainerHandle,
+    counts: OomCounts,
+    pending: VecDeque<Event>,
+    done: bool,
+}
+
+impl Events<'_> {
+    /// Queue the OOM events counted since the last read.
+    fn read_counts(&mut self) -> Result<(), Error> {
+        let Some(source) = &mut self.handle.oom_source else {
+            return Ok(());
+        };
+        let counts = source.counts()?;
+        let ooms = counts.ooms.saturating_sub(self.counts.ooms);
+        let kills = counts.kills.saturating_sub(self.counts.kills);
+        self.pending.extend((0..ooms).map(|_| Event::Oom));
+        self.pending.extend((0..kills).map(|_| Event::OomKill));
+        self.counts = counts;
+        Ok(())
+    }
+
+    /// Block until the container exits or its cgroup changes, queueing what
+    /// happened.
+    fn poll(&mut self) -> Result<(), Error> {
+        let mut fds = vec![libc::pollfd {
+            fd: self.handle.pidfd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        }];
+        if let Some(source) = &self.handle.oom_source {
+            let (fd, events) = source.pollfd();
+            fds.push(libc::pollfd {
+                fd,
+                events,
+                revents: 0,
+            });
+        }
+
+        loop {
+            let ret = unsafe {
+                libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1)
+            };
+            if ret >= 0 {
+                break;
+            }
+            let err = Error::last_os_error();
+            if err.kind() != ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+
+        let exited = fds[0].revents != 0;
+        // The kill that made the container exit is counted by then.
+        if exited || fds.get(1).is_some_and(|fd| fd.revents != 0) {
+            self.read_counts()?;
+        }
+        if exited {
+            self.pending.push_back(Event::Exited(self.handle.wait()?));
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for Events<'_> {
+    type Item = Result<Event, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                self.done |= matches!(event, Event::Exited(_));
+                return Some(Ok(event));
+            }
+            if self.done {
+                return None;
+            }
+            if let Some(status) = self.handle.status {
+                self.done = true;
+                return Some(Ok(Event::Exited(status)));
+            }
+            if let Err(err) = self.poll() {
+                self.done = true;
+                self.pending.clear();
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+/// A running (or exited) container.
+///
+/// The container is referred to by a pidfd, so it's not affected by pid
+/// reuse.
+pub struct ContainerHandle {
+    child: Child,
+    pidfd: OwnedFd,
+    status: Option<ExitStatus>,
+    usage: Option<Usage>,
+    pidfile: Option<PathBuf>,
+    kill_on_drop: bool,
+    stdout: Option<OwnedFd>,
+    stderr: Option<OwnedFd>,
+    pty: Option<OwnedFd>,
+    seccomp_listener: Option<OwnedFd>,
+    oom_source: Option<OomSource>,
+    exec_sock: Option<UnixStream>,
+}
+
+impl ContainerHandle {
+    pub(crate) fn new(child: Child, pidfd: OwnedFd) -> Self {
+        Self {
+            child,
+            pidfd,
+            status: None,
+            usage: None,
+            pidfile: None,
+            kill_on_drop: false,
+            stdout: None,
+            stderr: None,
+            pty: None,
+            seccomp_listener: None,
+            oom_source: None,
+            exec_sock: None,
+        }
+    }
+
+    /// Set the read ends of the captured stdout and stderr.
+    pub(crate) fn with_output(
+        mut self,
+        stdout: OwnedFd,
+        stderr: OwnedFd,
+    ) -> Self {
+        self.stdout = Some(stdout);
+        self.stderr = Some(stderr);
+        self
+    }
+
+    /// Set the master end of the container's terminal.
+    pub(crate) fn with_pty(mut self, master: OwnedFd) -> Self {
+        self.pty = Some(master);
+        self
+    }
+
+    /// Set the listener of the container's seccomp filter.
+    pub(crate) fn with_seccomp_listener(mut self, listener: OwnedFd) -> Self {
+        self.seccomp_listener = Some(listener);
+        self
+    }
+
+    /// Set the OOM events of the container's cgroup, for
+    /// [`ContainerHandle::events`].
+    pub(crate) fn with_oom_source(mut self, source: OomSource) -> Self {
+        self.oom_source = Some(source);
+        self
+    }
+
+    /// Set our end of the socket of a container stopped before exec, the
+    /// child's end is closed when it executes the command.
+    pub(crate) fn with_exec_sock(mut self, sock: UnixStream) -> Self {
+        self.exec_sock = Some(sock);
+        self
+    }
+
+    /// Set the pidfile removed when the container is reaped.
+    pub(crate) fn with_pidfile(mut self, path: PathBuf) -> Self {
+        self.pidfile = Some(path);
+        self
+    }
+
+    /// SIGKILL and reap the container when the handle is dropped before it
+    /// was reaped, like tokio's `Command::kill_on_drop`.
+    ///
+    /// Off by default, so a dropped handle leaves the container running (and
+    /// a zombie until the parent exits).
+    pub fn kill_on_drop(mut self, kill_on_drop: bool) -> Self {
+        self.kill_on_drop = kill_on_drop;
+        self
+    }
+
+    /// Take the master end of the container's terminal, if it has one (see
+    /// [`Container::tty`]), to drive it without [`ContainerHandle::attach`].
+    ///
+    /// [`Container::tty`]: crate::Container::tty
+    pub fn take_pty(&mut self) -> Option<OwnedFd> {
+        self.pty.take()
+    }
+
+    /// Take the listener of the container's seccomp filter, if it has one
+    /// (see [`Container::seccomp_notify`]), to handle its notifications with
+    /// [`seccomp::recv`] and [`seccomp::respond`].
+    ///
+    /// The notified syscalls block until answered, also once the listener is
+    /// taken. Closing the listener makes them fail with `ENOSYS`.
+    ///
+    /// [`Container::seccomp_notify`]: crate::Container::seccomp_notify
+    /// [`seccomp::recv`]: crate::seccomp::recv
+    /// [`seccomp::respond`]: crate::seccomp::respond
+    pub fn take_seccomp_listener(&mut self) -> Option<OwnedFd> {
+        self.seccomp_listener.take()
+    }
+
+    /// Attach our terminal to the container's until the container closes it,
+    /// then wait for the container.
+    ///
+    /// Our stdin is put in raw mode when it's a terminal (and restored when
+    /// this returns, even with a panic) so every key reaches the container,
+    /// and the container's window size follows ours on `SIGWINCH`. Fails with
+    /// `InvalidInput` if the container has no terminal.
+    pub fn attach(&mut self) -> Result<ExitStatus, Error> {
+        let Some(master) = self.pty.take() else {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "the container has no terminal",
+            ));
+        };
+        pty::attach(&master)?;
+        self.wait()
+    }
+
+    /// Take the read end of the container's stdout, if it's captured.
+    pub fn take_stdout(&mut self) -> Option<OwnedFd> {
+        self.stdout.take()
+    }
+
+    /// Take the read end of the container's stderr, if it's captured.
+    pub fn take_stderr(&mut self) -> Option<OwnedFd> {
+        self.stderr.take()
+    }
+
+    /// Feed the captured stdout to `callback` as it arrives, from a new
+    /// reader thread. Returns `None` if stdout isn't captured (or was taken).
+    ///
+    /// The callback runs on the reader thread, hence `Send`, and gets the
+    /// chunks as they are read (up to 8 KiB), not lines. The thread exits at
+    /// EOF, once every process of the container closed its stdout, so join it
+    /// after [`ContainerHandle::wait`] to wait for the last chunk.
+    pub fn on_stdout<F>(
+        &mut self,
+        callback: F,
+    ) -> Option<JoinHandle<Result<(), Error>>>
+    where
+        F: FnMut(&[u8]) + Send + 'static,
+    {
+        self.stdout.take().map(|fd| stream(fd, callback))
+    }
+
+    /// Feed the captured stderr to `callback`, like
+    /// [`ContainerHandle::on_stdout`].
+    pub fn on_stderr<F>(
+        &mut self,
+        callback: F,
+    ) -> Option<JoinHandle<Result<(), Error>>>
+    where
+        F: FnMut(&[u8]) + Send + 'static,
+    {
+        self.stderr.take().map(|fd| stream(fd, callback))
+    }
+
+    /// Read the captured stdout and stderr to the end, then wait for the
+    /// container to exit.
+    ///
+    /// Both pipes are drained together with poll(2), so the container can't
+    /// block on a full pipe while we wait for the other one. A stream that
+    /// isn't captured (or was taken) is empty.
+    pub fn wait_with_output(mut self) -> Result<Output, Error> {
+        let mut streams =
+            [(self.stdout.take(), vec![]), (self.stderr.take(), vec![])];
+        drain(&mut streams)?;
+        let [(_, stdout), (_, stderr)] = streams;
+
+        Ok(Output {
+            status: self.wait()?,
+            stdout,
+            stderr,
+        })
+    }
+
+    /// The pid of the container's init process, as seen from the parent.
+    pub fn pid(&self) -> i64 {
+        self.child.pid
+    }
+
+    /// The process created for the container's init.
+    ///
+    /// Its pidfd, if any, is owned by the handle and closed with it.
+    pub fn child(&self) -> Child {
+        self.child
+    }
+
+    /// Wait for the container to exit and reap it.
+    ///
+    /// Once reaped, the cached status is returned on later calls.
+    pub fn wait(&mut self) -> Result<ExitStatus, Error> {
+        Ok(self
+            .waitid(libc::WEXITED)?
+            .expect("waitid without WNOHANG waits for the exit"))
+    }
+
+    /// Wait for the container like [`ContainerHandle::wait`], and return the
+    /// resources it used too.
+    ///
+    /// The usage is the one of the init process and of the descendants it
+    /// waited for, which includes every process of the container when it
+    /// reaps its orphans (as an init should). Processes still running when
+    /// the init exits are killed and reaped by the kernel, but not counted.
+    pub fn wait_with_usage(&mut self) -> Result<(ExitStatus, Usage), Error> {
+        let status = self.wait()?;
+        let usage = self.usage.expect("the usage is set with the status");
+        Ok((status, usage))
+    }
+
+    /// Stream the events of the container as they happen, blocking for each:
+    /// the OOMs of its cgroup, then its exit once it's reaped.
+    ///
+    /// OOMs are only reported for a container with a [`Container::cgroup`]
+    /// whose memory controller is enabled, and counted from the call. With
+    /// cgroup v1 a kill is only told from an OOM since Linux 4.13. A
+    /// container which was already reaped yields its exit alone.
+    ///
+    /// [`Container::cgroup`]: crate::Container::cgroup
+    pub fn events(&mut self) -> Events<'_> {
+        let counts = match &mut self.oom_source {
+            Some(source) => source.counts().unwrap_or_default(),
+            None => OomCounts::default(),
+        };
+        Events {
+            handle: self,
+            counts,
+            pending: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    /// Send `signal` to the container's init process.
+    pub fn signal(&self, signal: i32) -> Result<(), Error> {
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_pidfd_send_signal,
+                self.pidfd.as_raw_fd(),
+                signal,
+                std::ptr::null::<libc::siginfo_t>(),
+                0,
+            )
+        };
+        if ret != 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Stop the container: send `SIGTERM`, wait up to `grace` for it to exit
+    /// and `SIGKILL` it otherwise.
+    ///
+    /// The init process of a pid namespace only receives signals it has a
+    /// handler for, so a command that doesn't handle `SIGTERM` is always
+    /// killed.
+    pub fn stop(&mut self, grace: Duration) -> Result<StopOutcome, Error> {
+        if let Some(status) = self.status {
+            return Ok(StopOutcome::Graceful(status));
+        }
+
+        self.signal(libc::SIGTERM)?;
+
+        let deadline = Instant::now() + grace;
+        let mut pollfd = libc::pollfd {
+            fd: self.pidfd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let timeout = timeout.as_millis().min(i32::MAX as u128) as i32;
+
+            let ret = unsafe { libc::poll(&mut pollfd, 1, timeout) };
+            match ret {
+                0 => break,
+                1.. => return Ok(StopOutcome::Graceful(self.wait()?)),
+                _ => {
+                    let err = Error::last_os_error();
+                    if err.kind() != ErrorKind::Interrupted {
+                        return Err(err);
+                    }
+                }
+            }
+        }
+
+        self.signal(libc::SIGKILL)?;
+        Ok(StopOutcome::Killed(self.wait()?))
+    }
+
+    /// Reap the container if it has exited, without blocking.
+    ///
+    /// Meant to be called once the pidfd (see [`AsRawFd`]) is readable.
+    pub fn reap(&mut self) -> Result<Option<ExitStatus>, Error> {
+        self.waitid(libc::WEXITED | libc::WNOHANG)
+    }
+
+    /// Checkpoint the container with criu into the images directory `dir`,
+    /// to be restored with [`Container::restore_from_checkpoint`]. criu must
+    /// be installed and we must be root.
+    ///
+    /// The container is frozen and dumped, then killed and reaped, its exit
+    /// status is returned. It keeps running if the dump fails, the reason is
+    /// in `dump.log` in `dir`.
+    ///
+    /// [`Container::restore_from_checkpoint`]:
+    ///     crate::Container::restore_from_checkpoint
+    pub fn checkpoint<P: AsRef<Path>>(
+        &mut self,
+        dir: P,
+    ) -> Result<ExitStatus, Error> {
+        if self.reap()?.is_some() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "the container has exited",
+            ));
+        }
+        let dir = dir.as_ref();
+        criu::run(criu::dump_command(self.child.pid, dir), dir)?;
+        event!(pid = self.child.pid, ?dir, "checkpointed container");
+        self.wait()
+    }
+
+    /// The OCI runtime state of the container named `id`, created from its
+    /// `bundle` directory. A container which exited is reaped.
+    ///
+    /// The container is [`Status::Created`] until its init executes the
+    /// command, i.e. from the stop of [`Container::stop_before_exec`] until
+    /// it's resumed and executes.
+    ///
+    /// [`Container::stop_before_exec`]: crate::Container::stop_before_exec
+    pub fn oci_state(
+        &mut self,
+        id: &str,
+        bundle: &str,
+    ) -> Result<oci::State, Error> {
+        if self.reap()?.is_some() {
+            return Ok(oci::State::new(id, Status::Stopped, None, bundle));
+        }
+
+        let status = match self.exec_sock.is_some() && !self.executed()? {
+            true => Status::Created,
+            false => Status::Running,
+        };
+        Ok(oci::State::new(id, status, Some(self.child.pid), bundle))
+    }
+
+    /// Whether a container returned stopped before exec executed the
+    /// command, once its end of the socket is closed after READY_TO_EXEC.
+    fn executed(&mut self) -> Result<bool, Error> {
+        let Some(sock) = &self.exec_sock else {
+            return Ok(true);
+        };
+        let mut buf = [0u8; 8];
+        loop {
+            let ret = unsafe {
+                libc::recv(
+                    sock.as_raw_fd(),
+                    buf.as_mut_ptr().cast(),
+                    buf.len(),
+                    libc::MSG_DONTWAIT,
+                )
+            };
+            match ret {
+                0 => break,
+                1.. => continue,
+                _ => {
+                    let err = Error::last_os_error();
+                    match err.kind() {
+                        ErrorKind::WouldBlock => return Ok(false),
+                        ErrorKind::Interrupted => continue,
+                        _ => return Err(err),
+                    }
+                }
+            }
+        }
+        self.exec_sock = None;
+        Ok(true)
+    }
+
+    /// Wait for the container to stop or exit without reaping it, returning
+    /// true if it stopped.
+    pub(crate) fn wait_stopped(&self) -> Result<bool, Error> {
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        let ret = unsafe {
+            libc::waitid(
+                libc::P_PIDFD,
+                self.pidfd.as_raw_fd() as libc::id_t,
+                &mut info,
+                libc::WSTOPPED | libc::WEXITED | libc::WNOWAIT,
+            )
+        };
+        if ret != 0 {
+            return Err(Error::last_os_error());
+        }
+        // A traced process reports its stops as traps.
+        Ok(matches!(
+            info.si_code,
+            libc::CLD_STOPPED | libc::CLD_TRAPPED
+        ))
+    }
+
+    fn waitid(&mut self, options: i32) -> Result<Option<ExitStatus>, Error> {
+        if let Some(status) = self.status {
+            return Ok(Some(status));
+        }
+
+        // The waitid syscall also returns the usage, unlike the libc
+        // wrapper.
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        loop {
+            let ret = unsafe {
+                libc::syscall(
+                    libc::SYS_waitid,
+                    libc::P_PIDFD,
+                    self.pidfd.as_raw_fd(),
+                    &mut info,
+                    options,
+                    &mut usage,
+                )
+            };
+            if ret != 0 {
+                return Err(Error::last_os_error());
+            }
+
+            // With WNOHANG, a zero pid means the container is still running.
+            if unsafe { info.si_pid() } == 0 {
+                return Ok(None);
+            }
+            // The tracer is told about the stops of a traced container (see
+            // `Container::stop_before_exec`) even without WSTOPPED.
+            match info.si_code {
+                libc::CLD_TRAPPED | libc::CLD_STOPPED
+                    if options & libc::WNOHANG != 0 =>
+                {
+                    return Ok(None);
+                }
+                libc::CLD_TRAPPED | libc::CLD_STOPPED => continue,
+                _ => break,
+            }
+        }
+
+        let status = ExitStatus::from_siginfo(&info);
+        event!(pid = self.child.pid, ?status, "container exited");
+        self.status = Some(status);
+        self.usage = Some(Usage::from_rusage(&usage));
+        if let Some(path) = self.pidfile.take() {
+            let _ = std::fs::remove_file(path);
+        }
+        Ok(Some(status))
+    }
+}
+
+impl Drop for ContainerHandle {
+    fn drop(&mut self) {
+        // The signal goes through the pidfd, so it can't hit a reused pid.
+        if self.kill_on_drop && self.status.is_none() {
+            let _ = self.signal(libc::SIGKILL);
+            let _ = self.wait();
+        }
+    }
+}
+
+/// The pidfd of the container, to be registered with poll/epoll.
+///
+/// The pidfd becomes readable when the container exits, and stays readable
+/// (level-triggered) until the process is reaped, see
+/// [`ContainerHandle::reap`].
+impl AsRawFd for ContainerHandle {
+    fn as_raw_fd(&self) -> RawFd {
+        self.pidfd.as_raw_fd()
+    }
+}
+
+impl AsFd for ContainerHandle {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.pidfd.as_fd()
+    }
+}
+
+/// Read `fd` to the end on a new thread, passing each chunk to `callback`.
+fn stream<F>(fd: OwnedFd, mut callback: F) -> JoinHandle<Result<(), Error>>
+where
+    F: FnMut(&[u8]) + Send + 'static,
+{
+    std::thread::spawn(move || {
+        let mut file = File::from(fd);
+        let mut buf = [0u8; 8192];
+        loop {
+            match file.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(n) => callback(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    })
+}
+
+/// Read every stream to the end into its buffer, the streams are closed once
+/// they reach EOF.
+fn drain(streams: &mut [(Option<OwnedFd>, Vec<u8>)]) -> Result<(), Error> {
+    let mut buf = [0u8; 8192];
+    loop {
+        let mut fds: Vec<libc::pollfd> = streams
+            .iter()
+            .filter_map(|(fd, _)| fd.as_ref())
+            .map(|fd| libc::pollfd {
+                fd: fd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect();
+        if fds.is_empty() {
+            return Ok(());
+        }
+
+        let ret = unsafe {
+            libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1)
+        };
+        if ret < 0 {
+            let err = Error::last_os_error();
+            if err.kind() == ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+
+        // The pollfds are in the same order as the open streams.
+        let open = streams.iter_mut().filter(|(fd, _)| fd.is_some());
+        for ((fd, output), pollfd) in open.zip(&fds) {
+            if pollfd.revents == 0 {
+                continue;
+            }
+
+            let raw = fd.as_ref().expect("only open streams").as_raw_fd();
+            let n =
+                unsafe { libc::read(raw, buf.as_mut_ptr().cast(), buf.len()) };
+            match n {
+                0 => *fd = None,
+                1.. => output.extend_from_slice(&buf[..n as usize]),
+                _ => {
+                    let err = Error::last_os_error();
+                    if err.kind() != ErrorKind::Interrupted {
+                        return Err(err);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Wait for the first of `handles` to exit, returning its index and status.
+///
+/// All the pidfds are polled with a single poll(2), a pidfd becomes readable
+/// when its process exits. A handle that was already reaped is returned
+/// immediately.
+pub fn wait_any(
+    handles: &mut [ContainerHandle],
+) -> Result<(usize, ExitStatus), Error> {
+    if handles.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, "no handles to wait"));
+    }
+
+    if let Some(index) = handles.iter().position(|h| h.status.is_some()) {
+        return Ok((index, handles[index].wait()?));
+    }
+
+    let mut fds: Vec<libc::pollfd> = handles
+        .iter()
+        .map(|handle| libc::pollfd {
+            fd: handle.pidfd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        })
+        .collect();
+
+    loop {
+        let ret = unsafe {
+            libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1)
+        };
+        if ret < 0 {
+            let err = Error::last_os_error();
+            if err.kind() == ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        break;
+    }
+
+    let index = fds
+        .iter()
+        .position(|fd| fd.revents != 0)
+        .expect("poll returned with a ready pidfd");
+
+    Ok((index, handles[index].wait()?))
+}
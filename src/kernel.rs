@@ -0,0 +1,100 @@
+//! Runtime probes for the kernel features this crate relies on.
+
+use std::io::Error;
+
+use crate::clone3::CLONE_INTO_CGROUP;
+
+pub(crate) const CGROUP2_SUPER_MAGIC: i64 = 0x63677270;
+
+/// The kernel features available on the running host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KernelSupport {
+    /// clone3(2), Linux 5.3.
+    pub clone3: bool,
+    /// close_range(2), Linux 5.9.
+    pub close_range: bool,
+    /// pidfd_open(2), Linux 5.3.
+    pub pidfd: bool,
+    /// `CLONE_INTO_CGROUP`, Linux 5.7.
+    pub clone_into_cgroup: bool,
+    /// A cgroup v2 hierarchy mounted at `/sys/fs/cgroup`.
+    pub cgroup_v2: bool,
+}
+
+/// Probe the kernel for the features used when spawning a container.
+///
+/// Every probe is a syscall with arguments the kernel rejects before doing
+/// anything, so the error tells if the syscall (or flag) exists: `ENOSYS`
+/// means it doesn't.
+pub fn kernel_support() -> KernelSupport {
+    KernelSupport {
+        clone3: probe_clone3(),
+        close_range: probe_close_range(),
+        pidfd: probe_pidfd(),
+        clone_into_cgroup: probe_clone_into_cgroup(),
+        cgroup_v2: probe_cgroup_v2(),
+    }
+}
+
+/// Return the errno of the last failed syscall.
+fn errno() -> Option<i32> {
+    Error::last_os_error().raw_os_error()
+}
+
+fn probe_clone3() -> bool {
+    // A size of 0 is smaller than any version of clone_args: EINVAL.
+    let ret =
+        unsafe { libc::syscall(libc::SYS_clone3, std::ptr::null::<u8>(), 0) };
+    ret < 0 && errno() != Some(libc::ENOSYS)
+}
+
+fn probe_close_range() -> bool {
+    // first > last: EINVAL.
+    let ret = unsafe { libc::syscall(libc::SYS_close_range, u32::MAX, 0, 0) };
+    ret < 0 && errno() != Some(libc::ENOSYS)
+}
+
+fn probe_pidfd() -> bool {
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, libc::getpid(), 0) };
+    if fd < 0 {
+        return false;
+    }
+    unsafe { libc::close(fd as i32) };
+    true
+}
+
+fn probe_clone_into_cgroup() -> bool {
+    // Kernels without CLONE_INTO_CGROUP reject the unknown flag with EINVAL.
+    // Newer ones get past the flag validation and fail to store the pidfd
+    // at a bad address with EFAULT, before the new process can run.
+    //
+    // Note: A kernel built without cgroups accepts the flag and ignores it.
+    let clone_args = libc::clone_args {
+        flags: CLONE_INTO_CGROUP | libc::CLONE_PIDFD as u64,
+        pidfd: u64::MAX,
+        child_tid: 0,
+        parent_tid: 0,
+        exit_signal: 0,
+        stack: 0,
+        stack_size: 0,
+        tls: 0,
+        set_tid: 0,
+        set_tid_size: 0,
+        cgroup: i32::MAX as u64,
+    };
+
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_clone3,
+            &clone_args as *const libc::clone_args,
+            size_of::<libc::clone_args>(),
+        )
+    };
+    ret < 0 && matches!(errno(), Some(libc::EFAULT | libc::EBADF))
+}
+
+fn probe_cgroup_v2() -> bool {
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    let ret = unsafe { libc::statfs(c"/sys/fs/cgroup".as_ptr(), &mut stat) };
+    ret == 0 && stat.f_type == CGROUP2_SUPER_MAGIC
+}
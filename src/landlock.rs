@@ -0,0 +1,188 @@
+//! Landlock filesystem restrictions.
+//!
+//! The ruleset is described with [`LandlockRuleset`], created in the parent
+//! (which is allowed to allocate and open files) and only enforced in the
+//! child, see landlock(7).
+
+use std::{
+    ffi::{CString, OsStr, OsString},
+    io::{Error, ErrorKind},
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+};
+
+pub const ACCESS_FS_EXECUTE: u64 = 1 << 0;
+pub const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+pub const ACCESS_FS_READ_FILE: u64 = 1 << 2;
+pub const ACCESS_FS_READ_DIR: u64 = 1 << 3;
+pub const ACCESS_FS_REMOVE_DIR: u64 = 1 << 4;
+pub const ACCESS_FS_REMOVE_FILE: u64 = 1 << 5;
+pub const ACCESS_FS_MAKE_CHAR: u64 = 1 << 6;
+pub const ACCESS_FS_MAKE_DIR: u64 = 1 << 7;
+pub const ACCESS_FS_MAKE_REG: u64 = 1 << 8;
+pub const ACCESS_FS_MAKE_SOCK: u64 = 1 << 9;
+pub const ACCESS_FS_MAKE_FIFO: u64 = 1 << 10;
+pub const ACCESS_FS_MAKE_BLOCK: u64 = 1 << 11;
+pub const ACCESS_FS_MAKE_SYM: u64 = 1 << 12;
+
+/// All the access rights of the first Landlock ABI.
+pub const ACCESS_FS_ALL: u64 = (1 << 13) - 1;
+
+/// Read-only access to files and directories.
+pub const ACCESS_FS_READ: u64 =
+    ACCESS_FS_EXECUTE | ACCESS_FS_READ_FILE | ACCESS_FS_READ_DIR;
+
+const LANDLOCK_RULE_PATH_BENEATH: i32 = 1;
+
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+}
+
+// The kernel declares this struct as packed.
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+/// A description of a Landlock ruleset.
+///
+/// Every access right in `handled_access` is denied unless a rule allows it
+/// beneath a path.
+///
+/// # Example
+/// ```no_run
+/// use curium::landlock::{self, LandlockRuleset};
+///
+/// let rules = LandlockRuleset::new(landlock::ACCESS_FS_ALL)
+///     .allow("/tmp/bbox", landlock::ACCESS_FS_READ)
+///     .allow("/tmp/bbox/tmp", landlock::ACCESS_FS_ALL);
+/// ```
+pub struct LandlockRuleset {
+    handled_access: u64,
+    rules: Vec<(OsString, u64)>,
+}
+
+impl LandlockRuleset {
+    pub fn new(handled_access: u64) -> Self {
+        Self {
+            handled_access,
+            rules: vec![],
+        }
+    }
+
+    /// Allow `access` to the file hierarchy beneath `path`.
+    ///
+    /// The path is opened in the parent, so it is resolved against the host
+    /// and not against the container root.
+    pub fn allow<P: AsRef<OsStr>>(mut self, path: P, access: u64) -> Self {
+        self.rules.push((path.as_ref().to_owned(), access));
+        self
+    }
+
+    /// Create the ruleset and add all the rules to it, failing with
+    /// [`ErrorKind::InvalidInput`] if a path has a null byte.
+    ///
+    /// # Signal Safety
+    /// This function is not signal safe, it must be called in the parent.
+    pub fn create(&self) -> Result<OwnedFd, Error> {
+        let rules = self
+            .rules
+            .iter()
+            .map(|(path, access)| {
+                let path =
+                    CString::new(path.as_encoded_bytes()).map_err(|_| {
+                        Error::new(ErrorKind::InvalidInput, "null in the path")
+                    })?;
+                Ok((path, *access))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let attr = RulesetAttr {
+            handled_access_fs: self.handled_access,
+        };
+
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                &attr as *const RulesetAttr,
+                size_of::<RulesetAttr>(),
+                0,
+            )
+        };
+        if fd < 0 {
+            return Err(Error::last_os_error());
+        }
+
+        // SAFETY: landlock_create_ruleset returned a new file descriptor.
+        let ruleset = unsafe { OwnedFd::from_raw_fd(fd as RawFd) };
+
+        for (path, access) in rules {
+            let parent_fd = unsafe {
+                libc::open(path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC)
+            };
+            if parent_fd < 0 {
+                return Err(Error::last_os_error());
+            }
+            // SAFETY: open returned a new file descriptor.
+            let parent_fd = unsafe { OwnedFd::from_raw_fd(parent_fd) };
+
+            let rule = PathBeneathAttr {
+                allowed_access: access,
+                parent_fd: parent_fd.as_raw_fd(),
+            };
+
+            let ret = unsafe {
+                libc::syscall(
+                    libc::SYS_landlock_add_rule,
+                    ruleset.as_raw_fd(),
+                    LANDLOCK_RULE_PATH_BENEATH,
+                    &rule as *const PathBeneathAttr,
+                    0,
+                )
+            };
+            if ret != 0 {
+                return Err(Error::last_os_error());
+            }
+        }
+
+        Ok(ruleset)
+    }
+}
+
+/// Enforce a ruleset created by [`LandlockRuleset::create`] on the calling
+/// thread.
+///
+/// landlock_restrict_self requires `no_new_privs` (or `CAP_SYS_ADMIN`), so
+/// this sets it first.
+///
+/// # Signal Safety
+/// This function is signal safe.
+pub fn restrict_self(ruleset_fd: RawFd) -> Result<(), Error> {
+    let ret = unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) };
+    if ret != 0 {
+        return Err(Error::last_os_error());
+    }
+
+    let ret = unsafe {
+        libc::syscall(libc::SYS_landlock_restrict_self, ruleset_fd, 0)
+    };
+    if ret != 0 {
+        return Err(Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn null_in_a_path_fails_create() {
+        let rules = LandlockRuleset::new(ACCESS_FS_ALL)
+            .allow("/tmp", ACCESS_FS_READ)
+            .allow("/t\0mp", ACCESS_FS_READ);
+        let err = rules.create().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+}
@@ -1,13 +1,40 @@
 #![feature(never_type, panic_always_abort)]
 
+pub mod caps;
+pub mod cgroup;
 pub mod clone3;
 pub mod close_range;
 pub mod container;
+mod criu;
+mod elf;
 pub mod error;
+pub mod fd_channel;
 pub mod fixed_buf_writer;
+pub mod handle;
+pub mod kernel;
+pub mod landlock;
+pub mod lsm;
 pub mod mount;
+pub mod mount_spec;
+pub mod mountinfo;
+pub mod namespace;
+mod net;
+pub mod oci;
+pub mod plan;
+pub mod pty;
+pub mod seccomp;
+#[cfg(test)]
+mod testing;
+mod trace;
 pub mod uid_map;
 
+pub use caps::Capability;
+pub use cgroup::{CgroupBackend, IoLimit};
 pub use container::*;
 pub use error::*;
-pub use fixed_buf_writer::FixedBufferWriter;
+pub use fixed_buf_writer::{FixedBufferWriter, OverflowPolicy};
+pub use handle::*;
+pub use kernel::{KernelSupport, kernel_support};
+pub use mount::AtimeMode;
+pub use mount_spec::MountSpec;
+pub use namespace::{Namespaces, unshare_namespaces};
@@ -0,0 +1,55 @@
+//! Linux security module labels applied on exec.
+//!
+//! The label of the next exec is written to a `/proc/self/attr` file, the
+//! kernel applies it when the container's command is executed.
+
+use std::{ffi::CStr, io::Error};
+
+/// The exec attribute of AppArmor, under its own directory since Linux 5.8
+/// so it doesn't conflict with other LSMs.
+pub(crate) const APPARMOR_EXEC_ATTR: [&CStr; 2] =
+    [c"/proc/self/attr/apparmor/exec", c"/proc/self/attr/exec"];
+
+/// SELinux only has the legacy exec attribute.
+pub(crate) const SELINUX_EXEC_ATTR: [&CStr; 1] = [c"/proc/self/attr/exec"];
+
+/// Return true if SELinux is enabled on the host, which mounts selinuxfs.
+pub fn selinux_enabled() -> bool {
+    std::path::Path::new("/sys/fs/selinux/enforce").exists()
+}
+
+/// Return true if AppArmor is enabled on the host.
+pub fn apparmor_enabled() -> bool {
+    std::fs::read("/sys/module/apparmor/parameters/enabled")
+        .is_ok_and(|enabled| enabled.starts_with(b"Y"))
+}
+
+/// Write `attr` to the first of `paths` that exists.
+///
+/// # Signal Safety
+/// This function is signal safe.
+pub(crate) fn write_attr(paths: &[&CStr], attr: &CStr) -> Result<(), Error> {
+    let mut err = Error::from_raw_os_error(libc::ENOENT);
+    for path in paths {
+        let fd = unsafe {
+            libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC)
+        };
+        if fd < 0 {
+            err = Error::last_os_error();
+            if err.raw_os_error() == Some(libc::ENOENT) {
+                continue;
+            }
+            return Err(err);
+        }
+
+        let attr = attr.to_bytes();
+        let n = unsafe { libc::write(fd, attr.as_ptr().cast(), attr.len()) };
+        let result = match n {
+            n if n < 0 => Err(Error::last_os_error()),
+            _ => Ok(()),
+        };
+        unsafe { libc::close(fd) };
+        return result;
+    }
+    Err(err)
+}
@@ -1,7 +1,42 @@
+use std::{env, fs, process};
+
 use curium::Container;
 
+/// `curium state <id> <bundle>` creates the container of the OCI bundle,
+/// prints its state once created and once stopped, like `runc state`, and
+/// exits with its code. Without arguments, run a shell in /tmp/bbox.
 fn main() {
-    Container::new("/tmp/bbox".into(), "/bin/sh")
+    let args: Vec<_> = env::args().skip(1).collect();
+    match &args[..] {
+        [] => {
+            Container::new("/tmp/bbox".into(), "/bin/sh").run().unwrap();
+        }
+        [state, id, bundle] if state == "state" => {
+            process::exit(state_of(id, bundle))
+        }
+        _ => {
+            eprintln!("usage: curium [state <id> <bundle>]");
+            process::exit(2);
+        }
+    }
+}
+
+fn state_of(id: &str, bundle: &str) -> i32 {
+    let bundle = fs::canonicalize(bundle).expect("the bundle should exist");
+    let bundle = bundle.to_str().expect("the bundle path should be utf-8");
+    let mut handle = Container::from_oci_bundle(bundle)
+        .unwrap()
+        .stop_before_exec()
         .spawn()
         .unwrap();
+    println!("{}", handle.oci_state(id, bundle).unwrap().to_json());
+
+    // We are the tracer of the stopped container.
+    let pid = handle.pid() as libc::pid_t;
+    if unsafe { libc::ptrace(libc::PTRACE_DETACH, pid, 0, 0) } != 0 {
+        panic!("{}", std::io::Error::last_os_error());
+    }
+    let status = handle.wait().unwrap();
+    println!("{}", handle.oci_state(id, bundle).unwrap().to_json());
+    status.code.unwrap_or(1)
 }
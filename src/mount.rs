@@ -1,9 +1,112 @@
 //! mount(2) and umount2 helpers.
 
-use std::{ffi::CStr, marker::PhantomData};
+use std::{
+    ffi::{CStr, CString, OsStr},
+    marker::PhantomData,
+    ops::{BitOr, BitOrAssign},
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    path::Path,
+};
+
+const MOVE_MOUNT_F_EMPTY_PATH: u32 = 0x00000004;
+
+const MOUNT_ATTR_RDONLY: u64 = 0x00000001;
+const MOUNT_ATTR_NOSUID: u64 = 0x00000002;
+const MOUNT_ATTR_NODEV: u64 = 0x00000004;
+const MOUNT_ATTR_NOEXEC: u64 = 0x00000008;
+
+/// `struct mount_attr` of mount_setattr(2).
+#[repr(C)]
+struct MountAttr {
+    attr_set: u64,
+    attr_clr: u64,
+    propagation: u64,
+    userns_fd: u64,
+}
+
+/// Flags for mount(2).
+///
+/// Some flags select the operation rather than modify it, and must not be
+/// combined: `BIND`, `MOVE`, `REMOUNT` and the propagation flags (`SHARED`,
+/// `PRIVATE`, `SLAVE`, `UNBINDABLE`) are mutually exclusive, with the
+/// exception of `REMOUNT | BIND` which changes the per-mount flags of a bind
+/// mount.
+///
+/// # Example
+/// ```no_run
+/// use curium::mount::{MountFlags, mount};
+///
+/// mount(
+///     Some(c"tmpfs"),
+///     Some(c"/tmp/bbox/tmp"),
+///     Some(c"tmpfs"),
+///     MountFlags::NODEV | MountFlags::NOSUID,
+///     Some(c"size=64m"),
+/// )
+/// .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MountFlags(u64);
+
+impl MountFlags {
+    pub const RDONLY: Self = Self(libc::MS_RDONLY);
+    pub const NOSUID: Self = Self(libc::MS_NOSUID);
+    pub const NODEV: Self = Self(libc::MS_NODEV);
+    pub const NOEXEC: Self = Self(libc::MS_NOEXEC);
+    pub const SYNCHRONOUS: Self = Self(libc::MS_SYNCHRONOUS);
+    pub const REMOUNT: Self = Self(libc::MS_REMOUNT);
+    pub const MANDLOCK: Self = Self(libc::MS_MANDLOCK);
+    pub const DIRSYNC: Self = Self(libc::MS_DIRSYNC);
+    pub const NOATIME: Self = Self(libc::MS_NOATIME);
+    pub const NODIRATIME: Self = Self(libc::MS_NODIRATIME);
+    pub const BIND: Self = Self(libc::MS_BIND);
+    pub const MOVE: Self = Self(libc::MS_MOVE);
+    pub const REC: Self = Self(libc::MS_REC);
+    pub const SILENT: Self = Self(libc::MS_SILENT);
+    pub const UNBINDABLE: Self = Self(libc::MS_UNBINDABLE);
+    pub const PRIVATE: Self = Self(libc::MS_PRIVATE);
+    pub const SLAVE: Self = Self(libc::MS_SLAVE);
+    pub const SHARED: Self = Self(libc::MS_SHARED);
+    pub const RELATIME: Self = Self(libc::MS_RELATIME);
+    pub const STRICTATIME: Self = Self(libc::MS_STRICTATIME);
+    pub const LAZYTIME: Self = Self(libc::MS_LAZYTIME);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Create flags from raw `MS_*` bits.
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    /// Return the raw `MS_*` bits.
+    pub const fn bits(&self) -> u64 {
+        self.0
+    }
+
+    /// Return true if all the flags in `other` are set.
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for MountFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for MountFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
 
 pub struct Mount<'a> {
-    flags: u64,
+    flags: MountFlags,
     target: &'a CStr,
 }
 
@@ -11,24 +114,26 @@ pub struct Mount<'a> {
 pub struct ActionSetPropagation;
 pub struct ActionBind;
 pub struct ActionCreate;
+pub struct ActionRemount;
 
 pub struct ConfiguredMount<'a, Action> {
-    flags: u64,
+    flags: MountFlags,
     target: &'a CStr,
     source: Option<&'a CStr>,
     fs_type: Option<&'a CStr>,
+    data: Option<&'a CStr>,
     _action: PhantomData<Action>,
 }
 
 impl<'a> ConfiguredMount<'a, ActionSetPropagation> {
     /// Set the propagation type for `target`
     pub fn mount(self) -> Result<(), std::io::Error> {
-        mount(None, Some(self.target), None, self.flags)
+        mount(None, Some(self.target), None, self.flags, None)
     }
 
     /// Recursively change the propagation type of all mounts in a subtree.
     pub fn recursive(mut self) -> Self {
-        self.flags |= libc::MS_REC;
+        self.flags |= MountFlags::REC;
         self
     }
 }
@@ -36,8 +141,22 @@ impl<'a> ConfiguredMount<'a, ActionSetPropagation> {
 /// manual: the filesystemtype and data arguments are ignored.
 impl<'a> ConfiguredMount<'a, ActionBind> {
     /// Bind `source` to `target`
+    ///
+    /// The kernel ignores `MS_RDONLY` when creating a bind mount, so a
+    /// read-only bind is remounted read-only after it's created.
     pub fn mount(self) -> Result<(), std::io::Error> {
-        mount(self.source, Some(self.target), None, self.flags)
+        mount(self.source, Some(self.target), None, self.flags, None)?;
+
+        if self.flags.contains(MountFlags::RDONLY) {
+            mount(
+                None,
+                Some(self.target),
+                None,
+                self.flags | MountFlags::REMOUNT,
+                None,
+            )?;
+        }
+        Ok(())
     }
 
     /// All submounts under the `source` subtree (other than unbindable mounts)
@@ -49,7 +168,7 @@ impl<'a> ConfiguredMount<'a, ActionBind> {
     /// mounted; if there are any submounts under the directory tree, they are
     /// not bind mounted.
     pub fn recursive(mut self) -> Self {
-        self.flags |= libc::MS_REC;
+        self.flags |= MountFlags::REC;
         self
     }
 }
@@ -57,8 +176,259 @@ impl<'a> ConfiguredMount<'a, ActionBind> {
 impl<'a> ConfiguredMount<'a, ActionCreate> {
     /// Create a new mount.
     pub fn mount(self) -> Result<(), std::io::Error> {
-        mount(self.source, Some(self.target), self.fs_type, self.flags)
+        mount(
+            self.source,
+            Some(self.target),
+            self.fs_type,
+            self.flags,
+            self.data,
+        )
+    }
+
+    /// Filesystem specific options, a comma separated list of `key=value`
+    /// pairs (e.g. `size=64m,mode=1777` for tmpfs).
+    pub fn data(mut self, data: &'a CStr) -> Self {
+        self.data = Some(data);
+        self
+    }
+}
+
+/// How access times are updated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AtimeMode {
+    /// Update the atime only if it's older than the mtime or ctime, or a
+    /// day old. The kernel's default.
+    #[default]
+    Relatime,
+    /// Never update the atime.
+    Noatime,
+    /// Update the atime on every access.
+    Strictatime,
+    /// `Relatime`, with the timestamps only written to disk when the inode
+    /// is written for another reason. `MS_LAZYTIME` is a flag of the
+    /// filesystem rather than of the mount, see [`Mount::remount`].
+    Lazytime,
+}
+
+impl AtimeMode {
+    /// The per-mount atime flags, which are replaced together.
+    const MASK: MountFlags = MountFlags(
+        libc::MS_NOATIME
+            | libc::MS_NODIRATIME
+            | libc::MS_RELATIME
+            | libc::MS_STRICTATIME,
+    );
+
+    fn flags(self) -> MountFlags {
+        match self {
+            AtimeMode::Relatime => MountFlags::RELATIME,
+            AtimeMode::Noatime => MountFlags::NOATIME,
+            AtimeMode::Strictatime => MountFlags::STRICTATIME,
+            AtimeMode::Lazytime => MountFlags::RELATIME | MountFlags::LAZYTIME,
+        }
+    }
+}
+
+impl<'a> ConfiguredMount<'a, ActionRemount> {
+    /// Remount `target`, adding the flags to its current ones.
+    ///
+    /// A remount clears the flags it isn't given, and in a user namespace
+    /// the flags of a mount from a more privileged namespace are locked, so
+    /// the current flags of the mount are read with statfs(2) and kept.
+    /// Without `bind` the flags of the filesystem which statfs doesn't report
+    /// (`DIRSYNC`, `LAZYTIME`) are read from `/proc/self/mountinfo` and kept
+    /// too, which needs `target` as mountinfo shows it: absolute and without
+    /// symlinks.
+    pub fn mount(self) -> Result<(), std::io::Error> {
+        let mut current = current_flags(self.target)?;
+        if !self.flags.contains(MountFlags::BIND) {
+            current |= superblock_flags(self.target)?;
+        }
+        if self.flags.0 & AtimeMode::MASK.0 != 0 {
+            current.0 &= !AtimeMode::MASK.0;
+        }
+        mount(None, Some(self.target), None, self.flags | current, None)
+    }
+
+    /// Set how access times are updated.
+    pub fn atime(mut self, mode: AtimeMode) -> Self {
+        self.flags |= mode.flags();
+        self
+    }
+}
+
+/// Read the per-mount flags of the mount at `target`.
+///
+/// # Signal Safety
+/// This function is signal safe, glibc's statvfs only reads `/proc/mounts`
+/// on kernels without the `f_flags` of statfs(2) (before Linux 2.6.36).
+fn current_flags(target: &CStr) -> Result<MountFlags, std::io::Error> {
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    if unsafe { libc::statvfs(target.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error());
     }
+    // SAFETY: statvfs filled the struct.
+    let stat_flags = unsafe { stat.assume_init() }.f_flag;
+    Ok(from_stat_flags(stat_flags))
+}
+
+/// Read the flags of the filesystem mounted at `target` from its super
+/// options in `/proc/self/mountinfo`, the last mount at `target` being the
+/// visible one. Lines that don't fit in the buffer are skipped, so a
+/// filesystem with very long options (e.g. overlayfs) keeps only the flags
+/// statfs(2) reports.
+///
+/// # Signal Safety
+/// This function is signal safe, the file is read in a fixed buffer.
+fn superblock_flags(target: &CStr) -> Result<MountFlags, std::io::Error> {
+    let fd = unsafe {
+        libc::open(
+            c"/proc/self/mountinfo".as_ptr(),
+            libc::O_RDONLY | libc::O_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: open returned a new fd.
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    // mountinfo shows the mount points without a trailing slash.
+    let target = match target.to_bytes() {
+        [rest @ .., b'/'] if !rest.is_empty() => rest,
+        target => target,
+    };
+    let mut buf = [0u8; 8192];
+    let mut len = 0;
+    // Set while reading the rest of a line that didn't fit.
+    let mut skipping = false;
+    let mut flags = MountFlags::empty();
+    loop {
+        let read = unsafe {
+            libc::read(
+                fd.as_raw_fd(),
+                buf[len..].as_mut_ptr().cast(),
+                buf.len() - len,
+            )
+        };
+        match read {
+            0 => break,
+            1.. => len += read as usize,
+            _ => {
+                let err = std::io::Error::last_os_error();
+                if err.kind() == std::io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err);
+            }
+        }
+
+        let mut start = 0;
+        while let Some(end) = buf[start..len].iter().position(|&b| b == b'\n') {
+            let line = &buf[start..start + end];
+            if !skipping && let Some(line_flags) = super_flags(line, target) {
+                flags = line_flags;
+            }
+            skipping = false;
+            start += end + 1;
+        }
+        buf.copy_within(start..len, 0);
+        len -= start;
+        if len == buf.len() {
+            skipping = true;
+            len = 0;
+        }
+    }
+    Ok(flags)
+}
+
+/// The flags of the filesystem given by a mountinfo `line`, if its mount
+/// point is `target`.
+///
+/// # Signal Safety
+/// This function is signal safe.
+fn super_flags(line: &[u8], target: &[u8]) -> Option<MountFlags> {
+    const OPTIONS: [(&[u8], MountFlags); 4] = [
+        (b"sync", MountFlags::SYNCHRONOUS),
+        (b"dirsync", MountFlags::DIRSYNC),
+        (b"mand", MountFlags::MANDLOCK),
+        (b"lazytime", MountFlags::LAZYTIME),
+    ];
+
+    let mut fields = line.split(|&b| b == b' ');
+    if !escaped_eq(fields.nth(4)?, target) {
+        return None;
+    }
+    // The optional fields end with a single "-", followed by the filesystem
+    // type, the source and the super options.
+    let mut fields = fields.skip_while(|field| *field != b"-").skip(1);
+    let options = fields.nth(2)?;
+
+    let mut flags = MountFlags::empty();
+    for option in options.split(|&b| b == b',') {
+        for (name, flag) in OPTIONS {
+            if option == name {
+                flags |= flag;
+            }
+        }
+    }
+    Some(flags)
+}
+
+/// Compare a mountinfo field, where the kernel escapes spaces, tabs,
+/// newlines and backslashes as `\ooo`, to `unescaped`.
+///
+/// # Signal Safety
+/// This function is signal safe.
+fn escaped_eq(field: &[u8], unescaped: &[u8]) -> bool {
+    let mut unescaped = unescaped.iter();
+    let mut i = 0;
+    while i < field.len() {
+        let octal = match field.get(i..i + 4) {
+            Some([b'\\', digits @ ..])
+                if digits.iter().all(|d| (b'0'..=b'7').contains(d)) =>
+            {
+                Some(digits.iter().fold(0u32, |n, d| n * 8 + (d - b'0') as u32))
+            }
+            _ => None,
+        };
+        let byte = match octal {
+            Some(byte) => {
+                i += 4;
+                byte as u8
+            }
+            None => {
+                i += 1;
+                field[i - 1]
+            }
+        };
+        if unescaped.next() != Some(&byte) {
+            return false;
+        }
+    }
+    unescaped.next().is_none()
+}
+
+/// Convert the `ST_*` flags of statfs(2) to the `MS_*` flags of the mount.
+fn from_stat_flags(stat_flags: u64) -> MountFlags {
+    const FLAGS: [(u64, MountFlags); 8] = [
+        (libc::ST_RDONLY, MountFlags::RDONLY),
+        (libc::ST_NOSUID, MountFlags::NOSUID),
+        (libc::ST_NODEV, MountFlags::NODEV),
+        (libc::ST_NOEXEC, MountFlags::NOEXEC),
+        (libc::ST_NOATIME, MountFlags::NOATIME),
+        (libc::ST_NODIRATIME, MountFlags::NODIRATIME),
+        (libc::ST_RELATIME, MountFlags::RELATIME),
+        (libc::ST_SYNCHRONOUS, MountFlags::SYNCHRONOUS),
+    ];
+
+    let mut flags = MountFlags::empty();
+    for (stat_flag, flag) in FLAGS {
+        if stat_flags & stat_flag != 0 {
+            flags |= flag;
+        }
+    }
+    flags
 }
 
 pub enum MountPropagation {
@@ -81,14 +451,20 @@ pub enum MountPropagation {
 ///
 /// # Example
 /// ```no_run
-/// Mount::new("/")
+/// use curium::mount::{Mount, MountPropagation};
+///
+/// Mount::new(c"/")
 ///     .set_propagation(MountPropagation::Private)
 ///     .recursive()
 ///     .mount()
+///     .unwrap();
 /// ```
 impl<'a> Mount<'a> {
     pub fn new(target: &'a CStr) -> Self {
-        Self { flags: 0, target }
+        Self {
+            flags: MountFlags::empty(),
+            target,
+        }
     }
 
     /// Set the propagation type of an exsting mount
@@ -97,20 +473,22 @@ impl<'a> Mount<'a> {
         propagation_type: MountPropagation,
     ) -> ConfiguredMount<'a, ActionSetPropagation> {
         ConfiguredMount {
-            flags: self.flags | propagation_type as u64,
+            flags: self.flags | MountFlags::from_bits(propagation_type as u64),
             target: self.target,
             source: None,
             fs_type: None,
+            data: None,
             _action: PhantomData,
         }
     }
 
     pub fn bind(self, source: &'a CStr) -> ConfiguredMount<'a, ActionBind> {
         ConfiguredMount {
-            flags: self.flags | libc::MS_BIND,
+            flags: self.flags | MountFlags::BIND,
             target: self.target,
             source: Some(source),
             fs_type: None,
+            data: None,
             _action: PhantomData,
         }
     }
@@ -126,32 +504,149 @@ impl<'a> Mount<'a> {
             target: self.target,
             source: Some(source),
             fs_type: Some(fs_type),
+            data: None,
+            _action: PhantomData,
+        }
+    }
+
+    /// Change the flags of the existing mount at `target`.
+    ///
+    /// With `bind` only the flags of this mount are changed (`MS_REMOUNT |
+    /// MS_BIND`), which the kernel limits to `RDONLY`, `NOSUID`, `NODEV`,
+    /// `NOEXEC` and the atime flags, ignoring the rest. Otherwise the
+    /// filesystem is remounted too, which is the only way to change its
+    /// flags like `LAZYTIME` and `SYNCHRONOUS`, and it's shared by every
+    /// mount of the filesystem, including the host's ones.
+    pub fn remount(self, bind: bool) -> ConfiguredMount<'a, ActionRemount> {
+        let mut flags = self.flags | MountFlags::REMOUNT;
+        if bind {
+            flags |= MountFlags::BIND;
+        }
+        ConfiguredMount {
+            flags,
+            target: self.target,
+            source: None,
+            fs_type: None,
+            data: None,
             _action: PhantomData,
         }
     }
 
+    /// Recursively bind `source` to `target`, then make every mount of the
+    /// new tree private and read-only, along with the `no_dev`, `no_suid`
+    /// and `no_exec` flags set on the builder.
+    ///
+    /// A recursive bind only remounts its top mount read-only with mount(2),
+    /// since `MS_REMOUNT` doesn't recurse. The flags and the propagation are
+    /// then set on the whole tree at once with mount_setattr(2) and
+    /// `AT_RECURSIVE` (Linux 5.12). On older kernels the new tree is detached
+    /// again and `ENOSYS` returned, rather than leaving writable submounts.
+    ///
+    /// # Signal Safety
+    /// This function is signal safe.
+    pub fn rbind_readonly(self, source: &CStr) -> Result<(), std::io::Error> {
+        mount(
+            Some(source),
+            Some(self.target),
+            None,
+            MountFlags::BIND | MountFlags::REC,
+            None,
+        )?;
+
+        let attr = MountAttr {
+            attr_set: self.readonly_attrs(),
+            attr_clr: 0,
+            propagation: libc::MS_PRIVATE,
+            userns_fd: 0,
+        };
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_mount_setattr,
+                libc::AT_FDCWD,
+                self.target.as_ptr(),
+                libc::AT_RECURSIVE,
+                &attr as *const MountAttr,
+                size_of::<MountAttr>(),
+            )
+        };
+        if ret != 0 {
+            let err = std::io::Error::last_os_error();
+            unsafe { libc::umount2(self.target.as_ptr(), libc::MNT_DETACH) };
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Make the existing mount at `target` read-only, along with the
+    /// `no_dev`, `no_suid` and `no_exec` flags set on the builder, with
+    /// mount_setattr(2) (Linux 5.12).
+    ///
+    /// Unlike [`Mount::remount`], the other flags of the mount are kept, so
+    /// the ones locked in a user namespace don't have to be repeated.
+    ///
+    /// # Signal Safety
+    /// This function is signal safe.
+    pub fn make_readonly(self) -> Result<(), std::io::Error> {
+        let attr = MountAttr {
+            attr_set: self.readonly_attrs(),
+            attr_clr: 0,
+            propagation: 0,
+            userns_fd: 0,
+        };
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_mount_setattr,
+                libc::AT_FDCWD,
+                self.target.as_ptr(),
+                0,
+                &attr as *const MountAttr,
+                size_of::<MountAttr>(),
+            )
+        };
+        match ret {
+            0 => Ok(()),
+            _ => Err(std::io::Error::last_os_error()),
+        }
+    }
+
+    /// The `MOUNT_ATTR_*` flags of a read-only mount with the builder's
+    /// `no_dev`, `no_suid` and `no_exec`.
+    fn readonly_attrs(&self) -> u64 {
+        let mut attrs = MOUNT_ATTR_RDONLY;
+        for (flag, attr) in [
+            (MountFlags::NOSUID, MOUNT_ATTR_NOSUID),
+            (MountFlags::NODEV, MOUNT_ATTR_NODEV),
+            (MountFlags::NOEXEC, MOUNT_ATTR_NOEXEC),
+        ] {
+            if self.flags.contains(flag) {
+                attrs |= attr;
+            }
+        }
+        attrs
+    }
+
     /// Mount the filesystem as read-only
     pub fn readonly(mut self) -> Self {
-        self.flags |= libc::MS_RDONLY;
+        self.flags |= MountFlags::RDONLY;
         self
     }
 
     /// Do not allow access to devices on this filesystem.
     pub fn no_dev(mut self) -> Self {
-        self.flags |= libc::MS_NODEV;
+        self.flags |= MountFlags::NODEV;
         self
     }
 
     /// Do not honor set user/group id bits or file capabilities when executing
     /// programs from this filesystem.
     pub fn no_suid(mut self) -> Self {
-        self.flags |= libc::MS_NOSUID;
+        self.flags |= MountFlags::NOSUID;
         self
     }
 
     /// Do not allow programs to be executed from this filesystem
     pub fn no_exec(mut self) -> Self {
-        self.flags |= libc::MS_NOEXEC;
+        self.flags |= MountFlags::NOEXEC;
         self
     }
 }
@@ -160,15 +655,16 @@ pub fn mount(
     source: Option<&CStr>,
     target: Option<&CStr>,
     fs_type: Option<&CStr>,
-    mount_flags: u64,
+    mount_flags: MountFlags,
+    data: Option<&CStr>,
 ) -> Result<(), std::io::Error> {
     let result = unsafe {
         libc::mount(
             source.map(|s| s.as_ptr()).unwrap_or(std::ptr::null()),
             target.map(|s| s.as_ptr()).unwrap_or(std::ptr::null()),
             fs_type.map(|s| s.as_ptr()).unwrap_or(std::ptr::null()),
-            mount_flags,
-            std::ptr::null(),
+            mount_flags.bits(),
+            data.map(|s| s.as_ptr().cast()).unwrap_or(std::ptr::null()),
         )
     };
     if result != 0 {
@@ -178,15 +674,545 @@ pub fn mount(
     }
 }
 
+/// Flags for umount2(2).
+///
+/// `EXPIRE` can't be combined with `FORCE` or `DETACH`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct UmountFlags(i32);
+
+impl UmountFlags {
+    pub const FORCE: Self = Self(libc::MNT_FORCE);
+    pub const DETACH: Self = Self(libc::MNT_DETACH);
+    pub const EXPIRE: Self = Self(libc::MNT_EXPIRE);
+    pub const NOFOLLOW: Self = Self(libc::UMOUNT_NOFOLLOW);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Return the raw `MNT_*` bits.
+    pub const fn bits(&self) -> i32 {
+        self.0
+    }
+
+    /// Return true if all the flags in `other` are set.
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Return true if the kernel accepts this combination of flags.
+    pub const fn is_valid(&self) -> bool {
+        !self.contains(Self::EXPIRE)
+            || (!self.contains(Self::FORCE) && !self.contains(Self::DETACH))
+    }
+}
+
+impl BitOr for UmountFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for UmountFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// Why [`umount2`] failed.
+#[derive(Debug)]
+pub enum UmountError {
+    /// The flags can't be combined, checked before the syscall.
+    InvalidFlags,
+    /// `EINVAL`: the target isn't a mount point (or a locked one).
+    NotMounted,
+    /// `EBUSY`: the mount is in use, it can still be lazily detached with
+    /// [`UmountFlags::DETACH`].
+    Busy,
+    Os(std::io::Error),
+}
+
+impl std::fmt::Display for UmountError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UmountError::InvalidFlags => write!(f, "invalid umount flags"),
+            UmountError::NotMounted => write!(f, "not a mount point"),
+            UmountError::Busy => write!(f, "the mount is busy"),
+            UmountError::Os(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for UmountError {}
+
+impl From<UmountError> for std::io::Error {
+    fn from(err: UmountError) -> Self {
+        match err {
+            UmountError::InvalidFlags => std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid umount flags",
+            ),
+            UmountError::NotMounted => {
+                std::io::Error::from_raw_os_error(libc::EINVAL)
+            }
+            UmountError::Busy => std::io::Error::from_raw_os_error(libc::EBUSY),
+            UmountError::Os(err) => err,
+        }
+    }
+}
+
+/// Unmount `target`.
+///
+/// # Signal Safety
+/// This function is signal safe.
 pub fn umount2<T: AsRef<CStr>>(
     target: T,
-    umount_flags: i32,
-) -> Result<(), std::io::Error> {
+    umount_flags: UmountFlags,
+) -> Result<(), UmountError> {
+    if !umount_flags.is_valid() {
+        return Err(UmountError::InvalidFlags);
+    }
+
     let result =
-        unsafe { libc::umount2(target.as_ref().as_ptr(), umount_flags) };
+        unsafe { libc::umount2(target.as_ref().as_ptr(), umount_flags.bits()) };
     if result != 0 {
-        Err(std::io::Error::last_os_error())
-    } else {
-        Ok(())
+        let err = std::io::Error::last_os_error();
+        return Err(match err.raw_os_error() {
+            Some(libc::EINVAL) => UmountError::NotMounted,
+            Some(libc::EBUSY) => UmountError::Busy,
+            _ => UmountError::Os(err),
+        });
+    }
+    Ok(())
+}
+
+/// Move the root mount of the calling process to `put_old` and make
+/// `new_root` the new root mount, see pivot_root(2).
+///
+/// `new_root` must be a mount point, and `put_old` a directory at or under
+/// it. The same path for both (e.g. `"."` after changing into the new
+/// root) stacks the old root on top of the new one, where it can be
+/// detached with [`umount2`] without a put_old directory. The working
+/// directory isn't changed.
+///
+/// # Signal Safety
+/// This function is signal safe.
+pub fn pivot_root(
+    new_root: &CStr,
+    put_old: &CStr,
+) -> Result<(), std::io::Error> {
+    let ret = unsafe {
+        libc::syscall(libc::SYS_pivot_root, new_root.as_ptr(), put_old.as_ptr())
+    };
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Clone the directory referred to by `dir_fd` into a new detached bind
+/// mount, see open_tree(2) (Linux 5.2).
+///
+/// The returned mount isn't tied to a mount namespace until it's attached
+/// with [`attach_tree`], so it can be cloned in the parent and attached in a
+/// child's new mount namespace.
+pub fn clone_tree(dir_fd: RawFd) -> Result<OwnedFd, std::io::Error> {
+    let tree = unsafe {
+        libc::syscall(
+            libc::SYS_open_tree,
+            dir_fd,
+            c"".as_ptr(),
+            libc::OPEN_TREE_CLONE
+                | libc::OPEN_TREE_CLOEXEC
+                | libc::AT_EMPTY_PATH as u32,
+        )
+    };
+    if tree < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    // SAFETY: open_tree returned a new file descriptor.
+    Ok(unsafe { OwnedFd::from_raw_fd(tree as RawFd) })
+}
+
+/// Attach a mount created by [`clone_tree`] at `target`, see move_mount(2).
+///
+/// # Signal Safety
+/// This function is signal safe.
+pub fn attach_tree(tree: RawFd, target: &CStr) -> Result<(), std::io::Error> {
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_move_mount,
+            tree,
+            c"".as_ptr(),
+            libc::AT_FDCWD,
+            target.as_ptr(),
+            MOVE_MOUNT_F_EMPTY_PATH,
+        )
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// An owned counterpart of [`Mount`], for host-side code building mounts
+/// from configuration.
+///
+/// The builder keeps its own `CString`s, so the caller doesn't have to keep
+/// them alive, and has the same typestate methods as [`Mount`]. The mount
+/// itself is done by the same [`Mount`] builder. A null byte in a path or
+/// option fails the mount with [`ErrorKind::InvalidInput`].
+///
+/// [`ErrorKind::InvalidInput`]: std::io::ErrorKind::InvalidInput
+///
+/// # Signal Safety
+/// This builder allocates, so it's not signal safe and must not be used in
+/// the child, use [`Mount`] there.
+///
+/// # Example
+/// ```no_run
+/// use curium::mount::OwnedMountBuilder;
+///
+/// let target = String::from("/tmp/bbox/tmp");
+/// OwnedMountBuilder::new(&target)
+///     .no_dev()
+///     .create("tmpfs", "tmpfs")
+///     .data("size=64m")
+///     .mount()
+///     .unwrap();
+/// ```
+pub struct OwnedMountBuilder {
+    flags: MountFlags,
+    target: CString,
+    /// A path or option had a null byte, reported by the mount.
+    null: bool,
+}
+
+pub struct OwnedConfiguredMount<Action> {
+    flags: MountFlags,
+    target: CString,
+    source: Option<CString>,
+    fs_type: Option<CString>,
+    data: Option<CString>,
+    null: bool,
+    _action: PhantomData<Action>,
+}
+
+/// `s` as a `CString`, or an empty one with `null` set if it has a null byte.
+fn cstring<S: AsRef<OsStr>>(s: S, null: &mut bool) -> CString {
+    CString::new(s.as_ref().as_encoded_bytes()).unwrap_or_else(|_| {
+        *null = true;
+        CString::default()
+    })
+}
+
+fn null_error() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, "null in the mount")
+}
+
+impl OwnedMountBuilder {
+    pub fn new<P: AsRef<Path>>(target: P) -> Self {
+        let mut null = false;
+        Self {
+            flags: MountFlags::empty(),
+            target: cstring(target.as_ref(), &mut null),
+            null,
+        }
+    }
+
+    /// See [`Mount::set_propagation`].
+    pub fn set_propagation(
+        self,
+        propagation_type: MountPropagation,
+    ) -> OwnedConfiguredMount<ActionSetPropagation> {
+        OwnedConfiguredMount {
+            flags: self.flags | MountFlags::from_bits(propagation_type as u64),
+            target: self.target,
+            source: None,
+            fs_type: None,
+            data: None,
+            null: self.null,
+            _action: PhantomData,
+        }
+    }
+
+    /// See [`Mount::bind`].
+    pub fn bind<P: AsRef<Path>>(
+        mut self,
+        source: P,
+    ) -> OwnedConfiguredMount<ActionBind> {
+        OwnedConfiguredMount {
+            flags: self.flags | MountFlags::BIND,
+            source: Some(cstring(source.as_ref(), &mut self.null)),
+            target: self.target,
+            fs_type: None,
+            data: None,
+            null: self.null,
+            _action: PhantomData,
+        }
+    }
+
+    /// See [`Mount::create`].
+    pub fn create<S: AsRef<OsStr>>(
+        mut self,
+        fs_type: S,
+        source: S,
+    ) -> OwnedConfiguredMount<ActionCreate> {
+        OwnedConfiguredMount {
+            flags: self.flags,
+            source: Some(cstring(source, &mut self.null)),
+            fs_type: Some(cstring(fs_type, &mut self.null)),
+            target: self.target,
+            data: None,
+            null: self.null,
+            _action: PhantomData,
+        }
+    }
+
+    /// See [`Mount::rbind_readonly`].
+    pub fn rbind_readonly<P: AsRef<Path>>(
+        mut self,
+        source: P,
+    ) -> Result<(), std::io::Error> {
+        let source = cstring(source.as_ref(), &mut self.null);
+        if self.null {
+            return Err(null_error());
+        }
+        Mount {
+            flags: self.flags,
+            target: &self.target,
+        }
+        .rbind_readonly(&source)
+    }
+
+    pub fn readonly(mut self) -> Self {
+        self.flags |= MountFlags::RDONLY;
+        self
+    }
+
+    pub fn no_dev(mut self) -> Self {
+        self.flags |= MountFlags::NODEV;
+        self
+    }
+
+    pub fn no_suid(mut self) -> Self {
+        self.flags |= MountFlags::NOSUID;
+        self
+    }
+
+    pub fn no_exec(mut self) -> Self {
+        self.flags |= MountFlags::NOEXEC;
+        self
+    }
+}
+
+impl<Action> OwnedConfiguredMount<Action> {
+    /// Borrow as the signal-safe builder, which does the mount, unless a path
+    /// or option had a null byte.
+    fn borrow(&self) -> Result<ConfiguredMount<'_, Action>, std::io::Error> {
+        if self.null {
+            return Err(null_error());
+        }
+        Ok(ConfiguredMount {
+            flags: self.flags,
+            target: &self.target,
+            source: self.source.as_deref(),
+            fs_type: self.fs_type.as_deref(),
+            data: self.data.as_deref(),
+            _action: PhantomData,
+        })
+    }
+}
+
+impl OwnedConfiguredMount<ActionSetPropagation> {
+    pub fn mount(self) -> Result<(), std::io::Error> {
+        self.borrow()?.mount()
+    }
+
+    pub fn recursive(mut self) -> Self {
+        self.flags |= MountFlags::REC;
+        self
+    }
+}
+
+impl OwnedConfiguredMount<ActionBind> {
+    pub fn mount(self) -> Result<(), std::io::Error> {
+        self.borrow()?.mount()
+    }
+
+    pub fn recursive(mut self) -> Self {
+        self.flags |= MountFlags::REC;
+        self
+    }
+}
+
+impl OwnedConfiguredMount<ActionCreate> {
+    pub fn mount(self) -> Result<(), std::io::Error> {
+        self.borrow()?.mount()
+    }
+
+    /// See [`ConfiguredMount::data`].
+    pub fn data<S: AsRef<OsStr>>(mut self, data: S) -> Self {
+        self.data = Some(cstring(data, &mut self.null));
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn expire_is_exclusive() {
+        assert!(UmountFlags::EXPIRE.is_valid());
+        assert!((UmountFlags::FORCE | UmountFlags::DETACH).is_valid());
+        assert!((UmountFlags::EXPIRE | UmountFlags::NOFOLLOW).is_valid());
+        assert!(!(UmountFlags::EXPIRE | UmountFlags::FORCE).is_valid());
+        assert!(!(UmountFlags::EXPIRE | UmountFlags::DETACH).is_valid());
+    }
+
+    #[test]
+    fn invalid_flags_are_rejected_before_the_syscall() {
+        let err = umount2(c"/", UmountFlags::EXPIRE | UmountFlags::FORCE);
+        assert!(matches!(err, Err(UmountError::InvalidFlags)));
+    }
+
+    #[test]
+    fn stat_flags_are_converted() {
+        let flags = from_stat_flags(libc::ST_NOSUID | libc::ST_RELATIME);
+        assert_eq!(flags, MountFlags::NOSUID | MountFlags::RELATIME);
+        assert_eq!(from_stat_flags(0), MountFlags::empty());
+    }
+
+    #[test]
+    fn super_options_are_read() {
+        let line = b"37 36 0:5 / /tmp/with\\040space rw,nosuid shared:2 - \
+                     tmpfs tmpfs rw,lazytime,size=1024k";
+        assert_eq!(
+            super_flags(line, b"/tmp/with space"),
+            Some(MountFlags::LAZYTIME)
+        );
+        assert_eq!(super_flags(line, b"/tmp/with"), None);
+        assert_eq!(super_flags(line, b"/tmp/with space/x"), None);
+        assert!(superblock_flags(c"/").is_ok());
+    }
+
+    /// Run `test` in a forked child, in new user and mount namespaces where
+    /// we are root, and return whether it passed.
+    ///
+    /// The child of a multithreaded process may only run signal safe code,
+    /// `test` too.
+    fn in_mount_namespace<F: FnOnce() -> bool>(test: F) -> bool {
+        use std::io::Write;
+
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        let write = |path: &CStr, contents: &[u8]| {
+            let fd = unsafe { libc::open(path.as_ptr(), libc::O_WRONLY) };
+            fd >= 0
+                && unsafe {
+                    libc::write(fd, contents.as_ptr().cast(), contents.len())
+                } == contents.len() as isize
+        };
+
+        match unsafe { libc::fork() } {
+            0 => {
+                let mut uid_map = crate::FixedBufferWriter::<32>::new();
+                let mut gid_map = crate::FixedBufferWriter::<32>::new();
+                let passed = write!(uid_map, "0 {uid} 1").is_ok()
+                    && write!(gid_map, "0 {gid} 1").is_ok()
+                    && unsafe {
+                        libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNS)
+                    } == 0
+                    && write(c"/proc/self/uid_map", uid_map.buffer())
+                    && write(c"/proc/self/setgroups", b"deny")
+                    && write(c"/proc/self/gid_map", gid_map.buffer())
+                    && mount(
+                        None,
+                        Some(c"/"),
+                        None,
+                        MountFlags::REC | MountFlags::PRIVATE,
+                        None,
+                    )
+                    .is_ok()
+                    && test();
+                unsafe { libc::_exit(!passed as i32) }
+            }
+            pid => {
+                let mut status = 0;
+                unsafe { libc::waitpid(pid, &mut status, 0) };
+                libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0
+            }
+        }
+    }
+
+    #[test]
+    fn remount_keeps_the_current_flags() {
+        // A mount created in our user namespace, so its flags aren't locked
+        // and a bare remount would clear them.
+        let target = c"/tmp";
+        let mounted = || {
+            Mount::new(target)
+                .no_suid()
+                .create(c"tmpfs", c"tmpfs")
+                .mount()
+                .is_ok()
+        };
+
+        assert!(in_mount_namespace(|| {
+            mounted()
+                && Mount::new(target).readonly().remount(true).mount().is_ok()
+                && current_flags(target).is_ok_and(|flags| {
+                    flags.contains(MountFlags::RDONLY | MountFlags::NOSUID)
+                })
+        }));
+
+        // statfs doesn't report LAZYTIME, which a remount of the filesystem
+        // clears.
+        assert!(in_mount_namespace(|| {
+            mount(
+                Some(c"tmpfs"),
+                Some(target),
+                Some(c"tmpfs"),
+                MountFlags::NOSUID | MountFlags::LAZYTIME,
+                None,
+            )
+            .is_ok()
+                && Mount::new(target).readonly().remount(false).mount().is_ok()
+                && current_flags(target).is_ok_and(|flags| {
+                    flags.contains(MountFlags::RDONLY | MountFlags::NOSUID)
+                })
+                && superblock_flags(target)
+                    .is_ok_and(|flags| flags.contains(MountFlags::LAZYTIME))
+        }));
+    }
+
+    #[test]
+    fn owned_builder_keeps_the_arguments() {
+        let mount = OwnedMountBuilder::new(String::from("/mnt"))
+            .readonly()
+            .create("tmpfs", "tmpfs")
+            .data("size=1m");
+        let borrowed = mount.borrow().unwrap();
+
+        assert_eq!(borrowed.target, c"/mnt");
+        assert_eq!(borrowed.fs_type, Some(c"tmpfs"));
+        assert_eq!(borrowed.data, Some(c"size=1m"));
+        assert!(borrowed.flags.contains(MountFlags::RDONLY));
+    }
+
+    #[test]
+    fn null_fails_the_owned_mount() {
+        let err = OwnedMountBuilder::new("/mnt")
+            .create("tmpfs", "tmpfs")
+            .data("size=1m\0")
+            .mount()
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        let err = OwnedMountBuilder::new("/m\0nt").bind("/").mount();
+        assert_eq!(err.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
     }
 }
@@ -0,0 +1,334 @@
+//! Declarative mounts applied to the container root.
+
+use std::{
+    ffi::{CStr, CString, OsStr, OsString},
+    path::PathBuf,
+};
+
+use crate::{
+    error::{CuriumError, Result},
+    mount::{Mount, MountPropagation},
+    plan::Operation,
+};
+
+/// A mount to set up in the container.
+///
+/// `dst` is relative to the container root, while `src` (and the overlay
+/// directories) are host paths. The mount points must already exist in the
+/// rootfs. Filesystems other than binds are mounted `nodev,nosuid`.
+#[derive(Debug, Clone)]
+pub enum MountSpec {
+    Bind {
+        src: PathBuf,
+        dst: PathBuf,
+        ro: bool,
+    },
+    /// A bind of `src` with its submounts, each made private so the mount
+    /// events of the host don't propagate into the container (nor the other
+    /// way around). With `ro` every mount of the tree is read-only.
+    RecursiveBind {
+        src: PathBuf,
+        dst: PathBuf,
+        ro: bool,
+    },
+    Tmpfs {
+        dst: PathBuf,
+        /// Size in bytes, the kernel defaults to half of the RAM.
+        size: Option<u64>,
+        mode: Option<u32>,
+    },
+    Proc {
+        dst: PathBuf,
+    },
+    Sysfs {
+        dst: PathBuf,
+    },
+    Overlay {
+        lower: Vec<PathBuf>,
+        upper: PathBuf,
+        work: PathBuf,
+        dst: PathBuf,
+    },
+    /// POSIX message queues of the container's IPC namespace.
+    Mqueue {
+        dst: PathBuf,
+    },
+    /// A pseudoterminal filesystem, `options` is passed as the mount data
+    /// (e.g. `newinstance,ptmxmode=0666`).
+    Devpts {
+        dst: PathBuf,
+        options: String,
+    },
+}
+
+enum Kind {
+    Bind { ro: bool, recursive: bool },
+    Create { fs_type: &'static CStr },
+}
+
+/// A [`MountSpec`] with every argument converted to a `CString`, so it can be
+/// mounted in the child without allocating.
+pub(crate) struct PreparedMount {
+    kind: Kind,
+    target: CString,
+    source: CString,
+    data: Option<CString>,
+}
+
+fn cstring<S: AsRef<OsStr>>(s: S) -> Result<CString> {
+    CString::new(s.as_ref().as_encoded_bytes()).map_err(|_| {
+        CuriumError::InvalidConfig(format!(
+            "Null in the mount {:?}",
+            s.as_ref()
+        ))
+    })
+}
+
+impl MountSpec {
+    /// Prepare the mount for the container rooted at `root`.
+    ///
+    /// `label` is the SELinux context of the tmpfs, mqueue and devpts mounts.
+    /// A null byte in a path (or the label) is a configuration error.
+    pub(crate) fn prepare(
+        &self,
+        root: &str,
+        label: Option<&str>,
+    ) -> Result<PreparedMount> {
+        let context = |mut data: Vec<String>| {
+            if let Some(label) = label {
+                data.push(format!("context=\"{label}\""));
+            }
+            (!data.is_empty())
+                .then(|| cstring(data.join(",")))
+                .transpose()
+        };
+        let target = |dst: &PathBuf| {
+            let mut target = OsString::from(root);
+            target.push(dst);
+            cstring(target)
+        };
+
+        Ok(match self {
+            MountSpec::Bind { src, dst, ro } => PreparedMount {
+                kind: Kind::Bind {
+                    ro: *ro,
+                    recursive: false,
+                },
+                target: target(dst)?,
+                source: cstring(src)?,
+                data: None,
+            },
+            MountSpec::RecursiveBind { src, dst, ro } => PreparedMount {
+                kind: Kind::Bind {
+                    ro: *ro,
+                    recursive: true,
+                },
+                target: target(dst)?,
+                source: cstring(src)?,
+                data: None,
+            },
+            MountSpec::Tmpfs { dst, size, mode } => {
+                let mut data = vec![];
+                if let Some(size) = size {
+                    data.push(format!("size={size}"));
+                }
+                if let Some(mode) = mode {
+                    data.push(format!("mode={mode:o}"));
+                }
+
+                PreparedMount {
+                    kind: Kind::Create { fs_type: c"tmpfs" },
+                    target: target(dst)?,
+                    source: cstring("tmpfs")?,
+                    data: context(data)?,
+                }
+            }
+            MountSpec::Proc { dst } => PreparedMount {
+                kind: Kind::Create { fs_type: c"proc" },
+                target: target(dst)?,
+                source: cstring("proc")?,
+                data: None,
+            },
+            MountSpec::Sysfs { dst } => PreparedMount {
+                kind: Kind::Create { fs_type: c"sysfs" },
+                target: target(dst)?,
+                source: cstring("sysfs")?,
+                data: None,
+            },
+            MountSpec::Overlay {
+                lower,
+                upper,
+                work,
+                dst,
+            } => {
+                let mut data = OsString::from("lowerdir=");
+                for (i, dir) in lower.iter().enumerate() {
+                    if i > 0 {
+                        data.push(":");
+                    }
+                    data.push(dir);
+                }
+                data.push(",upperdir=");
+                data.push(upper);
+                data.push(",workdir=");
+                data.push(work);
+
+                PreparedMount {
+                    kind: Kind::Create {
+                        fs_type: c"overlay",
+                    },
+                    target: target(dst)?,
+                    source: cstring("overlay")?,
+                    data: Some(cstring(data)?),
+                }
+            }
+            MountSpec::Mqueue { dst } => PreparedMount {
+                kind: Kind::Create { fs_type: c"mqueue" },
+                target: target(dst)?,
+                source: cstring("mqueue")?,
+                data: context(vec![])?,
+            },
+            MountSpec::Devpts { dst, options } => PreparedMount {
+                kind: Kind::Create { fs_type: c"devpts" },
+                target: target(dst)?,
+                source: cstring("devpts")?,
+                data: context(
+                    options
+                        .split(',')
+                        .filter(|option| !option.is_empty())
+                        .map(str::to_owned)
+                        .collect(),
+                )?,
+            },
+        })
+    }
+}
+
+impl PreparedMount {
+    /// Describe the mount, see [`crate::Container::dry_run`].
+    pub(crate) fn operation(&self) -> Operation {
+        let (fs_type, mut options) = match self.kind {
+            Kind::Bind { ro, recursive } => {
+                let mut options = match recursive {
+                    true => vec!["rbind".to_owned(), "rprivate".to_owned()],
+                    false => vec!["bind".to_owned()],
+                };
+                if ro {
+                    options.push("ro".to_owned());
+                }
+                (None, options)
+            }
+            Kind::Create { fs_type } => (
+                Some(fs_type.to_string_lossy().into_owned()),
+                vec!["nodev".to_owned(), "nosuid".to_owned()],
+            ),
+        };
+        if let Some(data) = &self.data {
+            options.push(data.to_string_lossy().into_owned());
+        }
+
+        Operation::Mount {
+            source: self.source.to_string_lossy().into_owned(),
+            target: self.target.to_string_lossy().into_owned(),
+            fs_type,
+            options,
+        }
+    }
+
+    /// # Signal Safety
+    /// This function is signal safe.
+    pub(crate) fn mount(&self) -> std::io::Result<()> {
+        match self.kind {
+            Kind::Bind {
+                ro: true,
+                recursive: false,
+            } => Mount::new(&self.target)
+                .readonly()
+                .bind(&self.source)
+                .mount(),
+            Kind::Bind {
+                ro: false,
+                recursive: false,
+            } => Mount::new(&self.target).bind(&self.source).mount(),
+            Kind::Bind {
+                ro: true,
+                recursive: true,
+            } => Mount::new(&self.target).rbind_readonly(&self.source),
+            Kind::Bind {
+                ro: false,
+                recursive: true,
+            } => {
+                Mount::new(&self.target)
+                    .bind(&self.source)
+                    .recursive()
+                    .mount()?;
+                Mount::new(&self.target)
+                    .set_propagation(MountPropagation::Private)
+                    .recursive()
+                    .mount()
+            }
+            Kind::Create { fs_type } => {
+                let mount = Mount::new(&self.target)
+                    .no_dev()
+                    .no_suid()
+                    .create(fs_type, &self.source);
+
+                match &self.data {
+                    Some(data) => mount.data(data).mount(),
+                    None => mount.mount(),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn mount_label_is_appended_to_the_data() {
+        let label = Some("system_u:object_r:container_file_t:s0");
+
+        let tmpfs = MountSpec::Tmpfs {
+            dst: "/tmp".into(),
+            size: Some(1024),
+            mode: None,
+        };
+        assert_eq!(
+            tmpfs.prepare("/root", label).unwrap().data.as_deref(),
+            Some(
+                c"size=1024,context=\"system_u:object_r:container_file_t:s0\""
+            )
+        );
+
+        let devpts = MountSpec::Devpts {
+            dst: "/dev/pts".into(),
+            options: "newinstance".into(),
+        };
+        assert_eq!(
+            devpts.prepare("/root", None).unwrap().data.as_deref(),
+            Some(c"newinstance")
+        );
+
+        let proc = MountSpec::Proc {
+            dst: "/proc".into(),
+        };
+        assert_eq!(proc.prepare("/root", label).unwrap().data, None);
+    }
+
+    #[test]
+    fn recursive_bind_is_private() {
+        let bind = MountSpec::RecursiveBind {
+            src: "/srv".into(),
+            dst: "/mnt".into(),
+            ro: true,
+        };
+        let Operation::Mount { options, .. } =
+            bind.prepare("/root", None).unwrap().operation()
+        else {
+            unreachable!();
+        };
+        assert_eq!(options, ["rbind", "rprivate", "ro"]);
+    }
+}
@@ -0,0 +1,150 @@
+//! A parser for `/proc/<pid>/mountinfo`, see proc_pid_mountinfo(5).
+//!
+//! This is meant for the parent (and tests) to inspect the mounts of a
+//! container, it allocates.
+
+use std::{
+    ffi::OsString,
+    io::{Error, ErrorKind},
+    os::unix::ffi::OsStringExt,
+    path::PathBuf,
+};
+
+/// A line of a mountinfo file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MountInfo {
+    pub mount_id: u32,
+    /// The id of the parent mount, or of itself for the root of the mount
+    /// namespace.
+    pub parent_id: u32,
+    /// The mount point relative to the root of the reading process.
+    pub mountpoint: PathBuf,
+    pub fs_type: String,
+    /// The filesystem specific source, e.g. a device or `tmpfs`.
+    pub source: String,
+}
+
+/// Read the mounts of the calling process.
+pub fn mountinfo() -> Result<Vec<MountInfo>, Error> {
+    parse(&std::fs::read_to_string("/proc/self/mountinfo")?)
+}
+
+/// Read the mounts of the process `pid`, as seen from its mount namespace
+/// (the mount points are still relative to our root).
+pub fn mountinfo_of(pid: i64) -> Result<Vec<MountInfo>, Error> {
+    parse(&std::fs::read_to_string(format!("/proc/{pid}/mountinfo"))?)
+}
+
+/// Parse the contents of a mountinfo file.
+///
+/// The mounts are listed in the order they were mounted, so a mount comes
+/// after its parent and the list can be unmounted in reverse.
+pub fn parse(contents: &str) -> Result<Vec<MountInfo>, Error> {
+    contents
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(parse_line)
+        .collect()
+}
+
+fn parse_line(line: &str) -> Result<MountInfo, Error> {
+    let invalid =
+        || Error::new(ErrorKind::InvalidData, format!("bad mountinfo: {line}"));
+
+    let mut fields = line.split(' ');
+    let mount_id = fields.next().and_then(|id| id.parse().ok());
+    let parent_id = fields.next().and_then(|id| id.parse().ok());
+    // Skip major:minor and the root of the mount.
+    let mountpoint = fields.nth(2);
+    // The optional fields end with a single "-".
+    let mut fields = fields.skip_while(|field| *field != "-").skip(1);
+    let fs_type = fields.next();
+    let source = fields.next();
+
+    match (mount_id, parent_id, mountpoint, fs_type, source) {
+        (
+            Some(mount_id),
+            Some(parent_id),
+            Some(mountpoint),
+            Some(fs_type),
+            Some(source),
+        ) => Ok(MountInfo {
+            mount_id,
+            parent_id,
+            mountpoint: OsString::from_vec(unescape(mountpoint)).into(),
+            fs_type: fs_type.to_owned(),
+            source: String::from_utf8_lossy(&unescape(source)).into_owned(),
+        }),
+        _ => Err(invalid()),
+    }
+}
+
+/// The kernel escapes spaces, tabs, newlines and backslashes as `\ooo`.
+fn unescape(field: &str) -> Vec<u8> {
+    let bytes = field.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes.get(i + 1..i + 4).and_then(|digits| {
+            u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok()
+        });
+        match (bytes[i], octal) {
+            (b'\\', Some(byte)) => {
+                unescaped.push(byte);
+                i += 4;
+            }
+            (byte, _) => {
+                unescaped.push(byte);
+                i += 1;
+            }
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_mountinfo_line() {
+        let mounts = parse(
+            "36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root \
+             rw,errors=continue\n\
+             37 36 0:5 / /tmp/with\\040space rw - tmpfs tmpfs rw\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            mounts,
+            [
+                MountInfo {
+                    mount_id: 36,
+                    parent_id: 35,
+                    mountpoint: "/mnt2".into(),
+                    fs_type: "ext3".into(),
+                    source: "/dev/root".into(),
+                },
+                MountInfo {
+                    mount_id: 37,
+                    parent_id: 36,
+                    mountpoint: "/tmp/with space".into(),
+                    fs_type: "tmpfs".into(),
+                    source: "tmpfs".into(),
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn reject_truncated_line() {
+        assert!(parse("36 35 98:0 /mnt1 /mnt2 rw -").is_err());
+    }
+
+    #[test]
+    fn read_own_mounts() {
+        let mounts = mountinfo().unwrap();
+        assert!(mounts.iter().any(|m| m.mountpoint.as_os_str() == "/"));
+    }
+}
@@ -0,0 +1,89 @@
+//! Linux namespaces, see namespaces(7).
+
+use std::{
+    io::Error,
+    ops::{BitOr, BitOrAssign},
+};
+
+/// A set of namespaces, as `CLONE_NEW*` flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Namespaces(i32);
+
+impl Namespaces {
+    pub const USER: Self = Self(libc::CLONE_NEWUSER);
+    pub const MOUNT: Self = Self(libc::CLONE_NEWNS);
+    pub const PID: Self = Self(libc::CLONE_NEWPID);
+    pub const NET: Self = Self(libc::CLONE_NEWNET);
+    pub const IPC: Self = Self(libc::CLONE_NEWIPC);
+    pub const UTS: Self = Self(libc::CLONE_NEWUTS);
+    pub const CGROUP: Self = Self(libc::CLONE_NEWCGROUP);
+    pub const TIME: Self = Self(libc::CLONE_NEWTIME);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Return the raw `CLONE_NEW*` bits.
+    pub const fn bits(&self) -> i32 {
+        self.0
+    }
+
+    /// Return true if all the namespaces in `other` are set.
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Return true if no namespace is set.
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Return the namespaces set in `self` but not in `other`.
+    pub const fn difference(&self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+}
+
+impl BitOr for Namespaces {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Namespaces {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// Move the calling thread into new `namespaces`, see unshare(2).
+///
+/// This is meant to test the setup of a container in-process, e.g. to mount
+/// in a new mount namespace and check the mounts with
+/// [`crate::mountinfo::mountinfo`]. Unlike the namespaces of a spawned
+/// container:
+///
+/// - `PID` doesn't move the caller, only its next child is the init of the new
+///   pid namespace.
+/// - `USER` fails with `EINVAL` in a multithreaded process, like the test
+///   harness.
+/// - `MOUNT` copies the mounts with their propagation, so `/` should be made
+///   private before mounting or the mounts propagate back to the host.
+/// - Only the calling thread is moved, other threads (and the process, once the
+///   thread exits) stay in the old namespaces.
+///
+/// # Example
+/// ```no_run
+/// use curium::{Container, unshare_namespaces};
+///
+/// let container = Container::new("/tmp/bbox".into(), "/bin/sh");
+/// unshare_namespaces(container.namespaces()).unwrap();
+/// ```
+pub fn unshare_namespaces(namespaces: Namespaces) -> Result<(), Error> {
+    if unsafe { libc::unshare(namespaces.bits()) } != 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,35 @@
+//! Network setup of the container's network namespace.
+
+use std::io::Error;
+
+/// Bring the loopback interface of the calling process' network namespace
+/// up, a new namespace starts with it down.
+///
+/// # Signal Safety
+/// This function is signal safe.
+pub(crate) fn loopback_up() -> Result<(), Error> {
+    let sock = unsafe {
+        libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0)
+    };
+    if sock < 0 {
+        return Err(Error::last_os_error());
+    }
+
+    let mut ifreq: libc::ifreq = unsafe { std::mem::zeroed() };
+    for (dst, src) in ifreq.ifr_name.iter_mut().zip(b"lo") {
+        *dst = *src as libc::c_char;
+    }
+    let result = unsafe {
+        if libc::ioctl(sock, libc::SIOCGIFFLAGS, &mut ifreq) != 0 {
+            Err(Error::last_os_error())
+        } else {
+            ifreq.ifr_ifru.ifru_flags |= libc::IFF_UP as libc::c_short;
+            match libc::ioctl(sock, libc::SIOCSIFFLAGS, &ifreq) {
+                0 => Ok(()),
+                _ => Err(Error::last_os_error()),
+            }
+        }
+    };
+    unsafe { libc::close(sock) };
+    result
+}
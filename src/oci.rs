@@ -0,0 +1,123 @@
+//! The state of a container as defined by the OCI runtime spec, the output
+//! of `runc state`, and the part of a bundle's `config.json` read by
+//! [`Container::from_oci_bundle`](crate::Container::from_oci_bundle).
+
+use serde::{Deserialize, Serialize};
+
+/// The version of the runtime spec the state follows.
+pub const OCI_VERSION: &str = "1.0.2";
+
+/// The lifecycle status of a container.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// The container is being set up. A [`ContainerHandle`] only exists once
+    /// `spawn` returned, so it never reports this.
+    ///
+    /// [`ContainerHandle`]: crate::ContainerHandle
+    Creating,
+    /// The container is set up but the command isn't executed yet, see
+    /// [`Container::stop_before_exec`](crate::Container::stop_before_exec).
+    Created,
+    /// The command was executed and hasn't exited.
+    Running,
+    /// The command exited.
+    Stopped,
+}
+
+/// The state of a container, serialized as the JSON of the runtime spec.
+///
+/// ```
+/// use curium::oci::{State, Status};
+///
+/// let state = State::new("web", Status::Running, Some(42), "/bundles/web");
+/// assert_eq!(
+///     state.to_json(),
+///     r#"{"ociVersion":"1.0.2","id":"web","status":"running","pid":42,"bundle":"/bundles/web"}"#
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct State {
+    pub oci_version: String,
+    pub id: String,
+    pub status: Status,
+    /// The pid of the container's init process, as seen from the host. Left
+    /// out once the container is stopped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<i64>,
+    /// The absolute path of the container's bundle directory.
+    pub bundle: String,
+}
+
+impl State {
+    pub fn new<I: Into<String>, B: Into<String>>(
+        id: I,
+        status: Status,
+        pid: Option<i64>,
+        bundle: B,
+    ) -> Self {
+        Self {
+            oci_version: OCI_VERSION.to_owned(),
+            id: id.into(),
+            status,
+            pid,
+            bundle: bundle.into(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("the state should serialize")
+    }
+}
+
+/// The fields of `config.json` a container is created from, the others are
+/// ignored.
+#[derive(Debug, Deserialize)]
+pub(crate) struct Config {
+    pub(crate) root: Option<Root>,
+    pub(crate) process: Process,
+    pub(crate) hostname: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct Root {
+    /// The rootfs, relative to the bundle unless absolute.
+    pub(crate) path: String,
+    #[serde(default)]
+    pub(crate) readonly: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct Process {
+    pub(crate) args: Vec<String>,
+    #[serde(default)]
+    pub(crate) env: Vec<String>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stopped_state_has_no_pid() {
+        let state = State::new("db", Status::Stopped, None, "/bundle");
+        assert_eq!(
+            state.to_json(),
+            r#"{"ociVersion":"1.0.2","id":"db","status":"stopped","bundle":"/bundle"}"#
+        );
+    }
+
+    #[test]
+    fn config_defaults() {
+        let config: Config =
+            serde_json::from_str(r#"{"process":{"args":["sh"]},"mounts":[]}"#)
+                .unwrap();
+        assert!(config.root.is_none());
+        assert_eq!(config.process.args, ["sh"]);
+        assert!(config.process.env.is_empty());
+
+        let root: Root = serde_json::from_str(r#"{"path":"fs"}"#).unwrap();
+        assert!(!root.readonly);
+    }
+}
@@ -0,0 +1,311 @@
+//! The steps of spawning a container, as listed by
+//! [`Container::dry_run`](crate::Container::dry_run).
+
+use std::{fmt, path::PathBuf};
+
+use crate::{caps::Capability, namespace::Namespaces, uid_map::IdMap};
+
+/// Names of the clone flags shown by [`Operation::Clone`].
+const CLONE_FLAGS: [(u64, &str); 12] = [
+    (libc::CLONE_NEWNS as u64, "CLONE_NEWNS"),
+    (libc::CLONE_NEWCGROUP as u64, "CLONE_NEWCGROUP"),
+    (libc::CLONE_NEWUTS as u64, "CLONE_NEWUTS"),
+    (libc::CLONE_NEWIPC as u64, "CLONE_NEWIPC"),
+    (libc::CLONE_NEWUSER as u64, "CLONE_NEWUSER"),
+    (libc::CLONE_NEWPID as u64, "CLONE_NEWPID"),
+    (libc::CLONE_NEWNET as u64, "CLONE_NEWNET"),
+    (libc::CLONE_NEWTIME as u64, "CLONE_NEWTIME"),
+    (libc::CLONE_PIDFD as u64, "CLONE_PIDFD"),
+    (libc::CLONE_VM as u64, "CLONE_VM"),
+    (libc::CLONE_FS as u64, "CLONE_FS"),
+    (libc::CLONE_FILES as u64, "CLONE_FILES"),
+];
+
+/// A step of [`Container::spawn`](crate::Container::spawn), in the order
+/// they are taken: the parent's up to [`Operation::Clone`] and
+/// [`Operation::Wake`], then the child's up to [`Operation::Exec`].
+///
+/// Errors are left out, like the redirections of the standard streams.
+/// Paths are the ones the child uses, so under the rootfs until the root is
+/// changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    /// Create the cgroup `name` and set its limits, as (setting, value).
+    CreateCgroup {
+        name: PathBuf,
+        limits: Vec<(&'static str, String)>,
+    },
+    /// clone3(2) the container's init.
+    Clone {
+        flags: u64,
+    },
+    /// Write the uid and gid maps of the child.
+    WriteIdMaps {
+        uid_maps: Vec<IdMap>,
+        gid_maps: Vec<IdMap>,
+    },
+    /// Move the child into the v1 cgroup `name`.
+    AddToCgroup {
+        name: PathBuf,
+    },
+    /// prlimit(2) the `resource` of the child.
+    SetRlimit {
+        resource: &'static str,
+        soft: u64,
+        hard: u64,
+    },
+    /// Call a [`Container::after_clone`](crate::Container::after_clone)
+    /// callback.
+    AfterClone,
+    /// Let the child go on, it waits for the parent when it has something to
+    /// set up.
+    Wake,
+    /// Call a [`Container::after_start`](crate::Container::after_start)
+    /// callback, while the child goes on.
+    AfterStart,
+    SetHostname(String),
+    SetDomainname(String),
+    /// Bring up the loopback interface of the new network namespace.
+    LoopbackUp,
+    /// setns(2) into the `namespaces` of the process referred to by the
+    /// pidfd `fd`. The parent enters the pid namespace alone for the clone.
+    JoinNamespaces {
+        fd: i32,
+        namespaces: Namespaces,
+    },
+    /// unshare(2) the `namespaces`, which are created after the clone.
+    Unshare {
+        namespaces: Namespaces,
+    },
+    /// setns(2) into the mount namespace referred to by `fd`.
+    EnterMountNamespace {
+        fd: i32,
+    },
+    /// Mount `source` on `target`, with the options listed like mount(8).
+    Mount {
+        source: String,
+        target: String,
+        fs_type: Option<String>,
+        options: Vec<String>,
+    },
+    /// Cover `path`: a file with `/dev/null`, a directory with an empty
+    /// read-only tmpfs.
+    Mask {
+        path: String,
+    },
+    /// Create the directory `path`, if it doesn't exist.
+    Mkdir {
+        path: String,
+    },
+    /// Give the `path` to the `uid`.
+    Chown {
+        path: String,
+        uid: u32,
+    },
+    /// Create the file `path`, or replace its contents.
+    WriteFile {
+        path: String,
+    },
+    PivotRoot {
+        new_root: String,
+        put_old: String,
+    },
+    /// Detach the mount at `target`.
+    Unmount {
+        target: String,
+    },
+    Rmdir {
+        path: String,
+    },
+    Chroot {
+        path: String,
+    },
+    Chdir {
+        path: String,
+    },
+    /// fchdir(2) to the directory referred to by `fd`.
+    Fchdir {
+        fd: i32,
+    },
+    /// sched_setscheduler(2) the child with `policy`, inherited across exec.
+    SetScheduler {
+        policy: &'static str,
+    },
+    /// Set the file mode creation mask.
+    Umask {
+        mask: u32,
+    },
+    /// Disable address space randomization with personality(2), for the
+    /// command once executed.
+    DisableAslr,
+    /// Set the label of the `lsm` applied on exec.
+    SetExecLabel {
+        lsm: &'static str,
+        label: String,
+    },
+    /// Enforce the Landlock ruleset.
+    RestrictLandlock,
+    /// Load the filter of
+    /// [`Container::seccomp_default`](crate::Container::seccomp_default).
+    LoadSeccompDefault,
+    /// Load the seccomp filter notifying the parent of the `syscalls`, and
+    /// send it the listener.
+    LoadSeccompFilter {
+        syscalls: Vec<i64>,
+    },
+    DropBoundingCaps(Vec<Capability>),
+    RaiseAmbientCaps(Vec<Capability>),
+    /// Stop with `SIGSTOP`, traced by the parent.
+    StopBeforeExec,
+    /// execve(2) `path`, or every candidate of `PATH` with
+    /// [`Container::search_path`](crate::Container::search_path).
+    Exec {
+        path: String,
+        argv: Vec<String>,
+        envp: Vec<String>,
+    },
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::CreateCgroup { name, limits } => {
+                write!(f, "create cgroup {}", name.display())?;
+                for (setting, value) in limits {
+                    write!(f, " {setting}={value}")?;
+                }
+                Ok(())
+            }
+            Operation::Clone { flags } => {
+                write!(f, "clone3({})", flag_names(*flags))
+            }
+            Operation::WriteIdMaps { uid_maps, gid_maps } => {
+                let lines = |maps: &[IdMap]| {
+                    maps.iter()
+                        .map(|map| {
+                            format!(
+                                "{} {} {}",
+                                map.inside, map.outside, map.count
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                };
+                write!(
+                    f,
+                    "write uid_map [{}] gid_map [{}]",
+                    lines(uid_maps),
+                    lines(gid_maps)
+                )
+            }
+            Operation::AddToCgroup { name } => {
+                write!(f, "add to cgroup {}", name.display())
+            }
+            Operation::SetRlimit {
+                resource,
+                soft,
+                hard,
+            } => write!(f, "prlimit({resource}, {soft}, {hard})"),
+            Operation::AfterClone => write!(f, "after_clone callback"),
+            Operation::Wake => write!(f, "wake"),
+            Operation::AfterStart => write!(f, "after_start callback"),
+            Operation::SetHostname(name) => write!(f, "sethostname({name:?})"),
+            Operation::SetDomainname(name) => {
+                write!(f, "setdomainname({name:?})")
+            }
+            Operation::LoopbackUp => write!(f, "bring up lo"),
+            Operation::JoinNamespaces { fd, namespaces } => {
+                let flags = namespaces.bits() as u64;
+                write!(f, "setns({fd}, {})", flag_names(flags))
+            }
+            Operation::Unshare { namespaces } => {
+                let flags = namespaces.bits() as u64;
+                write!(f, "unshare({})", flag_names(flags))
+            }
+            Operation::EnterMountNamespace { fd } => {
+                write!(f, "setns({fd}, CLONE_NEWNS)")
+            }
+            Operation::Mount {
+                source,
+                target,
+                fs_type,
+                options,
+            } => {
+                write!(f, "mount {source} on {target}")?;
+                if let Some(fs_type) = fs_type {
+                    write!(f, " type {fs_type}")?;
+                }
+                write!(f, " ({})", options.join(","))
+            }
+            Operation::Mask { path } => write!(f, "mask {path}"),
+            Operation::Mkdir { path } => write!(f, "mkdir {path}"),
+            Operation::Chown { path, uid } => write!(f, "chown {uid} {path}"),
+            Operation::WriteFile { path } => write!(f, "write {path}"),
+            Operation::PivotRoot { new_root, put_old } => {
+                write!(f, "pivot_root({new_root:?}, {put_old:?})")
+            }
+            Operation::Unmount { target } => write!(f, "umount -l {target}"),
+            Operation::Rmdir { path } => write!(f, "rmdir {path}"),
+            Operation::Chroot { path } => write!(f, "chroot({path:?})"),
+            Operation::Chdir { path } => write!(f, "chdir({path:?})"),
+            Operation::Fchdir { fd } => write!(f, "fchdir({fd})"),
+            Operation::SetScheduler { policy } => {
+                write!(f, "sched_setscheduler({policy}, 0)")
+            }
+            Operation::Umask { mask } => write!(f, "umask({mask:03o})"),
+            Operation::DisableAslr => {
+                write!(f, "personality(ADDR_NO_RANDOMIZE)")
+            }
+            Operation::SetExecLabel { lsm, label } => {
+                write!(f, "set {lsm} exec label {label}")
+            }
+            Operation::RestrictLandlock => write!(f, "landlock_restrict_self"),
+            Operation::LoadSeccompDefault => {
+                write!(f, "seccomp default profile")
+            }
+            Operation::LoadSeccompFilter { syscalls } => {
+                write!(f, "seccomp notify {syscalls:?}")
+            }
+            Operation::DropBoundingCaps(caps) => {
+                write!(f, "drop bounding caps {caps:?}")
+            }
+            Operation::RaiseAmbientCaps(caps) => {
+                write!(f, "raise ambient caps {caps:?}")
+            }
+            Operation::StopBeforeExec => write!(f, "stop before exec"),
+            Operation::Exec { path, argv, envp } => {
+                write!(f, "execve({path:?}, {argv:?}, {envp:?})")
+            }
+        }
+    }
+}
+
+/// Name the clone `flags`, like `CLONE_NEWNS|CLONE_PIDFD`.
+fn flag_names(flags: u64) -> String {
+    let mut rest = flags;
+    let mut names = vec![];
+    for (flag, name) in CLONE_FLAGS {
+        if rest & flag != 0 {
+            names.push(name.to_owned());
+            rest &= !flag;
+        }
+    }
+    if rest != 0 {
+        names.push(format!("{rest:#x}"));
+    }
+    names.join("|")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn clone_flags_are_named() {
+        let flags = (libc::CLONE_NEWNS | libc::CLONE_PIDFD) as u64 | 1 << 40;
+        assert_eq!(
+            Operation::Clone { flags }.to_string(),
+            "clone3(CLONE_NEWNS|CLONE_PIDFD|0x10000000000)"
+        );
+    }
+}
@@ -0,0 +1,305 @@
+//! Pseudo-terminals for containers run with [`Container::tty`], and what it
+//! takes to attach the host's terminal to one, see pty(7).
+//!
+//! [`Container::tty`]: crate::Container::tty
+
+use std::{
+    ffi::CStr,
+    io::{self, ErrorKind, Write},
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    sync::atomic::{AtomicI32, Ordering},
+};
+
+/// The write end of the pipe the `SIGWINCH` handler writes to while a
+/// terminal is attached, -1 otherwise.
+static WINCH_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+/// Open a new pseudo-terminal, returning its (master, slave) ends.
+pub(crate) fn open() -> io::Result<(OwnedFd, OwnedFd)> {
+    let fd = unsafe {
+        libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC)
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: posix_openpt returned a new fd.
+    let master = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    let mut name = [0 as libc::c_char; 64];
+    if unsafe { libc::grantpt(fd) } != 0
+        || unsafe { libc::unlockpt(fd) } != 0
+        || unsafe { libc::ptsname_r(fd, name.as_mut_ptr(), name.len()) } != 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: ptsname_r null terminated the name.
+    let name = unsafe { CStr::from_ptr(name.as_ptr()) };
+    let fd = unsafe {
+        libc::open(
+            name.as_ptr(),
+            libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: open returned a new fd.
+    let slave = unsafe { OwnedFd::from_raw_fd(fd) };
+    Ok((master, slave))
+}
+
+/// Give the terminal `to` the window size of the terminal `from`.
+pub fn copy_window_size<F: AsRawFd, T: AsRawFd>(
+    from: &F,
+    to: &T,
+) -> io::Result<()> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(from.as_raw_fd(), libc::TIOCGWINSZ, &mut size) }
+        != 0
+        || unsafe { libc::ioctl(to.as_raw_fd(), libc::TIOCSWINSZ, &size) } != 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The terminal of our stdin in raw mode (see cfmakeraw(3)), restored when
+/// dropped, including on panic.
+pub struct RawMode {
+    original: libc::termios,
+}
+
+impl RawMode {
+    /// Put the terminal of stdin in raw mode, `None` if stdin isn't a
+    /// terminal.
+    pub fn stdin() -> io::Result<Option<Self>> {
+        if unsafe { libc::isatty(libc::STDIN_FILENO) } != 1 {
+            return Ok(None);
+        }
+
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut raw = original;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) }
+            != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Some(Self { original }))
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original)
+        };
+    }
+}
+
+/// A `SIGWINCH` handler writing to a pipe, the old handler is put back when
+/// dropped.
+struct WinchHandler {
+    read: OwnedFd,
+    write: OwnedFd,
+    old: libc::sigaction,
+}
+
+extern "C" fn on_winch(_: libc::c_int) {
+    let fd = WINCH_PIPE.load(Ordering::Relaxed);
+    if fd >= 0 {
+        // Don't clobber the errno of the interrupted code.
+        let errno = unsafe { *libc::__errno_location() };
+        unsafe { libc::write(fd, [0u8].as_ptr().cast(), 1) };
+        unsafe { *libc::__errno_location() = errno };
+    }
+}
+
+impl WinchHandler {
+    fn install() -> io::Result<Self> {
+        let mut fds = [0; 2];
+        let flags = libc::O_CLOEXEC | libc::O_NONBLOCK;
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), flags) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: pipe2 returned two new file descriptors.
+        let (read, write) = unsafe {
+            (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1]))
+        };
+        if WINCH_PIPE
+            .compare_exchange(-1, fds[1], Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err(io::Error::new(
+                ErrorKind::ResourceBusy,
+                "a terminal is already attached",
+            ));
+        }
+
+        let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+        action.sa_sigaction = on_winch as *const () as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        let mut old: libc::sigaction = unsafe { std::mem::zeroed() };
+        if unsafe { libc::sigaction(libc::SIGWINCH, &action, &mut old) } != 0 {
+            WINCH_PIPE.store(-1, Ordering::Relaxed);
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { read, write, old })
+    }
+
+    /// Drop the pending notifications, returning whether there were any.
+    fn drain(&self) -> bool {
+        let mut buf = [0u8; 16];
+        let mut resized = false;
+        while unsafe {
+            libc::read(
+                self.read.as_raw_fd(),
+                buf.as_mut_ptr().cast(),
+                buf.len(),
+            )
+        } > 0
+        {
+            resized = true;
+        }
+        resized
+    }
+}
+
+impl Drop for WinchHandler {
+    fn drop(&mut self) {
+        unsafe {
+            libc::sigaction(libc::SIGWINCH, &self.old, std::ptr::null_mut())
+        };
+        let _ = WINCH_PIPE.compare_exchange(
+            self.write.as_raw_fd(),
+            -1,
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        );
+    }
+}
+
+/// Connect our stdin and stdout to the terminal `master` until the
+/// container closes its side, with stdin in raw mode and the window size
+/// kept in sync.
+pub(crate) fn attach(master: &OwnedFd) -> io::Result<()> {
+    let raw_mode = RawMode::stdin()?;
+    let winch = WinchHandler::install()?;
+    if raw_mode.is_some() {
+        let _ = copy_window_size(&io::stdin(), master);
+    }
+
+    let pollfd = |fd: RawFd| libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let mut fds = [
+        pollfd(libc::STDIN_FILENO),
+        pollfd(master.as_raw_fd()),
+        pollfd(winch.read.as_raw_fd()),
+    ];
+    let mut buf = [0u8; 8192];
+    let mut stdout = io::stdout().lock();
+    loop {
+        if unsafe {
+            libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1)
+        } < 0
+        {
+            match io::Error::last_os_error() {
+                err if err.kind() == ErrorKind::Interrupted => continue,
+                err => return Err(err),
+            }
+        }
+
+        if fds[1].revents != 0 {
+            // The read fails with EIO once every fd of the slave is closed.
+            match read(master.as_raw_fd(), &mut buf) {
+                Ok(0) => break,
+                Ok(len) => {
+                    stdout.write_all(&buf[..len])?;
+                    stdout.flush()?;
+                }
+                Err(err) if err.raw_os_error() == Some(libc::EIO) => break,
+                Err(err) => return Err(err),
+            }
+        }
+        if fds[0].revents != 0 {
+            match read(libc::STDIN_FILENO, &mut buf)? {
+                // A negative fd is skipped by poll.
+                0 => fds[0].fd = -1,
+                len => write_all(master.as_raw_fd(), &buf[..len])?,
+            }
+        }
+        if fds[2].revents != 0 && winch.drain() && raw_mode.is_some() {
+            let _ = copy_window_size(&io::stdin(), master);
+        }
+    }
+    Ok(())
+}
+
+fn read(fd: RawFd, buf: &mut [u8]) -> io::Result<usize> {
+    loop {
+        let len = unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) };
+        match len {
+            0.. => return Ok(len as usize),
+            _ => match io::Error::last_os_error() {
+                err if err.kind() == ErrorKind::Interrupted => continue,
+                err => return Err(err),
+            },
+        }
+    }
+}
+
+fn write_all(fd: RawFd, mut buf: &[u8]) -> io::Result<()> {
+    while !buf.is_empty() {
+        let len = unsafe { libc::write(fd, buf.as_ptr().cast(), buf.len()) };
+        match len {
+            0.. => buf = &buf[len as usize..],
+            _ => match io::Error::last_os_error() {
+                err if err.kind() == ErrorKind::Interrupted => continue,
+                err => return Err(err),
+            },
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn slave_writes_are_read_from_the_master() {
+        let (master, slave) = open().unwrap();
+        write_all(slave.as_raw_fd(), b"hi\n").unwrap();
+        let mut buf = [0u8; 16];
+        let len = read(master.as_raw_fd(), &mut buf).unwrap();
+        // The line discipline turns the newline into CR LF.
+        assert_eq!(&buf[..len], b"hi\r\n");
+    }
+
+    #[test]
+    fn window_size_is_copied() {
+        let (from, _from_slave) = open().unwrap();
+        let (to, _to_slave) = open().unwrap();
+        let size = libc::winsize {
+            ws_row: 24,
+            ws_col: 80,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        assert_eq!(
+            unsafe { libc::ioctl(from.as_raw_fd(), libc::TIOCSWINSZ, &size) },
+            0
+        );
+
+        copy_window_size(&from, &to).unwrap();
+        let mut copied: libc::winsize = unsafe { std::mem::zeroed() };
+        unsafe { libc::ioctl(to.as_raw_fd(), libc::TIOCGWINSZ, &mut copied) };
+        assert_eq!((copied.ws_row, copied.ws_col), (24, 80));
+    }
+}
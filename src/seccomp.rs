@@ -0,0 +1,643 @@
+//! Seccomp filters: the default profile and user notifications, see
+//! seccomp(2) and seccomp_unotify(2).
+//!
+//! The filters are built in the parent and loaded by the child right before
+//! exec. The notify filter is loaded with `SECCOMP_FILTER_FLAG_NEW_LISTENER`,
+//! the child sends the listener fd back over its socket with
+//! [`crate::fd_channel`], and the syscalls of the filter then wait for a
+//! [`respond`] to each notification [`recv`]ed from it.
+
+use std::{
+    io::{self, Error},
+    mem,
+    os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd, RawFd},
+};
+
+/// The `AUDIT_ARCH_*` of the syscalls the filter matches, the others are
+/// killed: a syscall number means another syscall on another arch.
+#[cfg(target_arch = "x86_64")]
+pub(crate) const AUDIT_ARCH: Option<u32> = Some(0xc000_003e);
+#[cfg(target_arch = "aarch64")]
+pub(crate) const AUDIT_ARCH: Option<u32> = Some(0xc000_00b7);
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+pub(crate) const AUDIT_ARCH: Option<u32> = None;
+
+/// The syscalls of the child between loading the filter and the exec, which
+/// would wait for a parent that waits for the exec.
+pub(crate) const SPAWN_SYSCALLS: [i64; 4] = [
+    libc::SYS_sendmsg,
+    libc::SYS_sendto,
+    libc::SYS_execve,
+    libc::SYS_execveat,
+];
+
+/// The syscalls the default profile allows on every arch, roughly the
+/// unconditional allowlist of the Docker and Podman default profile: what
+/// creates namespaces, mounts, loads modules or changes the system (time,
+/// reboot, swap, keyring, bpf...) is left out. ptrace is allowed like on
+/// Linux 4.8 and later, which run the filter again after a tracer's changes.
+/// `clone` is allowed without the namespace flags, see [`default_filter`].
+const DEFAULT_ALLOWED: [i64; 240] = [
+    libc::SYS_accept,
+    libc::SYS_accept4,
+    libc::SYS_adjtimex,
+    libc::SYS_bind,
+    libc::SYS_brk,
+    libc::SYS_capget,
+    libc::SYS_capset,
+    libc::SYS_chdir,
+    libc::SYS_clock_adjtime,
+    libc::SYS_clock_getres,
+    libc::SYS_clock_gettime,
+    libc::SYS_clock_nanosleep,
+    libc::SYS_close,
+    libc::SYS_close_range,
+    libc::SYS_connect,
+    libc::SYS_copy_file_range,
+    libc::SYS_dup,
+    libc::SYS_dup3,
+    libc::SYS_epoll_create1,
+    libc::SYS_epoll_ctl,
+    libc::SYS_epoll_pwait,
+    libc::SYS_epoll_pwait2,
+    libc::SYS_eventfd2,
+    libc::SYS_execve,
+    libc::SYS_execveat,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+    libc::SYS_faccessat,
+    libc::SYS_faccessat2,
+    libc::SYS_fallocate,
+    libc::SYS_fanotify_mark,
+    libc::SYS_fchdir,
+    libc::SYS_fchmod,
+    libc::SYS_fchmodat,
+    libc::SYS_fchown,
+    libc::SYS_fchownat,
+    libc::SYS_fcntl,
+    libc::SYS_fdatasync,
+    libc::SYS_fgetxattr,
+    libc::SYS_flistxattr,
+    libc::SYS_flock,
+    libc::SYS_fremovexattr,
+    libc::SYS_fsetxattr,
+    libc::SYS_fstat,
+    libc::SYS_fstatfs,
+    libc::SYS_fsync,
+    libc::SYS_ftruncate,
+    libc::SYS_futex,
+    libc::SYS_futex_waitv,
+    libc::SYS_getcpu,
+    libc::SYS_getcwd,
+    libc::SYS_getdents64,
+    libc::SYS_getegid,
+    libc::SYS_geteuid,
+    libc::SYS_getgid,
+    libc::SYS_getgroups,
+    libc::SYS_getitimer,
+    libc::SYS_getpeername,
+    libc::SYS_getpgid,
+    libc::SYS_getpid,
+    libc::SYS_getppid,
+    libc::SYS_getpriority,
+    libc::SYS_getrandom,
+    libc::SYS_getresgid,
+    libc::SYS_getresuid,
+    libc::SYS_get_robust_list,
+    libc::SYS_getrusage,
+    libc::SYS_getsid,
+    libc::SYS_getsockname,
+    libc::SYS_getsockopt,
+    libc::SYS_gettid,
+    libc::SYS_gettimeofday,
+    libc::SYS_getuid,
+    libc::SYS_getxattr,
+    libc::SYS_inotify_add_watch,
+    libc::SYS_inotify_init1,
+    libc::SYS_inotify_rm_watch,
+    libc::SYS_io_cancel,
+    libc::SYS_ioctl,
+    libc::SYS_io_destroy,
+    libc::SYS_io_getevents,
+    libc::SYS_ioprio_get,
+    libc::SYS_ioprio_set,
+    libc::SYS_io_setup,
+    libc::SYS_io_submit,
+    libc::SYS_kill,
+    libc::SYS_landlock_add_rule,
+    libc::SYS_landlock_create_ruleset,
+    libc::SYS_landlock_restrict_self,
+    libc::SYS_lgetxattr,
+    libc::SYS_linkat,
+    libc::SYS_listen,
+    libc::SYS_listxattr,
+    libc::SYS_llistxattr,
+    libc::SYS_lremovexattr,
+    libc::SYS_lseek,
+    libc::SYS_lsetxattr,
+    libc::SYS_madvise,
+    libc::SYS_membarrier,
+    libc::SYS_memfd_create,
+    libc::SYS_mincore,
+    libc::SYS_mkdirat,
+    libc::SYS_mknodat,
+    libc::SYS_mlock,
+    libc::SYS_mlock2,
+    libc::SYS_mlockall,
+    libc::SYS_mmap,
+    libc::SYS_mprotect,
+    libc::SYS_mq_getsetattr,
+    libc::SYS_mq_notify,
+    libc::SYS_mq_open,
+    libc::SYS_mq_timedreceive,
+    libc::SYS_mq_timedsend,
+    libc::SYS_mq_unlink,
+    libc::SYS_mremap,
+    libc::SYS_msgctl,
+    libc::SYS_msgget,
+    libc::SYS_msgrcv,
+    libc::SYS_msgsnd,
+    libc::SYS_msync,
+    libc::SYS_munlock,
+    libc::SYS_munlockall,
+    libc::SYS_munmap,
+    libc::SYS_name_to_handle_at,
+    libc::SYS_nanosleep,
+    libc::SYS_newfstatat,
+    libc::SYS_openat,
+    libc::SYS_openat2,
+    libc::SYS_pidfd_open,
+    libc::SYS_pidfd_send_signal,
+    libc::SYS_pipe2,
+    libc::SYS_pkey_alloc,
+    libc::SYS_pkey_free,
+    libc::SYS_pkey_mprotect,
+    libc::SYS_ppoll,
+    libc::SYS_prctl,
+    libc::SYS_pread64,
+    libc::SYS_preadv,
+    libc::SYS_preadv2,
+    libc::SYS_prlimit64,
+    libc::SYS_pselect6,
+    libc::SYS_ptrace,
+    libc::SYS_pwrite64,
+    libc::SYS_pwritev,
+    libc::SYS_pwritev2,
+    libc::SYS_read,
+    libc::SYS_readahead,
+    libc::SYS_readlinkat,
+    libc::SYS_readv,
+    libc::SYS_recvfrom,
+    libc::SYS_recvmmsg,
+    libc::SYS_recvmsg,
+    libc::SYS_remap_file_pages,
+    libc::SYS_removexattr,
+    libc::SYS_renameat2,
+    libc::SYS_restart_syscall,
+    libc::SYS_rseq,
+    libc::SYS_rt_sigaction,
+    libc::SYS_rt_sigpending,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_rt_sigqueueinfo,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_rt_sigsuspend,
+    libc::SYS_rt_sigtimedwait,
+    libc::SYS_rt_tgsigqueueinfo,
+    libc::SYS_sched_getaffinity,
+    libc::SYS_sched_getattr,
+    libc::SYS_sched_getparam,
+    libc::SYS_sched_get_priority_max,
+    libc::SYS_sched_get_priority_min,
+    libc::SYS_sched_getscheduler,
+    libc::SYS_sched_rr_get_interval,
+    libc::SYS_sched_setaffinity,
+    libc::SYS_sched_setattr,
+    libc::SYS_sched_setparam,
+    libc::SYS_sched_setscheduler,
+    libc::SYS_sched_yield,
+    libc::SYS_seccomp,
+    libc::SYS_semctl,
+    libc::SYS_semget,
+    libc::SYS_semop,
+    libc::SYS_semtimedop,
+    libc::SYS_sendmmsg,
+    libc::SYS_sendmsg,
+    libc::SYS_sendto,
+    libc::SYS_setfsgid,
+    libc::SYS_setfsuid,
+    libc::SYS_setgid,
+    libc::SYS_setgroups,
+    libc::SYS_setitimer,
+    libc::SYS_setpgid,
+    libc::SYS_setpriority,
+    libc::SYS_setregid,
+    libc::SYS_setresgid,
+    libc::SYS_setresuid,
+    libc::SYS_setreuid,
+    libc::SYS_set_robust_list,
+    libc::SYS_setsid,
+    libc::SYS_setsockopt,
+    libc::SYS_set_tid_address,
+    libc::SYS_setuid,
+    libc::SYS_setxattr,
+    libc::SYS_shmat,
+    libc::SYS_shmctl,
+    libc::SYS_shmdt,
+    libc::SYS_shmget,
+    libc::SYS_shutdown,
+    libc::SYS_sigaltstack,
+    libc::SYS_signalfd4,
+    libc::SYS_socket,
+    libc::SYS_socketpair,
+    libc::SYS_splice,
+    libc::SYS_statfs,
+    libc::SYS_statx,
+    libc::SYS_symlinkat,
+    libc::SYS_sync,
+    libc::SYS_syncfs,
+    libc::SYS_sysinfo,
+    libc::SYS_tee,
+    libc::SYS_tgkill,
+    libc::SYS_timer_create,
+    libc::SYS_timer_delete,
+    libc::SYS_timer_getoverrun,
+    libc::SYS_timer_gettime,
+    libc::SYS_timer_settime,
+    libc::SYS_timerfd_create,
+    libc::SYS_timerfd_gettime,
+    libc::SYS_timerfd_settime,
+    libc::SYS_times,
+    libc::SYS_tkill,
+    libc::SYS_truncate,
+    libc::SYS_umask,
+    libc::SYS_uname,
+    libc::SYS_unlinkat,
+    libc::SYS_utimensat,
+    libc::SYS_vmsplice,
+    libc::SYS_wait4,
+    libc::SYS_waitid,
+    libc::SYS_write,
+    libc::SYS_writev,
+];
+
+/// The syscalls of [`DEFAULT_ALLOWED`] only x86_64 has, the older ones the
+/// generic syscall table replaced with an `*at` or `p*` variant.
+#[cfg(target_arch = "x86_64")]
+const ARCH_ALLOWED: [i64; 44] = [
+    libc::SYS_access,
+    libc::SYS_alarm,
+    libc::SYS_chmod,
+    libc::SYS_chown,
+    libc::SYS_creat,
+    libc::SYS_dup2,
+    libc::SYS_epoll_create,
+    libc::SYS_epoll_wait,
+    libc::SYS_eventfd,
+    libc::SYS_fadvise64,
+    libc::SYS_fchmodat2,
+    libc::SYS_fork,
+    libc::SYS_futimesat,
+    libc::SYS_getdents,
+    libc::SYS_getpgrp,
+    libc::SYS_getrlimit,
+    libc::SYS_inotify_init,
+    libc::SYS_lchown,
+    libc::SYS_link,
+    libc::SYS_lstat,
+    libc::SYS_mkdir,
+    libc::SYS_mknod,
+    libc::SYS_open,
+    libc::SYS_pause,
+    libc::SYS_pipe,
+    libc::SYS_poll,
+    libc::SYS_readlink,
+    libc::SYS_rename,
+    libc::SYS_renameat,
+    libc::SYS_rmdir,
+    libc::SYS_select,
+    libc::SYS_sendfile,
+    libc::SYS_setrlimit,
+    libc::SYS_signalfd,
+    libc::SYS_stat,
+    libc::SYS_symlink,
+    libc::SYS_sync_file_range,
+    libc::SYS_time,
+    libc::SYS_unlink,
+    libc::SYS_utime,
+    libc::SYS_utimes,
+    libc::SYS_vfork,
+    libc::SYS_arch_prctl,
+    libc::SYS_clone,
+];
+/// The syscalls of [`DEFAULT_ALLOWED`] missing from libc on aarch64, by
+/// their number in the generic syscall table.
+#[cfg(target_arch = "aarch64")]
+const ARCH_ALLOWED: [i64; 6] = [
+    71,  // sendfile
+    84,  // sync_file_range
+    163, // getrlimit
+    164, // setrlimit
+    223, // fadvise64
+    452, // fchmodat2
+];
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const ARCH_ALLOWED: [i64; 0] = [];
+
+/// The `clone` flags creating a namespace, denied by the default profile.
+const CLONE_NAMESPACE_FLAGS: u32 = (libc::CLONE_NEWNS
+    | libc::CLONE_NEWCGROUP
+    | libc::CLONE_NEWUTS
+    | libc::CLONE_NEWIPC
+    | libc::CLONE_NEWUSER
+    | libc::CLONE_NEWPID
+    | libc::CLONE_NEWNET) as u32;
+
+/// The x32 syscalls share the arch of x86_64, with this bit set in their
+/// number.
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+const SECCOMP_IOCTL_NOTIF_RECV: libc::Ioctl =
+    libc::_IOWR::<libc::seccomp_notif>(b'!' as u32, 0);
+const SECCOMP_IOCTL_NOTIF_SEND: libc::Ioctl =
+    libc::_IOWR::<libc::seccomp_notif_resp>(b'!' as u32, 1);
+
+/// The offsets of the fields of `struct seccomp_data` the filter loads.
+const NR_OFFSET: u32 = 0;
+const ARCH_OFFSET: u32 = 4;
+/// The low 32 bits of the first argument, on a little endian arch.
+const ARG0_OFFSET: u32 = 16;
+
+// SAFETY: The macros of linux/filter.h only build the struct.
+fn stmt(code: u32, k: u32) -> libc::sock_filter {
+    unsafe { libc::BPF_STMT(code as u16, k) }
+}
+
+fn jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    unsafe { libc::BPF_JUMP(code as u16, k, jt, jf) }
+}
+
+fn load_word(offset: u32) -> libc::sock_filter {
+    stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, offset)
+}
+
+fn ret(action: u32) -> libc::sock_filter {
+    stmt(libc::BPF_RET | libc::BPF_K, action)
+}
+
+/// The start of a filter: kill the syscalls of another arch, then load the
+/// syscall number. `None` on an arch without an [`AUDIT_ARCH`].
+fn filter_prologue() -> Option<Vec<libc::sock_filter>> {
+    let mut filter = vec![
+        load_word(ARCH_OFFSET),
+        jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            AUDIT_ARCH?,
+            1,
+            0,
+        ),
+        ret(libc::SECCOMP_RET_KILL_PROCESS),
+        load_word(NR_OFFSET),
+    ];
+    if cfg!(target_arch = "x86_64") {
+        filter.extend([
+            jump(
+                libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K,
+                X32_SYSCALL_BIT,
+                0,
+                1,
+            ),
+            ret(libc::SECCOMP_RET_KILL_PROCESS),
+        ]);
+    }
+    Some(filter)
+}
+
+/// Build the filter notifying the listener of `syscalls` and allowing the
+/// others, `None` on an arch without an [`AUDIT_ARCH`].
+pub(crate) fn notify_filter(
+    syscalls: &[i64],
+) -> Option<Vec<libc::sock_filter>> {
+    let mut filter = filter_prologue()?;
+    for &nr in syscalls {
+        filter.extend([
+            jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, nr as u32, 0, 1),
+            ret(libc::SECCOMP_RET_USER_NOTIF),
+        ]);
+    }
+    filter.push(ret(libc::SECCOMP_RET_ALLOW));
+    Some(filter)
+}
+
+/// Build the filter of the default profile, `None` on an arch without an
+/// [`AUDIT_ARCH`].
+///
+/// The syscalls out of the allowlist fail with `EPERM`, except `clone3`
+/// which fails with `ENOSYS`: its flags are behind a pointer a filter can't
+/// read, and libc falls back to `clone` when it's missing. `clone` fails
+/// with `EPERM` when its flags create a namespace.
+pub(crate) fn default_filter() -> Option<Vec<libc::sock_filter>> {
+    let errno = |errno: i32| ret(libc::SECCOMP_RET_ERRNO | errno as u32);
+    let mut filter = filter_prologue()?;
+    filter.extend([
+        jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            libc::SYS_clone3 as u32,
+            0,
+            1,
+        ),
+        errno(libc::ENOSYS),
+    ]);
+    filter.extend([
+        jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            libc::SYS_clone as u32,
+            0,
+            4,
+        ),
+        load_word(ARG0_OFFSET),
+        jump(
+            libc::BPF_JMP | libc::BPF_JSET | libc::BPF_K,
+            CLONE_NAMESPACE_FLAGS,
+            0,
+            1,
+        ),
+        errno(libc::EPERM),
+        ret(libc::SECCOMP_RET_ALLOW),
+    ]);
+    for &nr in DEFAULT_ALLOWED.iter().chain(&ARCH_ALLOWED) {
+        if nr == libc::SYS_clone {
+            continue;
+        }
+        filter.extend([
+            jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, nr as u32, 0, 1),
+            ret(libc::SECCOMP_RET_ALLOW),
+        ]);
+    }
+    filter.push(errno(libc::EPERM));
+    Some(filter)
+}
+
+/// Load `filter` on the calling thread, for good.
+///
+/// Sets `no_new_privs` first, like [`load_with_listener`].
+///
+/// # Signal Safety
+/// This function is signal safe.
+pub(crate) fn load(filter: &[libc::sock_filter]) -> Result<(), Error> {
+    set_filter(filter, 0).map(drop)
+}
+
+/// Load `filter` on the calling thread and return its listener fd.
+///
+/// Without `CAP_SYS_ADMIN` loading a filter requires `no_new_privs`, so this
+/// sets it first, like [`crate::landlock::restrict_self`].
+///
+/// # Signal Safety
+/// This function is signal safe.
+pub(crate) fn load_with_listener(
+    filter: &[libc::sock_filter],
+) -> Result<OwnedFd, Error> {
+    let fd = set_filter(filter, libc::SECCOMP_FILTER_FLAG_NEW_LISTENER)?;
+    // SAFETY: seccomp returned a new fd.
+    Ok(unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
+}
+
+/// Set `no_new_privs` and load `filter` with the `flags`, returning what
+/// seccomp(2) returns.
+///
+/// # Signal Safety
+/// This function is signal safe.
+fn set_filter(
+    filter: &[libc::sock_filter],
+    flags: libc::c_ulong,
+) -> Result<libc::c_long, Error> {
+    let ret = unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) };
+    if ret != 0 {
+        return Err(Error::last_os_error());
+    }
+
+    let prog = libc::sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_ptr().cast_mut(),
+    };
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_seccomp,
+            libc::SECCOMP_SET_MODE_FILTER,
+            flags,
+            &prog as *const libc::sock_fprog,
+        )
+    };
+    if fd < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(fd)
+}
+
+/// Wait for the next notification of the `listener`.
+///
+/// The syscall of the notification waits until it's answered with
+/// [`respond`], its arguments are in `data.args` (pointers into the memory
+/// of the process `pid`).
+pub fn recv<F: AsFd>(listener: &F) -> Result<libc::seccomp_notif, Error> {
+    loop {
+        // The kernel rejects a notification that isn't zeroed.
+        let mut notif: libc::seccomp_notif = unsafe { mem::zeroed() };
+        let ret = unsafe {
+            libc::ioctl(
+                listener.as_fd().as_raw_fd(),
+                SECCOMP_IOCTL_NOTIF_RECV,
+                &mut notif,
+            )
+        };
+        match ret {
+            0 => return Ok(notif),
+            _ => match Error::last_os_error() {
+                err if err.kind() == io::ErrorKind::Interrupted => continue,
+                err => return Err(err),
+            },
+        }
+    }
+}
+
+/// How a notified syscall returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Response {
+    /// Return `val` without executing the syscall.
+    Return(i64),
+    /// Fail with the errno, without executing the syscall.
+    Error(i32),
+    /// Execute the syscall as if it wasn't filtered. Its arguments may have
+    /// changed since they were looked at, so this can't be used to allow a
+    /// syscall after checking them.
+    Continue,
+}
+
+/// Answer the notification `id` of the `listener`.
+///
+/// Fails with `ENOENT` if the syscall was interrupted meanwhile, e.g. the
+/// process was killed.
+pub fn respond<F: AsFd>(
+    listener: &F,
+    id: u64,
+    response: Response,
+) -> Result<(), Error> {
+    let (val, error, flags) = match response {
+        Response::Return(val) => (val, 0, 0),
+        Response::Error(errno) => (0, -errno, 0),
+        Response::Continue => {
+            (0, 0, libc::SECCOMP_USER_NOTIF_FLAG_CONTINUE as u32)
+        }
+    };
+    let resp = libc::seccomp_notif_resp {
+        id,
+        val,
+        error,
+        flags,
+    };
+    let ret = unsafe {
+        libc::ioctl(
+            listener.as_fd().as_raw_fd(),
+            SECCOMP_IOCTL_NOTIF_SEND,
+            &resp,
+        )
+    };
+    if ret != 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn filter_notifies_the_syscalls() {
+        let Some(filter) = notify_filter(&[libc::SYS_getpid, libc::SYS_uname])
+        else {
+            return;
+        };
+        let notified = filter
+            .iter()
+            .filter(|insn| insn.k == libc::SECCOMP_RET_USER_NOTIF)
+            .count();
+        assert_eq!(notified, 2);
+        assert_eq!(filter.last().unwrap().k, libc::SECCOMP_RET_ALLOW);
+    }
+
+    #[test]
+    fn default_filter_fits() {
+        let Some(filter) = default_filter() else {
+            return;
+        };
+        // BPF_MAXINSNS
+        assert!(filter.len() <= 4096);
+        assert_eq!(
+            filter.last().unwrap().k,
+            libc::SECCOMP_RET_ERRNO | libc::EPERM as u32
+        );
+        assert!(!DEFAULT_ALLOWED.contains(&libc::SYS_mount));
+        assert!(!DEFAULT_ALLOWED.contains(&libc::SYS_unshare));
+    }
+}
@@ -0,0 +1,1220 @@
+//! Helpers to run real containers from the crate's tests without root.
+//!
+//! The container gets a user namespace with the current uid and gid mapped
+//! to 0, which is enough to set up its mounts unprivileged. Hosts that
+//! restrict unprivileged user namespaces (e.g. AppArmor on Ubuntu 24.04) must
+//! allow them for these tests to pass.
+
+mod rootfs;
+
+use std::{fs, path::PathBuf};
+
+use crate::{Container, ExitStatus};
+pub(crate) use rootfs::Rootfs;
+
+/// Run `cmd` (the path and its arguments) in a rootless container and wait
+/// for it to exit.
+pub(crate) fn run_rootless(cmd: &[&str]) -> ExitStatus {
+    let rootfs = Rootfs::new();
+    rootfs
+        .container(cmd)
+        .run()
+        .expect("should run the container")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The stdout of `container`, which must exit successfully.
+    fn stdout(container: Container) -> String {
+        let output = container
+            .capture_output()
+            .spawn()
+            .unwrap()
+            .wait_with_output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{:?}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// The filesystem type and the options (of the mount and of its
+    /// superblock) of the last mount at `point` in `mountinfo`.
+    fn mount<'a>(mountinfo: &'a str, point: &str) -> (&'a str, Vec<&'a str>) {
+        let fields: Vec<_> = mountinfo
+            .lines()
+            .rfind(|line| line.split(' ').nth(4) == Some(point))
+            .unwrap_or_else(|| panic!("nothing at {point}:\n{mountinfo}"))
+            .split(' ')
+            .collect();
+        // The optional fields end with "-".
+        let fs_type =
+            fields.iter().position(|field| *field == "-").unwrap() + 1;
+        let options =
+            fields[5].split(',').chain(fields[fs_type + 2].split(','));
+        (fields[fs_type], options.collect())
+    }
+
+    #[test]
+    fn exit_code_is_reported() {
+        let status = run_rootless(&["/bin/sh", "-c", "exit 3"]);
+        assert_eq!(status.code, Some(3));
+        assert_eq!(status.signal, None);
+    }
+
+    #[test]
+    fn current_user_is_root_inside() {
+        let rootfs = Rootfs::new();
+        assert_eq!(stdout(rootfs.container(&["/bin/id", "-u"])), "0\n");
+    }
+
+    #[test]
+    fn entrypoint_is_followed_by_command() {
+        let rootfs = Rootfs::new();
+        let status = rootfs
+            .container(&["/bin/true"])
+            .entrypoint(["/bin/sh", "-c"])
+            .command(["exit $0", "7"])
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(status.code, Some(7));
+    }
+
+    #[test]
+    fn ambient_capabilities_are_raised() {
+        // CAP_NET_BIND_SERVICE is bit 10.
+        let rootfs = Rootfs::new();
+        let container = rootfs
+            .container(&["/bin/grep", "CapAmb", "/proc/self/status"])
+            .ambient_capabilities(&[crate::Capability::NetBindService]);
+        assert_eq!(stdout(container), "CapAmb:\t0000000000000400\n");
+    }
+
+    #[test]
+    fn bounding_caps_are_dropped() {
+        // CAP_NET_RAW is bit 13 of CapBnd, CAP_NET_ADMIN bit 12.
+        let rootfs = Rootfs::new();
+        let container = rootfs
+            .container(&[
+                "/bin/sed",
+                "-n",
+                "s/^CapBnd:\t*//p",
+                "/proc/self/status",
+            ])
+            .drop_bounding_caps(&[crate::Capability::NetRaw]);
+        let bounding =
+            u64::from_str_radix(stdout(container).trim_end(), 16).unwrap();
+        assert_eq!(bounding >> 13 & 1, 0, "{bounding:x}");
+        assert_eq!(bounding >> 12 & 1, 1, "{bounding:x}");
+    }
+
+    #[test]
+    fn chroot_root_mode() {
+        let rootfs = Rootfs::new();
+        let container = rootfs
+            .container(&["/bin/sh", "-c", "cat /proc/1/comm && ls -A /"])
+            .root_mode(crate::RootMode::Chroot);
+        let output = stdout(container);
+        let mut lines = output.lines();
+        assert_eq!(lines.next(), Some("sh"));
+        assert!(lines.all(|entry| entry != "old_root"), "{output}");
+    }
+
+    #[test]
+    fn harden_proc() {
+        let rootfs = Rootfs::new();
+        let mountinfo = stdout(
+            rootfs
+                .container(&["/bin/cat", "/proc/self/mountinfo"])
+                .harden_proc(),
+        );
+        let (_, options) = mount(&mountinfo, "/proc/sys");
+        assert!(options.contains(&"ro"), "{options:?}");
+        // Files are covered by the host's /dev/null, missing ones are skipped.
+        for file in ["/proc/keys", "/proc/timer_list", "/proc/kcore"] {
+            if fs::exists(file).unwrap() {
+                let (fs_type, _) = mount(&mountinfo, file);
+                assert_ne!(fs_type, "proc", "{file}");
+            }
+        }
+    }
+
+    #[test]
+    fn harden_sys() {
+        let rootfs = Rootfs::new();
+        for mode in [crate::NetMode::None, crate::NetMode::Host] {
+            let output = stdout(
+                rootfs
+                    .container(&[
+                        "/bin/sh",
+                        "-c",
+                        "ls -A /sys/firmware /sys/fs/cgroup \
+                         && cat /proc/self/mountinfo",
+                    ])
+                    .net_mode(mode)
+                    .harden_sys(),
+            );
+            let (listing, mountinfo) =
+                output.split_once("\n\n/sys/fs/cgroup:\n").unwrap();
+            assert_eq!(listing, "/sys/firmware:", "{mode:?}");
+            let (fs_type, options) = mount(mountinfo, "/sys/firmware");
+            assert_eq!(fs_type, "tmpfs", "{mode:?}");
+            assert!(options.contains(&"ro"), "{mode:?}: {options:?}");
+        }
+    }
+
+    #[test]
+    fn extra_clone_flags() {
+        // In a new cgroup namespace, the cgroups of the container are roots.
+        let rootfs = Rootfs::new();
+        let cgroups = stdout(
+            rootfs
+                .container(&["/bin/cat", "/proc/self/cgroup"])
+                .clone_flags(libc::CLONE_NEWCGROUP as u64),
+        );
+        assert!(
+            cgroups.lines().all(|line| line.ends_with(":/")),
+            "{cgroups}"
+        );
+    }
+
+    #[test]
+    fn readonly_root_from_an_oci_bundle() {
+        let rootfs = Rootfs::new();
+        let bundle = rootfs.path.with_extension("bundle");
+        fs::create_dir(&bundle).unwrap();
+        let config = serde_json::json!({
+            "root": { "path": rootfs.path, "readonly": true },
+            "process": {
+                "args": [
+                    "/bin/sh",
+                    "-c",
+                    "echo \"$A\" && ! touch /file 2>&- \
+                     && cat /proc/self/mountinfo",
+                ],
+                "env": ["A=1"],
+            },
+        });
+        fs::write(bundle.join("config.json"), config.to_string()).unwrap();
+
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        let container = Container::from_oci_bundle(&bundle);
+        fs::remove_dir_all(&bundle).unwrap();
+        let output = stdout(
+            container
+                .unwrap()
+                .uid_map(0, uid, 1)
+                .gid_map(0, gid, 1)
+                .extra_mounts(rootfs.mounts.clone()),
+        );
+        let (env, mountinfo) = output.split_once('\n').unwrap();
+        assert_eq!(env, "1");
+        let (_, options) = mount(mountinfo, "/");
+        assert!(options.contains(&"ro"), "{options:?}");
+        assert!(!rootfs.path.join("file").exists());
+    }
+
+    #[test]
+    fn unshare_fds_before_close() {
+        let rootfs = Rootfs::new();
+        let status = rootfs
+            .container(&["/bin/true"])
+            .unshare_fds_before_close()
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn wait_with_output() {
+        let rootfs = Rootfs::new();
+        let output = rootfs
+            .container(&["/bin/sh", "-c", "echo out; echo err >&2; exit 2"])
+            .capture_output()
+            .spawn()
+            .unwrap()
+            .wait_with_output()
+            .unwrap();
+
+        assert_eq!(output.status.code, Some(2));
+        assert_eq!(output.stdout, b"out\n");
+        assert_eq!(output.stderr, b"err\n");
+    }
+
+    #[test]
+    fn wait_with_output_larger_than_the_pipes() {
+        let rootfs = Rootfs::new();
+        let output = rootfs
+            .container(&["/bin/sh", "-c", "seq 100000 >&2; seq 100000"])
+            .capture_output()
+            .spawn()
+            .unwrap()
+            .wait_with_output()
+            .unwrap();
+
+        assert!(output.status.success());
+        assert!(output.stdout.len() > 1 << 16);
+        assert!(output.stdout.ends_with(b"\n100000\n"));
+        assert_eq!(output.stdout, output.stderr);
+    }
+
+    #[test]
+    fn output_callbacks() {
+        use std::sync::{Arc, Mutex};
+
+        let rootfs = Rootfs::new();
+        let mut handle = rootfs
+            .container(&["/bin/sh", "-c", "seq 100000; echo err >&2"])
+            .capture_output()
+            .spawn()
+            .unwrap();
+        let lines = Arc::new(Mutex::new(0));
+        let stdout = handle
+            .on_stdout({
+                let lines = lines.clone();
+                move |chunk| {
+                    *lines.lock().unwrap() +=
+                        chunk.iter().filter(|&&b| b == b'\n').count()
+                }
+            })
+            .unwrap();
+        let mut stderr = vec![];
+        let (tx, rx) = std::sync::mpsc::channel();
+        let stderr_thread = handle
+            .on_stderr(move |chunk| tx.send(chunk.to_vec()).unwrap())
+            .unwrap();
+
+        assert!(handle.wait().unwrap().success());
+        stdout.join().unwrap().unwrap();
+        stderr_thread.join().unwrap().unwrap();
+        for chunk in rx {
+            stderr.extend(chunk);
+        }
+        assert_eq!(*lines.lock().unwrap(), 100000);
+        assert_eq!(stderr, b"err\n");
+        assert!(handle.on_stdout(|_| ()).is_none());
+    }
+
+    #[test]
+    fn hostname() {
+        let rootfs = Rootfs::new();
+        let container = rootfs
+            .container(&["/bin/cat", "/proc/sys/kernel/hostname"])
+            .hostname("box");
+        assert_eq!(stdout(container), "box\n");
+    }
+
+    #[test]
+    fn hostname_random() {
+        let rootfs = Rootfs::new();
+        let output = rootfs
+            .container(&["/bin/cat", "/proc/sys/kernel/hostname"])
+            .hostname_random()
+            .capture_output()
+            .spawn()
+            .unwrap()
+            .wait_with_output()
+            .unwrap();
+
+        let hostname = String::from_utf8(output.stdout).unwrap();
+        let hostname = hostname.trim_end();
+        assert_eq!(hostname.len(), 12);
+        assert!(hostname.bytes().all(|b| b.is_ascii_hexdigit()));
+    }
+
+    #[test]
+    fn hostname_and_domain() {
+        let rootfs = Rootfs::new();
+        fs::create_dir(rootfs.path.join("etc")).unwrap();
+        let output = rootfs
+            .container(&[
+                "/bin/cat",
+                "/proc/sys/kernel/hostname",
+                "/proc/sys/kernel/domainname",
+                "/etc/hostname",
+            ])
+            .with_hostname_and_domain("box", "example.com")
+            .capture_output()
+            .spawn()
+            .unwrap()
+            .wait_with_output()
+            .unwrap();
+        assert_eq!(output.stdout, b"box\nexample.com\nbox\n");
+    }
+
+    #[test]
+    fn hostname_matches_proc() {
+        let rootfs = Rootfs::new();
+        for mode in [crate::ProcMode::New, crate::ProcMode::HostBind] {
+            let container = rootfs
+                .container(&[
+                    "/bin/sh",
+                    "-c",
+                    "uname -n && cat /proc/sys/kernel/hostname",
+                ])
+                .hostname("box")
+                .proc_mode(mode);
+            assert_eq!(stdout(container), "box\nbox\n", "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn dev_shm() {
+        // The rootfs has no /dev, the child creates it.
+        let rootfs = Rootfs::new();
+        let output = stdout(
+            rootfs
+                .container(&[
+                    "/bin/sh",
+                    "-c",
+                    "echo x > /dev/shm/seg && stat -c %a /dev/shm \
+                     && cat /proc/self/mountinfo",
+                ])
+                .dev_shm(1024 * 1024),
+        );
+        let (mode, mountinfo) = output.split_once('\n').unwrap();
+        assert_eq!(mode, "1777");
+        let (fs_type, options) = mount(mountinfo, "/dev/shm");
+        assert_eq!(fs_type, "tmpfs");
+        assert!(options.contains(&"size=1024k"), "{options:?}");
+    }
+
+    #[test]
+    fn run_tmpfs() {
+        let rootfs = Rootfs::new();
+        let output = stdout(
+            rootfs
+                .container(&[
+                    "/bin/sh",
+                    "-c",
+                    "echo x > /run/user/0/sock && echo \"$XDG_RUNTIME_DIR\" \
+                     && stat -c '%a %u' \"$XDG_RUNTIME_DIR\" \
+                     && cat /proc/self/mountinfo",
+                ])
+                .run_tmpfs(Some(0)),
+        );
+        let mut lines = output.splitn(3, '\n');
+        assert_eq!(lines.next(), Some("/run/user/0"));
+        assert_eq!(lines.next(), Some("700 0"));
+        let (fs_type, options) = mount(lines.next().unwrap(), "/run");
+        assert_eq!(fs_type, "tmpfs");
+        assert!(options.contains(&"mode=755"), "{options:?}");
+    }
+
+    #[test]
+    fn proc_modes() {
+        let rootfs = Rootfs::new();
+        let host_pid = std::process::id().to_string();
+        for (mode, host_visible) in [
+            (crate::ProcMode::New, false),
+            (crate::ProcMode::HostBind, true),
+        ] {
+            let listing = stdout(
+                rootfs
+                    .container(&["/bin/ls", "-A", "/proc"])
+                    .proc_mode(mode),
+            );
+            assert!(listing.contains("self"), "{mode:?}: {listing}");
+            assert_eq!(
+                listing.lines().any(|entry| entry == host_pid),
+                host_visible,
+                "{mode:?}"
+            );
+        }
+        let listing = stdout(
+            rootfs
+                .container(&["/bin/ls", "-A", "/proc"])
+                .proc_mode(crate::ProcMode::None),
+        );
+        assert_eq!(listing, "");
+    }
+
+    #[test]
+    fn missing_sys_mountpoint() {
+        let rootfs = Rootfs::new();
+        let err = rootfs
+            .container(&["/bin/true"])
+            .sys_target("/missing")
+            .spawn();
+        assert!(matches!(
+            err,
+            Err(crate::CuriumError::SetupFailed {
+                step: crate::SetupStep::SysMountpoint,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn failed_mount_reports_its_step() {
+        let rootfs = Rootfs::new();
+        let err = rootfs
+            .container(&["/bin/true"])
+            .extra_mounts(vec![crate::MountSpec::Bind {
+                src: rootfs.path.join("missing"),
+                dst: "/tmp".into(),
+                ro: false,
+            }])
+            .spawn();
+        assert!(matches!(
+            err,
+            Err(crate::CuriumError::SetupFailed {
+                step: crate::SetupStep::Mount,
+                err,
+            }) if err.raw_os_error() == Some(libc::ENOENT)
+        ));
+    }
+
+    #[test]
+    fn dry_run_lists_the_mounts_of_spawn() {
+        let rootfs = Rootfs::new();
+        fs::create_dir(rootfs.path.join("mnt")).unwrap();
+        let container = rootfs
+            .container(&["/bin/cat", "/proc/self/mountinfo"])
+            .dev_shm(1 << 20)
+            .run_tmpfs(Some(0))
+            .extra_mounts(vec![crate::MountSpec::Bind {
+                src: "/usr".into(),
+                dst: "/mnt".into(),
+                ro: true,
+            }]);
+        let plan = container.dry_run().unwrap();
+        let root = rootfs.path.to_str().unwrap();
+        // The mounts under the new root, where they end up once it's entered.
+        let listed: Vec<_> = plan
+            .iter()
+            .filter_map(|operation| match operation {
+                crate::plan::Operation::Mount {
+                    target,
+                    fs_type,
+                    options,
+                    ..
+                } => {
+                    let target = target.strip_prefix(root)?;
+                    let target: &str = match target {
+                        "" => "/",
+                        target => target,
+                    };
+                    Some((target, fs_type, options))
+                }
+                _ => None,
+            })
+            .collect();
+        let mountinfo = stdout(container);
+
+        for (target, fs_type, options) in &listed {
+            let (mounted_type, mounted_options) = mount(&mountinfo, target);
+            if let Some(fs_type) = fs_type {
+                assert_eq!(mounted_type, fs_type, "{target}");
+            }
+            if options.iter().any(|option| option == "ro") {
+                assert!(mounted_options.contains(&"ro"), "{target}");
+            }
+        }
+        for line in mountinfo.lines() {
+            let point = line.split(' ').nth(4).unwrap();
+            assert!(
+                listed.iter().any(|(target, ..)| *target == point),
+                "{point} isn't listed: {listed:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn proc_target() {
+        // The command is the init of the pid namespace.
+        let rootfs = Rootfs::new();
+        let container = rootfs
+            .container(&["/bin/readlink", "/sys/self"])
+            .proc_target("/sys")
+            .sys_target("/proc");
+        assert_eq!(stdout(container), "1\n");
+    }
+
+    #[test]
+    fn ephemeral_writable_root() {
+        let rootfs = Rootfs::new();
+        let container = rootfs
+            .container(&["/bin/sh", "-c", "echo hi > /scratch && cat /scratch"])
+            .ephemeral_writable_root();
+        assert_eq!(stdout(container), "hi\n");
+        assert!(!rootfs.path.join("scratch").exists());
+    }
+
+    #[test]
+    fn root_atime() {
+        let rootfs = Rootfs::new();
+        let mountinfo = stdout(
+            rootfs
+                .container(&["/bin/cat", "/proc/self/mountinfo"])
+                .ephemeral_writable_root()
+                .root_atime(crate::AtimeMode::Noatime),
+        );
+        let (_, options) = mount(&mountinfo, "/");
+        assert!(options.contains(&"noatime"), "{options:?}");
+    }
+
+    #[test]
+    fn stop_before_exec() {
+        let rootfs = Rootfs::new();
+        let mut handle = rootfs
+            .container(&["/bin/true"])
+            .stop_before_exec()
+            .spawn()
+            .unwrap();
+
+        let stat =
+            fs::read_to_string(format!("/proc/{}/stat", handle.pid())).unwrap();
+        assert!(stat.contains(") t "), "{stat}");
+
+        // Detaching with no signal drops the pending SIGSTOP.
+        let pid = handle.pid() as libc::pid_t;
+        assert_eq!(unsafe { libc::ptrace(libc::PTRACE_DETACH, pid, 0, 0) }, 0);
+        assert!(handle.wait().unwrap().success());
+    }
+
+    #[test]
+    fn wait_with_usage() {
+        let rootfs = Rootfs::new();
+        let (status, usage) = rootfs
+            .container(&[
+                "/bin/sh",
+                "-c",
+                "i=0; while [ $i -lt 50000 ]; do i=$((i + 1)); done",
+            ])
+            .spawn()
+            .unwrap()
+            .wait_with_usage()
+            .unwrap();
+        assert!(status.success());
+        assert!(!(usage.user_time + usage.sys_time).is_zero());
+        assert!(usage.max_rss > 0);
+    }
+
+    #[test]
+    fn net_modes() {
+        let rootfs = Rootfs::new();
+        let output = stdout(rootfs.container(&[
+            "/bin/cat",
+            "/proc/net/dev",
+            "/sys/class/net/lo/flags",
+        ]));
+        // Only the loopback, with IFF_UP.
+        assert_eq!(output.lines().count(), 4, "{output}");
+        assert!(output.ends_with("0x9\n"), "{output}");
+
+        let devices = stdout(
+            rootfs
+                .container(&["/bin/cat", "/proc/net/dev"])
+                .net_mode(crate::NetMode::Host),
+        );
+        assert!(devices.lines().count() > 3, "{devices}");
+        let mountinfo = stdout(
+            rootfs
+                .container(&["/bin/cat", "/proc/self/mountinfo"])
+                .net_mode(crate::NetMode::Host),
+        );
+        for line in mountinfo.lines() {
+            let point = line.split(' ').nth(4).unwrap();
+            if point == "/sys" || point.starts_with("/sys/") {
+                let (_, options) = mount(&mountinfo, point);
+                assert!(options.contains(&"ro"), "{line}");
+            }
+        }
+    }
+
+    #[test]
+    fn redirect_to_files() {
+        let rootfs = Rootfs::new();
+        let out = rootfs.path.with_extension("out");
+        let err = rootfs.path.with_extension("err");
+        let status = rootfs
+            .container(&["/bin/sh", "-c", "cat; echo out; echo err >&2"])
+            .stdin_null()
+            .stdout_to_file(&out)
+            .stderr_to_file(&err)
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert!(status.success());
+        assert_eq!(fs::read_to_string(&out).unwrap(), "out\n");
+        assert_eq!(fs::read_to_string(&err).unwrap(), "err\n");
+        fs::remove_file(out).unwrap();
+        fs::remove_file(err).unwrap();
+    }
+
+    #[test]
+    fn pidfile() {
+        let rootfs = Rootfs::new();
+        let pidfile = rootfs.path.with_extension("pid");
+        let mut handle = rootfs
+            .container(&["/bin/sleep", "0.2"])
+            .pidfile(&pidfile)
+            .spawn()
+            .unwrap();
+
+        let pid = fs::read_to_string(&pidfile).unwrap();
+        assert_eq!(pid, format!("{}\n", handle.pid()));
+        handle.wait().unwrap();
+        assert!(!pidfile.exists());
+    }
+
+    #[test]
+    fn old_root_dir() {
+        let rootfs = Rootfs::new();
+        let container = rootfs
+            .container(&["/bin/sh", "-c", "cat /proc/1/comm && ls -A /"])
+            .old_root_dir("host");
+        let output = stdout(container);
+        let mut lines = output.lines();
+        assert_eq!(lines.next(), Some("sh"));
+        assert!(lines.all(|entry| entry != "host"), "{output}");
+        assert!(!rootfs.path.join("host").exists());
+
+        fs::create_dir(rootfs.path.join("old_root")).unwrap();
+        let err = rootfs.container(&["/bin/true"]).spawn();
+        assert!(matches!(
+            err,
+            Err(crate::CuriumError::SetupFailed {
+                step: crate::SetupStep::OldRootDir,
+                err,
+            }) if err.raw_os_error() == Some(libc::EEXIST)
+        ));
+    }
+
+    #[test]
+    fn bind_executable() {
+        let rootfs = Rootfs::new();
+        // Outside the host directories bound into the rootfs. The loader is
+        // left out, the rootfs has it behind the /lib64 symlink.
+        let dir = rootfs.path.with_extension("bin");
+        fs::create_dir(&dir).unwrap();
+        let exe = dir.join("true");
+        fs::copy("/bin/true", &exe).unwrap();
+
+        let status = rootfs
+            .container(&[exe.to_str().unwrap()])
+            .bind_executable(false)
+            .spawn()
+            .unwrap()
+            .wait();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(status.unwrap().success());
+    }
+
+    #[test]
+    fn auto_bind_dependencies() {
+        // An empty rootfs, with only the mount points of /proc and /sys.
+        let root = std::env::temp_dir()
+            .join(format!("curium-test-{}-empty", std::process::id()));
+        for dir in ["proc", "sys"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        let container = || {
+            Container::new(root.to_str().unwrap().into(), "true")
+                .uid_map(0, uid, 1)
+                .gid_map(0, gid, 1)
+                .ephemeral_writable_root()
+        };
+
+        let status = |mut container: Container| container.run().unwrap();
+        let without = status(container().bind_executable(true));
+        let with = status(container().auto_bind_dependencies());
+        fs::remove_dir_all(&root).unwrap();
+        // The loader fails without the libraries, unless true is static.
+        assert!(with.success());
+        if crate::elf::interpreter(std::path::Path::new("/bin/true"))
+            .unwrap()
+            .is_some()
+        {
+            assert!(!without.success());
+        }
+    }
+
+    #[test]
+    fn kill_on_drop() {
+        let rootfs = Rootfs::new();
+        let handle = rootfs
+            .container(&["/bin/sleep", "10"])
+            .spawn()
+            .unwrap()
+            .kill_on_drop(true);
+        let pid = handle.pid();
+
+        drop(handle);
+        // Reaped, not only killed.
+        assert!(!std::path::Path::new(&format!("/proc/{pid}")).exists());
+    }
+
+    #[test]
+    fn oci_state() {
+        use crate::oci::Status;
+
+        let rootfs = Rootfs::new();
+        let bundle = rootfs.path.to_str().unwrap();
+        let mut handle = rootfs
+            .container(&["/bin/sleep", "0.2"])
+            .stop_before_exec()
+            .spawn()
+            .unwrap();
+        let state = handle.oci_state("test", bundle).unwrap();
+        assert_eq!(state.status, Status::Created);
+        assert_eq!(state.pid, Some(handle.pid()));
+
+        let pid = handle.pid() as libc::pid_t;
+        assert_eq!(unsafe { libc::ptrace(libc::PTRACE_DETACH, pid, 0, 0) }, 0);
+        // The exec is done once /proc shows the new command.
+        while fs::read_to_string(format!("/proc/{pid}/comm")).unwrap()
+            != "sleep\n"
+        {
+            std::thread::yield_now();
+        }
+        let state = handle.oci_state("test", bundle).unwrap();
+        assert_eq!(state.status, Status::Running);
+
+        handle.wait().unwrap();
+        let state = handle.oci_state("test", bundle).unwrap();
+        assert_eq!(state.status, Status::Stopped);
+        assert_eq!(state.pid, None);
+    }
+
+    #[test]
+    fn command_not_found() {
+        let rootfs = Rootfs::new();
+        let err = rootfs.container(&["/bin/does-not-exist"]).spawn();
+        assert!(matches!(err, Err(crate::CuriumError::CommandNotFound)));
+    }
+
+    #[test]
+    fn bind_mount_recursive() {
+        let rootfs = Rootfs::new();
+        fs::create_dir(rootfs.path.join("mnt")).unwrap();
+        // The host's /sys has submounts, like /sys/fs/cgroup.
+        let submount = fs::read_to_string("/proc/self/mountinfo")
+            .unwrap()
+            .lines()
+            .filter_map(|line| line.split(' ').nth(4))
+            .find(|point| point.starts_with("/sys/"))
+            .map(|point| point.replacen("/sys", "/mnt", 1));
+        let Some(submount) = submount else {
+            return;
+        };
+
+        for ro in [false, true] {
+            let mountinfo = stdout(
+                rootfs
+                    .container(&["/bin/cat", "/proc/self/mountinfo"])
+                    .bind_mount_recursive("/sys", "/mnt", ro),
+            );
+            let mounts: Vec<Vec<_>> = mountinfo
+                .lines()
+                .map(|line| line.split(' ').collect())
+                .filter(|fields: &Vec<_>| {
+                    fields[4] == "/mnt" || fields[4] == submount
+                })
+                .collect();
+
+            assert_eq!(mounts.len(), 2, "{mountinfo}");
+            for fields in mounts {
+                // Propagation shows up in the optional fields until "-".
+                let optional = fields[6..].iter().take_while(|f| **f != "-");
+                assert!(
+                    optional.clone().all(|f| !f.starts_with("shared:")),
+                    "{fields:?}"
+                );
+                assert_eq!(fields[5].split(',').any(|o| o == "ro"), ro);
+            }
+        }
+    }
+
+    #[test]
+    fn run_outcome() {
+        use crate::{CuriumError, Outcome};
+
+        let rootfs = Rootfs::new();
+        let outcome = |script| {
+            rootfs
+                .container(&["/bin/sh", "-c", script])
+                .run_outcome()
+                .unwrap()
+        };
+        assert!(matches!(outcome("exit 1"), Outcome::Exited(1)));
+        // The init of a pid namespace can't be killed from inside, and
+        // killing it before the exec is a startup failure.
+        let killed = rootfs
+            .container(&["/bin/sh", "-c", "sleep 10"])
+            .after_start(|child| {
+                let pid = child.as_pid();
+                std::thread::spawn(move || {
+                    let cmdline = format!("/proc/{pid}/cmdline");
+                    while !fs::read(&cmdline).unwrap().starts_with(b"/bin/sh") {
+                        std::thread::sleep(std::time::Duration::from_millis(5));
+                    }
+                    unsafe { libc::kill(pid, libc::SIGKILL) };
+                });
+                Ok(())
+            })
+            .run_outcome()
+            .unwrap();
+        assert!(matches!(killed, Outcome::Signaled(libc::SIGKILL)));
+        // A missing command is told from a command exiting with 127.
+        assert!(matches!(outcome("exit 127"), Outcome::Exited(127)));
+        let outcome = rootfs
+            .container(&["/bin/does-not-exist"])
+            .run_outcome()
+            .unwrap();
+        assert!(matches!(
+            outcome,
+            Outcome::StartupFailed(CuriumError::CommandNotFound)
+        ));
+    }
+
+    #[test]
+    fn tty() {
+        use std::io::Read;
+
+        let rootfs = Rootfs::new();
+        let mut handle = rootfs
+            .container(&[
+                "/bin/sh",
+                "-c",
+                // The controlling terminal is the 7th field of stat.
+                "for fd in 0 1 2; do [ -t $fd ] && echo $fd; done \
+                 && cut -d' ' -f7 /proc/$$/stat",
+            ])
+            .tty(true)
+            .spawn()
+            .unwrap();
+        let mut master = fs::File::from(handle.take_pty().unwrap());
+        assert!(handle.wait().unwrap().success());
+
+        // The read fails with EIO once the output is drained.
+        let mut output: Vec<u8> = vec![];
+        let mut buf = [0u8; 256];
+        while let Ok(len @ 1..) = master.read(&mut buf) {
+            output.extend(&buf[..len]);
+        }
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<_> = output.split_terminator("\r\n").collect();
+        assert_eq!(lines[..3], ["0", "1", "2"], "{output:?}");
+        assert_ne!(lines[3], "0");
+    }
+
+    #[test]
+    fn net_sysctls() {
+        use crate::NetSysctl;
+
+        let rootfs = Rootfs::new();
+        let container = rootfs
+            .container(&[
+                "/bin/cat",
+                "/proc/sys/net/ipv4/ip_unprivileged_port_start",
+                "/proc/sys/net/ipv4/ping_group_range",
+            ])
+            .net_sysctls([
+                NetSysctl::UnprivilegedPortStart(80),
+                // Only gid 0 is mapped, the kernel rejects unmapped gids.
+                NetSysctl::PingGroupRange(0, 0),
+            ]);
+        assert_eq!(stdout(container), "80\n0\t0\n");
+    }
+
+    #[test]
+    fn proc_options() {
+        // hidepid=2 is shown by name.
+        let rootfs = Rootfs::new();
+        let output = stdout(
+            rootfs
+                .container(&[
+                    "/bin/sh",
+                    "-c",
+                    "ls -A /proc && cat /proc/self/mountinfo",
+                ])
+                .proc_options(2, true)
+                .harden_proc(),
+        );
+        assert!(!output.lines().any(|entry| entry == "sys"), "{output}");
+        let (_, options) = mount(&output, "/proc");
+        assert!(options.contains(&"hidepid=invisible"), "{options:?}");
+        assert!(options.contains(&"subset=pid"), "{options:?}");
+    }
+
+    #[test]
+    fn parent_callbacks() {
+        use std::sync::mpsc;
+
+        let rootfs = Rootfs::new();
+        let (tx, rx) = mpsc::channel();
+        let mut handle = rootfs
+            .container(&["/bin/true"])
+            .after_clone({
+                let tx = tx.clone();
+                move |child| {
+                    let path = format!("/proc/{}/uid_map", child.pid);
+                    tx.send(("clone", fs::read_to_string(path)?)).unwrap();
+                    Ok(())
+                }
+            })
+            .after_start(move |child| {
+                tx.send(("start", child.pid.to_string())).unwrap();
+                Ok(())
+            })
+            .spawn()
+            .unwrap();
+        assert!(handle.wait().unwrap().success());
+
+        let calls: Vec<_> = rx.iter().collect();
+        assert_eq!(calls.len(), 2);
+        // The ids are mapped before the callback.
+        assert_eq!(calls[0].0, "clone");
+        assert!(calls[0].1.trim().starts_with('0'), "{}", calls[0].1);
+        assert_eq!(calls[1], ("start", handle.pid().to_string()));
+
+        let err = rootfs
+            .container(&["/bin/true"])
+            .after_clone(|_| Err(std::io::Error::other("no cgroup")))
+            .spawn();
+        assert!(
+            matches!(&err, Err(crate::CuriumError::Io(err)) if err.to_string() == "no cgroup")
+        );
+    }
+
+    #[test]
+    fn startup_timeout() {
+        let rootfs = Rootfs::new();
+        let err = rootfs
+            .container(&["/bin/true"])
+            .startup_timeout(std::time::Duration::from_millis(50))
+            .after_clone(|_| {
+                std::thread::sleep(std::time::Duration::from_millis(200));
+                Ok(())
+            })
+            .spawn()
+            .err();
+        assert!(
+            matches!(err, Some(crate::CuriumError::StartupTimedOut)),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn seccomp_notify() {
+        use crate::seccomp::{self, Response};
+
+        let rootfs = Rootfs::new();
+        for (response, success) in [
+            (Response::Error(libc::EPERM), false),
+            (Response::Continue, true),
+        ] {
+            let mut handle = rootfs
+                .container(&["/bin/sh", "-c", "[ -n \"$(uname -n)\" ]"])
+                .seccomp_notify([libc::SYS_uname])
+                .spawn()
+                .unwrap();
+            let listener = handle.take_seccomp_listener().unwrap();
+
+            let notif = seccomp::recv(&listener).unwrap();
+            assert_eq!(notif.data.nr as i64, libc::SYS_uname);
+            assert_ne!(notif.pid as i64, handle.pid());
+            seccomp::respond(&listener, notif.id, response).unwrap();
+            assert_eq!(handle.wait().unwrap().success(), success);
+        }
+
+        // A failure before the filter is loaded is still reported.
+        let err = rootfs
+            .container(&["/bin/true"])
+            .sys_target("/missing")
+            .seccomp_notify([libc::SYS_uname])
+            .spawn();
+        assert!(matches!(
+            err,
+            Err(crate::CuriumError::SetupFailed {
+                step: crate::SetupStep::SysMountpoint,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn seccomp_default() {
+        // Forking is allowed, creating a namespace isn't.
+        let rootfs = Rootfs::new();
+        let output = stdout(
+            rootfs
+                .container(&[
+                    "/bin/sh",
+                    "-c",
+                    "echo hi | cat; unshare -U true 2>&-; echo $?; \
+                     unshare -p -f true 2>&-; echo $?",
+                ])
+                .seccomp_default(),
+        );
+        assert_eq!(output, "hi\n1\n1\n");
+    }
+
+    #[test]
+    fn cgroup_namespace_root() {
+        let rootfs = Rootfs::new();
+        let run = |container: Container, script| {
+            stdout(container.arg("-c").arg(script).cgroup_namespace_root())
+        };
+
+        let cgroups =
+            run(rootfs.container(&["/bin/sh"]), "cat /proc/self/cgroup");
+        assert!(
+            cgroups.lines().all(|line| line.ends_with(":/")),
+            "{cgroups}"
+        );
+
+        // Siblings can only be made in a writable v2 hierarchy.
+        let Some(hierarchy) = ["/sys/fs/cgroup", "/sys/fs/cgroup/unified"]
+            .into_iter()
+            .find(|dir| {
+                fs::exists(format!("{dir}/cgroup.controllers")).unwrap()
+            })
+        else {
+            return;
+        };
+        let own = fs::read_to_string("/proc/self/cgroup").unwrap();
+        let own = own
+            .lines()
+            .find_map(|line| line.strip_prefix("0::"))
+            .unwrap();
+        let dir = |name| {
+            PathBuf::from(format!("{hierarchy}{own}"))
+                .join(format!("curium-test-{}-{name}", std::process::id()))
+        };
+        let (cgroup, sibling) = (dir("container"), dir("sibling"));
+        if fs::create_dir(&cgroup).is_err() {
+            eprintln!("skipped the siblings, {hierarchy} isn't writable");
+            return;
+        }
+        fs::create_dir(&sibling).unwrap();
+
+        let procs = cgroup.join("cgroup.procs");
+        let listing = run(
+            rootfs.container(&["/bin/sh"]).after_clone(move |child| {
+                fs::write(&procs, child.pid.to_string())
+            }),
+            "ls /sys/fs/cgroup",
+        );
+        fs::remove_dir(&cgroup).unwrap();
+        fs::remove_dir(&sibling).unwrap();
+        assert!(listing.contains("cgroup.procs"), "{listing}");
+        assert!(!listing.contains("sibling"), "{listing}");
+    }
+
+    #[test]
+    fn rlimit_nofile() {
+        let rootfs = Rootfs::new();
+        fs::write(rootfs.path.join("empty"), "").unwrap();
+        // paste opens all its files at once.
+        let limits = |limit: u64| {
+            stdout(
+                rootfs
+                    .container(&[
+                        "/bin/sh",
+                        "-c",
+                        "ulimit -n && paste $(yes /empty 2>&- | head -n 3000) \
+                         2>&-; echo $?",
+                    ])
+                    .with_rlimit_nofile(limit, limit),
+            )
+        };
+        assert_eq!(limits(1024), "1024\n1\n");
+        assert_eq!(limits(4096), "4096\n0\n");
+    }
+
+    #[test]
+    fn exit_is_the_last_event() {
+        use crate::{Event, ExitStatus};
+
+        let rootfs = Rootfs::new();
+        let mut handle = rootfs
+            .container(&["/bin/sh", "-c", "exit 3"])
+            .spawn()
+            .unwrap();
+        let events: Vec<_> = handle.events().map(Result::unwrap).collect();
+        let status = ExitStatus {
+            code: Some(3),
+            signal: None,
+        };
+        assert_eq!(events, [Event::Exited(status)]);
+        // A reaped container still yields its exit.
+        let events: Vec<_> = handle.events().map(Result::unwrap).collect();
+        assert_eq!(events, [Event::Exited(status)]);
+    }
+
+    #[test]
+    fn batch_mode_schedules_idle() {
+        // The 41st field of stat(5) is the policy, SCHED_IDLE is 5.
+        let rootfs = Rootfs::new();
+        let policy = |container: Container| {
+            let stat = stdout(container);
+            stat.rsplit_once(')')
+                .unwrap()
+                .1
+                .split(' ')
+                .nth(39)
+                .unwrap()
+                .to_owned()
+        };
+        let stat = || rootfs.container(&["/bin/cat", "/proc/self/stat"]);
+        assert_eq!(policy(stat()), "0");
+        assert_eq!(policy(stat().batch_mode()), "5");
+    }
+
+    #[test]
+    fn hermetic() {
+        let rootfs = Rootfs::new();
+        let output = stdout(
+            rootfs
+                .container(&[
+                    "/bin/sh",
+                    "-c",
+                    "echo \"$TZ $SOURCE_DATE_EPOCH $(umask) ${FOO-unset}\" \
+                     && cat /proc/sys/kernel/hostname /proc/self/personality \
+                     /proc/uptime",
+                ])
+                .set_var("FOO", "1")
+                .hermetic()
+                .set_var("PATH", "/bin:/usr/bin"),
+        );
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines[..3], ["UTC 0 0022 unset", "localhost", "00040000"]);
+        let uptime: f64 = lines[3].split(' ').next().unwrap().parse().unwrap();
+        assert!(uptime < 60.0, "{uptime}");
+    }
+
+    #[test]
+    fn killed_container_reports_the_signal() {
+        use crate::ExitStatus;
+
+        let rootfs = Rootfs::new();
+        let mut handle = rootfs
+            .container(&["/bin/sh", "-c", "sleep 10"])
+            .spawn()
+            .unwrap();
+        handle.signal(libc::SIGKILL).unwrap();
+        let status = handle.wait().unwrap();
+        assert_eq!(
+            status,
+            ExitStatus {
+                code: None,
+                signal: Some(libc::SIGKILL),
+            }
+        );
+        assert!(!status.success());
+    }
+}
@@ -0,0 +1,89 @@
+//! The rootfs of the tests, also included by the benches, where `crate`
+//! is the bench with the `curium` items it needs imported.
+
+use std::{
+    fs,
+    os::unix::fs::symlink,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{Container, MountSpec};
+
+/// Host directories bound into the rootfs, or recreated as symlinks when the
+/// host has them as symlinks (e.g. `/bin -> usr/bin`).
+const HOST_DIRS: [&str; 5] = ["/usr", "/bin", "/sbin", "/lib", "/lib64"];
+
+/// A minimal rootfs built from the host's binaries and libraries in a
+/// temporary directory, removed on drop.
+pub(crate) struct Rootfs {
+    pub(crate) path: PathBuf,
+    pub(crate) mounts: Vec<MountSpec>,
+}
+
+impl Rootfs {
+    pub(crate) fn new() -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "curium-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir(&path).expect("should create the rootfs");
+        for dir in ["proc", "sys"] {
+            fs::create_dir(path.join(dir)).unwrap();
+        }
+
+        let mut mounts = vec![];
+        for dir in HOST_DIRS {
+            let Ok(meta) = fs::symlink_metadata(dir) else {
+                continue;
+            };
+            let target = path.join(&dir[1..]);
+            if meta.is_symlink() {
+                symlink(fs::read_link(dir).unwrap(), target).unwrap();
+            } else {
+                fs::create_dir(target).unwrap();
+                mounts.push(MountSpec::Bind {
+                    src: dir.into(),
+                    dst: dir.into(),
+                    ro: false,
+                });
+            }
+        }
+
+        Self { path, mounts }
+    }
+
+    /// A container running `cmd` in this rootfs, as root in its own user
+    /// namespace.
+    pub(crate) fn container(&self, cmd: &[&str]) -> Container {
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        self.container_without_ids(cmd)
+            .uid_map(0, uid, 1)
+            .gid_map(0, gid, 1)
+    }
+
+    /// A container running `cmd` in this rootfs without id maps, which needs
+    /// root.
+    pub(crate) fn container_without_ids(&self, cmd: &[&str]) -> Container {
+        let mut container = Container::new(
+            self.path.to_str().expect("temp dir should be utf-8").into(),
+            cmd[0],
+        )
+        .env("PATH=/usr/bin:/bin")
+        .extra_mounts(self.mounts.clone());
+
+        for arg in &cmd[1..] {
+            container = container.arg(arg);
+        }
+        container
+    }
+}
+
+impl Drop for Rootfs {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,15 @@
+//! Lifecycle events, emitted with `tracing` when the `tracing` feature is
+//! enabled.
+//!
+//! Only parent-side milestones are traced, the child runs in an
+//! async-signal-safe context and can't emit events.
+
+/// Emit a debug event, this expands to nothing without the `tracing` feature.
+macro_rules! event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)*);
+    };
+}
+
+pub(crate) use event;
@@ -1,19 +1,42 @@
 //! User and group mapping operations.
 
-use std::{fs::OpenOptions, io::Write};
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Read, Write},
+    process::Command,
+};
 
-use crate::FixedBufferWriter;
+use crate::{CuriumError, FixedBufferWriter};
 
-/// Map a range of user IDs inside a namespace.
+/// Map a range of user IDs inside the user namespace of the calling
+/// process, which must be new: its uid map can only be written once.
+///
+/// Fails with [`CuriumError::InvalidConfig`] if the map isn't empty, e.g.
+/// without `CLONE_NEWUSER` the caller is in a namespace whose map is
+/// already written.
 ///
 /// # Signal Safety
-/// This function is signal safe.
-fn map_uid_range(outside_uid: u32, inside_uid: u32, count: u32) {
+/// This function is signal safe, but building the error allocates.
+fn map_uid_range(
+    outside_uid: u32,
+    inside_uid: u32,
+    count: u32,
+) -> Result<(), CuriumError> {
     // check if the string to Path conversion is signal safe
     let mut uid_map_file = OpenOptions::new()
+        .read(true)
         .write(true)
-        .open("/proc/self/uid_map")
-        .expect("Should be able to open /proc/self/uid_map for writing");
+        .open("/proc/self/uid_map")?;
+
+    // The read leaves the offset at 0 when the map is empty, the only offset
+    // the kernel takes a write at.
+    if uid_map_file.read(&mut [0])? != 0 {
+        return Err(CuriumError::InvalidConfig(
+            "the uid map is already written, the caller isn't in a new user \
+             namespace (CLONE_NEWUSER)"
+                .into(),
+        ));
+    }
 
     // 10 bytes for each 32 bit integer, and 3 for the spaces.
     let mut uid_map_line = FixedBufferWriter::<33>::new();
@@ -28,16 +51,134 @@ fn map_uid_range(outside_uid: u32, inside_uid: u32, count: u32) {
     // user_namespaces(7) says that the uid_map file may be written to only
     // **once**, or else the write will return `EPERM`. So in theory, a
     // single write should completely write the buffer.
-    match uid_map_file.write(uid_map_line.buffer()) {
-        Err(_) => panic!("writing to /proc/self/uid_map failed"),
-        Ok(nbytes) if nbytes != uid_map_line.len() => {
-            panic!("writing to /proc/self/uid_map failed")
-        }
-        Ok(_) => (),
+    match uid_map_file.write(uid_map_line.buffer())? {
+        nbytes if nbytes != uid_map_line.len() => Err(io::Error::new(
+            io::ErrorKind::WriteZero,
+            "the uid map was partially written",
+        )
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+/// Map a single uid in the new user namespace of the calling process.
+///
+/// Fails with [`CuriumError::InvalidConfig`] if its uid map is already
+/// written, as it is outside a new user namespace.
+pub fn map_uid(outside_uid: u32, inside_uid: u32) -> Result<(), CuriumError> {
+    map_uid_range(outside_uid, inside_uid, 1)
+}
+
+/// A range of ids mapped into a user namespace, one line of a `uid_map` or
+/// `gid_map` file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdMap {
+    /// The first id inside the namespace.
+    pub inside: u32,
+    /// The first id outside the namespace.
+    pub outside: u32,
+    pub count: u32,
+}
+
+/// Return true if the calling process is in a user namespace other than the
+/// initial one, whose uid map is the identity of every id.
+pub fn in_user_namespace() -> Result<bool, io::Error> {
+    let uid_map = fs::read_to_string("/proc/self/uid_map")?;
+    let identity = ["0", "0", "4294967295"];
+    Ok(!uid_map.split_whitespace().eq(identity))
+}
+
+/// Write the uid and gid maps of the process `pid` from its parent.
+///
+/// An unprivileged parent can only map its own uid and gid, and
+/// `setgroups` is denied before writing the gid map, see
+/// user_namespaces(7). This is always the case for a parent that is itself
+/// in a user namespace without `CAP_SETUID` over it (e.g. a CI container).
+/// The other maps are then written with the setuid `newuidmap` and
+/// `newgidmap` helpers, which check `/etc/subuid` and `/etc/subgid`.
+///
+/// # Signal Safety
+/// This function is not signal safe.
+pub fn write_id_maps(
+    pid: i64,
+    uid_maps: &[IdMap],
+    gid_maps: &[IdMap],
+) -> Result<(), io::Error> {
+    if !uid_maps.is_empty() {
+        write_id_map(pid, "uid_map", "newuidmap", uid_maps)?;
+    }
+    if !gid_maps.is_empty() {
+        fs::write(format!("/proc/{pid}/setgroups"), "deny")?;
+        write_id_map(pid, "gid_map", "newgidmap", gid_maps)?;
+    }
+    Ok(())
+}
+
+fn write_id_map(
+    pid: i64,
+    file: &str,
+    helper: &str,
+    maps: &[IdMap],
+) -> Result<(), io::Error> {
+    let lines: String = maps
+        .iter()
+        .map(|m| format!("{} {} {}\n", m.inside, m.outside, m.count))
+        .collect();
+
+    // A failed write doesn't count as the single write allowed.
+    let err = match fs::write(format!("/proc/{pid}/{file}"), lines) {
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => err,
+        result => return result,
     };
+
+    let mut command = Command::new(helper);
+    command.arg(pid.to_string());
+    for map in maps {
+        command.args([
+            map.inside.to_string(),
+            map.outside.to_string(),
+            map.count.to_string(),
+        ]);
+    }
+
+    match command.status() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => {
+            Err(io::Error::other(format!("{helper} failed: {status}")))
+        }
+        // A PATH entry we can't search is reported as EACCES.
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied
+            ) =>
+        {
+            let nested = match in_user_namespace()? {
+                true => " in a nested user namespace",
+                false => "",
+            };
+            Err(io::Error::new(
+                err.kind(),
+                format!(
+                    "only the own id can be mapped{nested} without {helper}"
+                ),
+            ))
+        }
+        Err(e) => Err(e),
+    }
 }
 
-/// Map a single uid
-pub fn map_uid(outside_uid: u32, inside_uid: u32) {
-    map_uid_range(outside_uid, inside_uid, 1);
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn written_uid_map_is_rejected() {
+        // The map of the test process is the host's identity, or written by
+        // whatever created its user namespace.
+        assert!(matches!(
+            map_uid(1000, 0),
+            Err(CuriumError::InvalidConfig(_))
+        ));
+    }
 }